@@ -80,34 +80,34 @@ fn main() {
         continue;
       }
 
-      let frame = audio_decoder.decode(&packet).unwrap();
-
-      let (frames, _) = graph.process(&[frame], &[]).unwrap();
-
-      let frame = frames.first().unwrap();
-
-      unsafe {
-        let size = ((*frame.frame).channels * (*frame.frame).nb_samples) as usize;
-        let sample_format: SampleFormat = (*frame.frame).format.try_into().unwrap();
-
-        log::info!(
-          "Frame {} samples, {} channels, {:?}, {} bytes // {} bytes",
-          (*frame.frame).nb_samples,
-          (*frame.frame).channels,
-          sample_format,
-          (*frame.frame).linesize[0],
-          size,
-        );
-
-        let samples: Vec<i32> = Vec::from_raw_parts((*frame.frame).data[0] as _, size, size);
-
-        let float_samples: Vec<f32> = samples
-          .iter()
-          .map(|value| (*value as f32) / i32::MAX as f32)
-          .collect();
-
-        producer.push_slice(&float_samples);
-        std::mem::forget(samples);
+      for frame in audio_decoder.decode(&packet).unwrap() {
+        let (frames, _) = graph.process(&[frame], &[]).unwrap();
+
+        for frame in &frames {
+          unsafe {
+            let size = ((*frame.frame).ch_layout.nb_channels * (*frame.frame).nb_samples) as usize;
+            let sample_format: SampleFormat = (*frame.frame).format.try_into().unwrap();
+
+            log::info!(
+              "Frame {} samples, {} channels, {:?}, {} bytes // {} bytes",
+              (*frame.frame).nb_samples,
+              (*frame.frame).ch_layout.nb_channels,
+              sample_format,
+              (*frame.frame).linesize[0],
+              size,
+            );
+
+            let samples: Vec<i32> = Vec::from_raw_parts((*frame.frame).data[0] as _, size, size);
+
+            let float_samples: Vec<f32> = samples
+              .iter()
+              .map(|value| (*value as f32) / i32::MAX as f32)
+              .collect();
+
+            producer.push_slice(&float_samples);
+            std::mem::forget(samples);
+          }
+        }
       }
     }
   }
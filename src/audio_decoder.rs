@@ -1,12 +1,24 @@
-use crate::{format_context::FormatContext, frame::Frame, packet::Packet, tools};
+use crate::{
+  channel_layout::ChannelLayout,
+  error::Error,
+  format_context::{FormatContext, SeekMode, StreamDecoder},
+  frame::Frame,
+  packet::Packet,
+  tools::{self, rational::Rational},
+};
 use ffmpeg_sys_next::*;
-use std::ptr::null_mut;
+use std::{
+  cell::RefCell,
+  ptr::{null, null_mut},
+};
 
 #[derive(Debug)]
 pub struct AudioDecoder {
   pub identifier: String,
   pub stream_index: isize,
   pub codec_context: *mut AVCodecContext,
+  /// Frames decoded past a seek target, returned first by the next decode.
+  pending_frames: RefCell<Vec<Frame>>,
 }
 
 impl AudioDecoder {
@@ -14,7 +26,7 @@ impl AudioDecoder {
     identifier: String,
     format: &FormatContext,
     stream_index: isize,
-  ) -> Result<Self, String> {
+  ) -> Result<Self, Error> {
     unsafe {
       let codec = avcodec_find_decoder(format.get_codec_id(stream_index));
       let mut codec_context = avcodec_alloc_context3(codec);
@@ -28,6 +40,8 @@ impl AudioDecoder {
           avcodec_free_context(&mut codec_context);
         }
       );
+      (*codec_context).pkt_timebase =
+        (**(*format.format_context).streams.offset(stream_index)).time_base;
       check_result!(avcodec_open2(codec_context, codec, null_mut()), {
         avcodec_free_context(&mut codec_context);
       });
@@ -36,6 +50,7 @@ impl AudioDecoder {
         identifier,
         stream_index,
         codec_context,
+        pending_frames: RefCell::new(vec![]),
       })
     }
   }
@@ -45,11 +60,11 @@ impl AudioDecoder {
   }
 
   pub fn get_nb_channels(&self) -> i32 {
-    unsafe { (*self.codec_context).channels }
+    unsafe { (*self.codec_context).ch_layout.nb_channels }
   }
 
-  pub fn get_channel_layout(&self) -> u64 {
-    unsafe { (*self.codec_context).channel_layout }
+  pub fn get_channel_layout(&self) -> ChannelLayout {
+    unsafe { ChannelLayout::from_av_layout(&(*self.codec_context).ch_layout) }
   }
 
   pub fn get_sample_fmt_name(&self) -> String {
@@ -59,23 +74,109 @@ impl AudioDecoder {
     }
   }
 
-  pub fn decode(&self, packet: &Packet) -> Result<Frame, String> {
+  /// Time base of the packets, and of the timestamps of the decoded frames.
+  pub fn get_packet_time_base(&self) -> Option<Rational> {
+    unsafe {
+      let time_base = (*self.codec_context).pkt_timebase;
+      if time_base.num > 0 && time_base.den > 0 {
+        Some(time_base.into())
+      } else {
+        None
+      }
+    }
+  }
+
+  /// Decode a packet and return every frame the decoder outputs.
+  /// An empty list means the decoder needs more input.
+  pub fn decode(&self, packet: &Packet) -> Result<Vec<Frame>, Error> {
     if packet.get_stream_index() != self.stream_index {
-      return Err("bad stream".to_string());
+      return Err(Error::Configuration("bad stream".to_string()));
     }
-    unsafe {
+    let frames = unsafe {
       check_result!(avcodec_send_packet(self.codec_context, packet.packet));
+      Frame::receive_all(
+        self.codec_context,
+        &self.identifier,
+        self.stream_index as usize,
+      )?
+    };
+    let mut pending_frames = self.pending_frames.take();
+    pending_frames.extend(frames);
+    Ok(pending_frames)
+  }
 
-      let frame = av_frame_alloc();
-      check_result!(avcodec_receive_frame(self.codec_context, frame));
+  /// Signal the end of the stream, and return the frames still buffered by the decoder.
+  pub fn drain(&self) -> Result<Vec<Frame>, Error> {
+    let frames = unsafe {
+      check_result!(avcodec_send_packet(self.codec_context, null()));
+      Frame::receive_all(
+        self.codec_context,
+        &self.identifier,
+        self.stream_index as usize,
+      )?
+    };
+    let mut pending_frames = self.pending_frames.take();
+    pending_frames.extend(frames);
+    Ok(pending_frames)
+  }
 
-      Ok(Frame {
-        frame,
-        name: Some(self.identifier.clone()),
-        index: self.stream_index as usize,
-      })
+  /// Drop the frames buffered by the decoder, required after each seek.
+  pub fn flush(&self) {
+    self.pending_frames.borrow_mut().clear();
+    unsafe {
+      avcodec_flush_buffers(self.codec_context);
     }
   }
+
+  /// Seek to a timestamp in the stream time base and return the first decoded frame.
+  pub fn seek(
+    &self,
+    format: &mut FormatContext,
+    timestamp: i64,
+    mode: SeekMode,
+  ) -> Result<Frame, Error> {
+    format.seek_decoder(self, timestamp, mode)
+  }
+
+  pub fn seek_to_seconds(
+    &self,
+    format: &mut FormatContext,
+    seconds: f64,
+    mode: SeekMode,
+  ) -> Result<Frame, Error> {
+    format.seek_decoder_to_seconds(self, seconds, mode)
+  }
+
+  pub fn seek_to_frame(
+    &self,
+    format: &mut FormatContext,
+    frame_number: i64,
+    mode: SeekMode,
+  ) -> Result<Frame, Error> {
+    format.seek_decoder_to_frame(self, frame_number, mode)
+  }
+}
+
+impl StreamDecoder for AudioDecoder {
+  fn get_stream_index(&self) -> isize {
+    self.stream_index
+  }
+
+  fn decode(&self, packet: &Packet) -> Result<Vec<Frame>, Error> {
+    AudioDecoder::decode(self, packet)
+  }
+
+  fn drain(&self) -> Result<Vec<Frame>, Error> {
+    AudioDecoder::drain(self)
+  }
+
+  fn flush(&self) {
+    AudioDecoder::flush(self)
+  }
+
+  fn set_pending_frames(&self, frames: Vec<Frame>) {
+    *self.pending_frames.borrow_mut() = frames;
+  }
 }
 
 impl Drop for AudioDecoder {
@@ -1,21 +1,34 @@
 use crate::{
+  error::Error,
   frame::Frame,
   order::{
     output::{ChannelLayout, OutputStream, SampleFormat},
-    parameters::ParameterValue,
+    parameters::{self, ParameterValue},
   },
   packet::Packet,
   tools,
 };
 use ffmpeg_sys_next::*;
+use libc::c_void;
 use std::{collections::HashMap, ptr::null_mut};
 
+/// Parameters applied to the codec context, the others are passed as codec options.
+const AUDIO_PARAMETERS: &[&str] = &["sample_rate", "sample_fmt", "channel_layout"];
+
+/// Largest relative difference between the requested sample rate and the supported one
+/// used instead, e.g. 44000 for 44100 Hz, beyond which the request is rejected.
+const MAX_SAMPLE_RATE_DEVIATION: f64 = 0.1;
+
 #[derive(Debug)]
 pub struct AudioEncoder {
   pub identifier: String,
   pub stream_index: isize,
   pub codec_context: *mut AVCodecContext,
   pub codec: *const AVCodec,
+  /// Re-chunks the input samples for codecs with a fixed frame size, null otherwise.
+  fifo: *mut AVAudioFifo,
+  /// Timestamp of the next frame read from the FIFO, in the encoder time base.
+  pts: Option<i64>,
 }
 
 impl AudioEncoder {
@@ -23,86 +36,459 @@ impl AudioEncoder {
     identifier: String,
     stream_index: isize,
     output_stream: &OutputStream,
-  ) -> Result<Self, String> {
+  ) -> Result<Self, Error> {
+    let parameters = &output_stream.parameters;
+    let channel_layout = AudioEncoder::get_channel_layout_parameter(parameters)?;
+    let sample_fmt = match parameters.get("sample_fmt") {
+      Some(ParameterValue::String(data)) => {
+        Some(AVSampleFormat::from(data.parse::<SampleFormat>()?))
+      }
+      _ => None,
+    };
+    let sample_rate = match parameters.get("sample_rate") {
+      Some(ParameterValue::Rational(data)) if data.den > 0 && data.num / data.den > 0 => {
+        Some(data.num / data.den)
+      }
+      Some(ParameterValue::Rational(data)) => {
+        return Err(Error::Configuration(format!("Invalid sample rate: {data}")))
+      }
+      _ => None,
+    };
+
     unsafe {
       let codec = tools::get_codec(&output_stream.codec);
       if codec.is_null() {
-        return Err(format!("Unable to found codec {}", output_stream.codec));
+        return Err(Error::CodecNotFound {
+          code: AVERROR_ENCODER_NOT_FOUND,
+          message: format!("Unable to found codec {}", output_stream.codec),
+        });
       }
+      let codec_name = tools::to_string((*codec).name);
+      let channel_layout = negotiate_channel_layout(
+        &codec_name,
+        channel_layout,
+        &supported_channel_layouts(codec),
+      )?;
+      let sample_fmt =
+        negotiate_sample_format(&codec_name, sample_fmt, &supported_sample_formats(codec))?;
+      let sample_rate =
+        negotiate_sample_rate(&codec_name, sample_rate, &supported_sample_rates(codec))?;
+
       let mut codec_context = avcodec_alloc_context3(codec);
 
-      let parameters = &output_stream.parameters;
-      if let Some(ParameterValue::Rational(data)) = parameters.get("sample_rate") {
-        (*codec_context).time_base = data.clone().invert().into();
-        (*codec_context).sample_rate = data.num / data.den;
+      if let Some(sample_rate) = sample_rate {
+        (*codec_context).time_base = AVRational {
+          num: 1,
+          den: sample_rate,
+        };
+        (*codec_context).sample_rate = sample_rate;
       }
 
-      if let Some(ParameterValue::String(data)) = parameters.get("sample_fmt") {
-        let sample_fmt: SampleFormat = data.parse().unwrap();
-        (*codec_context).sample_fmt = sample_fmt.into();
+      if let Some(sample_fmt) = sample_fmt {
+        (*codec_context).sample_fmt = sample_fmt;
       }
 
-      (*codec_context).channel_layout = AudioEncoder::select_channel_layout(codec, parameters);
-      (*codec_context).channels =
-        av_get_channel_layout_nb_channels((*codec_context).channel_layout);
+      if let Err(error) = channel_layout.copy_to(&mut (*codec_context).ch_layout) {
+        avcodec_free_context(&mut codec_context);
+        return Err(error);
+      }
 
-      check_result!(avcodec_open2(codec_context, codec, null_mut()), {
+      if let Err(error) =
+        parameters::open_encoder(codec_context, codec, parameters, AUDIO_PARAMETERS)
+      {
         avcodec_free_context(&mut codec_context);
-      });
+        return Err(error);
+      }
+
+      let fixed_frame_size = (*codec_context).frame_size > 0
+        && (*codec).capabilities & AV_CODEC_CAP_VARIABLE_FRAME_SIZE as i32 == 0;
+      let fifo = if fixed_frame_size {
+        let fifo = av_audio_fifo_alloc(
+          (*codec_context).sample_fmt,
+          (*codec_context).ch_layout.nb_channels,
+          (*codec_context).frame_size,
+        );
+        if fifo.is_null() {
+          avcodec_free_context(&mut codec_context);
+          return Err(Error::from_av_error(AVERROR(ENOMEM)));
+        }
+        fifo
+      } else {
+        null_mut()
+      };
 
       Ok(AudioEncoder {
         identifier,
         stream_index,
         codec_context,
         codec,
+        fifo,
+        pts: None,
       })
     }
   }
 
-  pub fn encode(&self, frame: &Frame, packet: &Packet) -> Result<bool, String> {
+  /// Encode a frame of any size, and return the packets produced by the encoder.
+  ///
+  /// For codecs with a fixed frame size (AAC, AC-3, MP2, Opus...), the samples are buffered
+  /// and sent by frames of `frame_size` samples. Call `flush` at the end of the stream
+  /// to encode the remaining samples.
+  pub fn encode(&mut self, frame: &Frame) -> Result<Vec<Packet>, Error> {
+    self.check_frame(frame)?;
+    let codec_time_base = unsafe { (*self.codec_context).time_base };
+    let mut pts = unsafe { (*frame.frame).pts };
+    if let Some(time_base) = frame.get_time_base() {
+      if pts != AV_NOPTS_VALUE {
+        pts = unsafe { av_rescale_q(pts, time_base.into(), codec_time_base) };
+      }
+    }
+    if self.fifo.is_null() {
+      unsafe {
+        // a new reference to the same buffers, to leave the timestamps of the input untouched
+        let mut encoded_frame = av_frame_clone(frame.frame);
+        if encoded_frame.is_null() {
+          return Err(Error::from_av_error(AVERROR(ENOMEM)));
+        }
+        (*encoded_frame).pts = pts;
+        (*encoded_frame).time_base = codec_time_base;
+        let packets = self.send_frame(encoded_frame);
+        av_frame_free(&mut encoded_frame);
+        return packets;
+      }
+    }
     unsafe {
-      check_result!(avcodec_send_frame(self.codec_context, frame.frame));
-      let ret = avcodec_receive_packet(self.codec_context, packet.packet as *mut _);
+      let buffered = i64::from(av_audio_fifo_size(self.fifo));
+      let frame_size = (*self.codec_context).frame_size;
+      match self.pts {
+        None => self.pts = Some(if pts == AV_NOPTS_VALUE { 0 } else { pts }),
+        // follow the gaps and overlaps of the input, instead of drifting from it
+        Some(head_pts)
+          if pts != AV_NOPTS_VALUE
+            && (pts - (head_pts + buffered)).abs() > i64::from(frame_size) =>
+        {
+          warn!(
+            "audio discontinuity on {}: expected pts {}, got {pts}",
+            self.identifier,
+            head_pts + buffered
+          );
+          self.pts = Some(pts - buffered);
+        }
+        Some(_) => {}
+      }
+      check_result!(av_audio_fifo_write(
+        self.fifo,
+        (*frame.frame).extended_data as *mut *mut c_void,
+        (*frame.frame).nb_samples
+      ));
 
-      if ret == AVERROR(EAGAIN) || ret == AVERROR_EOF {
-        let mut data = [0; AV_ERROR_MAX_STRING_SIZE];
-        av_strerror(ret, data.as_mut_ptr(), AV_ERROR_MAX_STRING_SIZE);
-        trace!("{}", tools::to_string(data.as_ptr()));
-        return Ok(false);
+      let mut packets = vec![];
+      while av_audio_fifo_size(self.fifo) >= frame_size {
+        packets.extend(self.encode_from_fifo()?);
       }
+      Ok(packets)
+    }
+  }
 
-      check_result!(ret);
+  /// Check that the samples of the frame are laid out as the encoder expects them.
+  fn check_frame(&self, frame: &Frame) -> Result<(), Error> {
+    unsafe {
+      let context = self.codec_context;
+      let channel_layout = frame.get_channel_layout();
+      let expected_layout = ChannelLayout::from_av_layout(&(*context).ch_layout);
+      let layout_matches = if channel_layout.is_specified() {
+        channel_layout == expected_layout
+      } else {
+        channel_layout.get_nb_channels() == expected_layout.get_nb_channels()
+      };
+      if (*frame.frame).format == (*context).sample_fmt as i32
+        && (*frame.frame).sample_rate == (*context).sample_rate
+        && layout_matches
+      {
+        return Ok(());
+      }
+      Err(Error::Configuration(format!(
+        "Audio frame ({}, {} Hz, {channel_layout}) does not match the encoder {} ({}, {} Hz, {expected_layout})",
+        get_sample_fmt_name((*frame.frame).format),
+        (*frame.frame).sample_rate,
+        self.identifier,
+        get_sample_fmt_name((*context).sample_fmt as i32),
+        (*context).sample_rate,
+      )))
+    }
+  }
+
+  /// Encode the samples left in the FIFO, padded with silence if the codec requires it,
+  /// then drain the encoder.
+  pub fn flush(&mut self) -> Result<Vec<Packet>, Error> {
+    let mut packets = vec![];
+    unsafe {
+      if !self.fifo.is_null() && av_audio_fifo_size(self.fifo) > 0 {
+        packets.extend(self.encode_from_fifo()?);
+      }
+    }
+    packets.extend(self.send_frame(null_mut())?);
+    Ok(packets)
+  }
+
+  /// Read up to `frame_size` samples from the FIFO into a new frame and encode it.
+  unsafe fn encode_from_fifo(&mut self) -> Result<Vec<Packet>, Error> {
+    let frame_size = (*self.codec_context).frame_size;
+    let nb_samples = av_audio_fifo_size(self.fifo).min(frame_size);
+    let small_last_frame = (*self.codec).capabilities & AV_CODEC_CAP_SMALL_LAST_FRAME as i32 != 0;
 
-      trace!(
-        "received encoded packet with {} bytes",
-        (*packet.packet).size
+    let mut frame = av_frame_alloc();
+    if frame.is_null() {
+      return Err(Error::from_av_error(AVERROR(ENOMEM)));
+    }
+    (*frame).nb_samples = if small_last_frame {
+      nb_samples
+    } else {
+      frame_size
+    };
+    (*frame).format = (*self.codec_context).sample_fmt as i32;
+    (*frame).sample_rate = (*self.codec_context).sample_rate;
+    check_result!(
+      av_channel_layout_copy(&mut (*frame).ch_layout, &(*self.codec_context).ch_layout),
+      {
+        av_frame_free(&mut frame);
+      }
+    );
+    check_result!(av_frame_get_buffer(frame, 0), {
+      av_frame_free(&mut frame);
+    });
+
+    if nb_samples < (*frame).nb_samples {
+      av_samples_set_silence(
+        (*frame).extended_data,
+        0,
+        (*frame).nb_samples,
+        (*frame).ch_layout.nb_channels,
+        (*self.codec_context).sample_fmt,
       );
-      Ok(true)
     }
+    let read = av_audio_fifo_read(
+      self.fifo,
+      (*frame).extended_data as *mut *mut c_void,
+      nb_samples,
+    );
+    check_result!(read, {
+      av_frame_free(&mut frame);
+    });
+
+    let pts = self.pts.unwrap_or(0);
+    (*frame).pts = pts;
+    self.pts = Some(pts + i64::from(nb_samples));
+
+    let packets = self.send_frame(frame);
+    av_frame_free(&mut frame);
+    packets
   }
 
-  fn select_channel_layout(
-    codec: *const AVCodec,
-    parameters: &HashMap<String, ParameterValue>,
-  ) -> u64 {
+  /// Send a frame, or null to enter draining mode, and receive all the available packets.
+  fn send_frame(&self, frame: *mut AVFrame) -> Result<Vec<Packet>, Error> {
+    let mut packets = vec![];
     unsafe {
-      if codec.is_null() || (*codec).channel_layouts.is_null() {
-        if let Some(ParameterValue::String(data)) = parameters.get("channel_layout") {
-          let layout: ChannelLayout = data.parse().unwrap();
-          layout.into()
-        } else {
-          AV_CH_LAYOUT_STEREO
+      check_result!(avcodec_send_frame(self.codec_context, frame));
+      loop {
+        let packet = Packet::new()?;
+        let ret = avcodec_receive_packet(self.codec_context, packet.packet);
+
+        if ret == AVERROR(EAGAIN) || ret == AVERROR_EOF {
+          let mut data = [0; AV_ERROR_MAX_STRING_SIZE];
+          av_strerror(ret, data.as_mut_ptr(), AV_ERROR_MAX_STRING_SIZE);
+          trace!("{}", tools::to_string(data.as_ptr()));
+          break;
         }
-      } else {
-        AV_CH_LAYOUT_STEREO
+
+        check_result!(ret);
+
+        trace!("received encoded packet with {} bytes", packet.get_size());
+        packets.push(packet);
       }
     }
+    Ok(packets)
+  }
+
+  /// Any ffmpeg layout ("5.1(side)", "FL+FR+LFE", "16c"...).
+  fn get_channel_layout_parameter(
+    parameters: &HashMap<String, ParameterValue>,
+  ) -> Result<Option<ChannelLayout>, Error> {
+    match parameters.get("channel_layout") {
+      Some(ParameterValue::String(data)) => data.parse().map(Some),
+      Some(ParameterValue::ChannelLayout(layout)) => Ok(Some(layout.clone())),
+      _ => Ok(None),
+    }
+  }
+}
+
+fn get_sample_fmt_name(sample_fmt: i32) -> String {
+  if !(0..AVSampleFormat::AV_SAMPLE_FMT_NB as i32).contains(&sample_fmt) {
+    return "none".to_string();
+  }
+  unsafe {
+    tools::to_string(av_get_sample_fmt_name(std::mem::transmute::<
+      i32,
+      AVSampleFormat,
+    >(sample_fmt)))
+  }
+}
+
+/// Channel layouts supported by the codec, empty when any layout is accepted.
+pub(crate) unsafe fn supported_channel_layouts(codec: *const AVCodec) -> Vec<ChannelLayout> {
+  let mut layouts = vec![];
+  let mut layout = (*codec).ch_layouts;
+  while !layout.is_null() && (*layout).nb_channels != 0 {
+    layouts.push(ChannelLayout::from_av_layout(layout));
+    layout = layout.add(1);
+  }
+  layouts
+}
+
+/// Sample formats supported by the codec, empty when unknown.
+pub(crate) unsafe fn supported_sample_formats(codec: *const AVCodec) -> Vec<AVSampleFormat> {
+  let mut sample_fmts = vec![];
+  let mut sample_fmt = (*codec).sample_fmts;
+  while !sample_fmt.is_null() && *sample_fmt != AVSampleFormat::AV_SAMPLE_FMT_NONE {
+    sample_fmts.push(*sample_fmt);
+    sample_fmt = sample_fmt.add(1);
+  }
+  sample_fmts
+}
+
+/// Sample rates supported by the codec, empty when any rate is accepted.
+pub(crate) unsafe fn supported_sample_rates(codec: *const AVCodec) -> Vec<i32> {
+  let mut sample_rates = vec![];
+  let mut sample_rate = (*codec).supported_samplerates;
+  while !sample_rate.is_null() && *sample_rate != 0 {
+    sample_rates.push(*sample_rate);
+    sample_rate = sample_rate.add(1);
+  }
+  sample_rates
+}
+
+/// The requested layout, or a supported one with the same number of channels.
+/// Without request, stereo or the first supported layout.
+fn negotiate_channel_layout(
+  codec_name: &str,
+  requested: Option<ChannelLayout>,
+  supported: &[ChannelLayout],
+) -> Result<ChannelLayout, Error> {
+  let stereo = ChannelLayout::with_nb_channels(2);
+  if supported.is_empty() {
+    return Ok(requested.unwrap_or(stereo));
+  }
+  let requested = match requested {
+    Some(requested) => requested,
+    None => {
+      return Ok(
+        supported
+          .iter()
+          .find(|layout| **layout == stereo)
+          .unwrap_or(&supported[0])
+          .clone(),
+      )
+    }
+  };
+  if supported.contains(&requested) {
+    return Ok(requested);
+  }
+  if let Some(layout) = supported
+    .iter()
+    .find(|layout| layout.get_nb_channels() == requested.get_nb_channels())
+  {
+    warn!("Channel layout {requested} is not supported by {codec_name}, using {layout}");
+    return Ok(layout.clone());
   }
+  let supported: Vec<String> = supported.iter().map(|layout| layout.to_string()).collect();
+  Err(Error::Configuration(format!(
+    "Channel layout {requested} is not supported by {codec_name}, supported layouts: {}",
+    supported.join(", ")
+  )))
+}
+
+/// The requested format, or its packed/planar counterpart. Without request, the first supported format.
+fn negotiate_sample_format(
+  codec_name: &str,
+  requested: Option<AVSampleFormat>,
+  supported: &[AVSampleFormat],
+) -> Result<Option<AVSampleFormat>, Error> {
+  if supported.is_empty() {
+    return Ok(requested);
+  }
+  let requested = match requested {
+    Some(requested) => requested,
+    None => return Ok(Some(supported[0])),
+  };
+  if supported.contains(&requested) {
+    return Ok(Some(requested));
+  }
+  unsafe {
+    let counterpart = if av_sample_fmt_is_planar(requested) != 0 {
+      av_get_packed_sample_fmt(requested)
+    } else {
+      av_get_planar_sample_fmt(requested)
+    };
+    if supported.contains(&counterpart) {
+      warn!(
+        "Sample format {} is not supported by {codec_name}, using {}",
+        tools::to_string(av_get_sample_fmt_name(requested)),
+        tools::to_string(av_get_sample_fmt_name(counterpart))
+      );
+      return Ok(Some(counterpart));
+    }
+    let supported: Vec<String> = supported
+      .iter()
+      .map(|sample_fmt| tools::to_string(av_get_sample_fmt_name(*sample_fmt)))
+      .collect();
+    Err(Error::Configuration(format!(
+      "Sample format {} is not supported by {codec_name}, supported formats: {}",
+      tools::to_string(av_get_sample_fmt_name(requested)),
+      supported.join(", ")
+    )))
+  }
+}
+
+/// The requested rate, or the nearest supported one if close enough. Without request,
+/// 48kHz or the first supported rate.
+fn negotiate_sample_rate(
+  codec_name: &str,
+  requested: Option<i32>,
+  supported: &[i32],
+) -> Result<Option<i32>, Error> {
+  if supported.is_empty() {
+    return Ok(requested);
+  }
+  let requested = match requested {
+    Some(requested) => requested,
+    None if supported.contains(&48000) => return Ok(Some(48000)),
+    None => return Ok(Some(supported[0])),
+  };
+  if supported.contains(&requested) {
+    return Ok(Some(requested));
+  }
+  let nearest = supported
+    .iter()
+    .copied()
+    .min_by_key(|sample_rate| (i64::from(*sample_rate) - i64::from(requested)).abs())
+    .unwrap_or(requested);
+  let deviation = (f64::from(nearest) - f64::from(requested)).abs() / f64::from(requested);
+  if deviation > MAX_SAMPLE_RATE_DEVIATION {
+    let supported: Vec<String> = supported.iter().map(|rate| rate.to_string()).collect();
+    return Err(Error::Configuration(format!(
+      "Sample rate {requested} is not supported by {codec_name}, supported rates: {}",
+      supported.join(", ")
+    )));
+  }
+  warn!("Sample rate {requested} is not supported by {codec_name}, using {nearest}");
+  Ok(Some(nearest))
 }
 
 impl Drop for AudioEncoder {
   fn drop(&mut self) {
     unsafe {
+      if !self.fifo.is_null() {
+        av_audio_fifo_free(self.fifo);
+      }
       if !self.codec_context.is_null() {
         avcodec_close(self.codec_context);
         avcodec_free_context(&mut self.codec_context);
@@ -110,3 +496,169 @@ impl Drop for AudioEncoder {
     }
   }
 }
+
+#[test]
+fn audio_encoder_fifo() {
+  use crate::stream::Disposition;
+  use crate::tools::rational::Rational;
+
+  let mut parameters = HashMap::new();
+  parameters.insert(
+    "sample_rate".to_string(),
+    ParameterValue::Rational(Rational::from(48000)),
+  );
+  parameters.insert(
+    "sample_fmt".to_string(),
+    ParameterValue::String("s16".to_string()),
+  );
+  let output_stream = OutputStream {
+    label: None,
+    codec: "mp2".to_string(),
+    parameters,
+    disposition: Disposition::default(),
+    language: None,
+  };
+  let mut encoder = AudioEncoder::new("mp2".to_string(), 0, &output_stream).unwrap();
+
+  // 3000 samples make 2 frames of 1152 samples, and a last one padded with silence
+  let samples = [0i16; 2000];
+  let mut packets = vec![];
+  for index in 0..3 {
+    let frame = Frame::from_interleaved_samples(&samples, 2, 48000, index * 1000).unwrap();
+    packets.extend(encoder.encode(&frame).unwrap());
+  }
+  assert_eq!(packets.len(), 2);
+  packets.extend(encoder.flush().unwrap());
+  assert_eq!(packets.len(), 3);
+
+  let pts: Vec<i64> = packets
+    .iter()
+    .map(|packet| packet.get_pts().unwrap())
+    .collect();
+  assert_eq!(pts[1] - pts[0], 1152);
+  assert_eq!(pts[2] - pts[1], 1152);
+
+  // the samples are copied with the layout of the encoder
+  let mono = Frame::from_interleaved_samples(&samples, 1, 48000, 0).unwrap();
+  assert!(matches!(
+    encoder.encode(&mono),
+    Err(Error::Configuration(_))
+  ));
+  let float = Frame::from_interleaved_samples(&[0f32; 2000], 2, 48000, 0).unwrap();
+  assert!(matches!(
+    encoder.encode(&float),
+    Err(Error::Configuration(_))
+  ));
+}
+
+#[test]
+fn audio_encoder_negotiation() {
+  use crate::stream::Disposition;
+  use crate::tools::rational::Rational;
+
+  let new_encoder = |parameters: Vec<(&str, ParameterValue)>| {
+    let output_stream = OutputStream {
+      label: None,
+      codec: "mp2".to_string(),
+      parameters: parameters
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value))
+        .collect(),
+      disposition: Disposition::default(),
+      language: None,
+    };
+    AudioEncoder::new("mp2".to_string(), 0, &output_stream)
+  };
+
+  let encoder = new_encoder(vec![
+    (
+      "sample_rate",
+      ParameterValue::Rational(Rational::from(44000)),
+    ),
+    ("sample_fmt", ParameterValue::String("s16p".to_string())),
+    ("channel_layout", ParameterValue::String("mono".to_string())),
+  ])
+  .unwrap();
+  unsafe {
+    assert_eq!((*encoder.codec_context).sample_rate, 44100);
+    assert_eq!(
+      (*encoder.codec_context).sample_fmt,
+      AVSampleFormat::AV_SAMPLE_FMT_S16
+    );
+    assert_eq!((*encoder.codec_context).ch_layout.nb_channels, 1);
+  }
+
+  let error = new_encoder(vec![(
+    "channel_layout",
+    ParameterValue::String("5.1".to_string()),
+  )])
+  .unwrap_err();
+  assert_eq!(
+    error,
+    Error::Configuration(
+      "Channel layout 5.1 is not supported by mp2, supported layouts: mono, stereo".to_string()
+    )
+  );
+
+  let error = new_encoder(vec![(
+    "sample_fmt",
+    ParameterValue::String("float".to_string()),
+  )])
+  .unwrap_err();
+  assert_eq!(
+    error,
+    Error::Configuration(
+      "Sample format flt is not supported by mp2, supported formats: s16".to_string()
+    )
+  );
+
+  let error = new_encoder(vec![(
+    "sample_rate",
+    ParameterValue::Rational(Rational::from(96000)),
+  )])
+  .unwrap_err();
+  assert_eq!(
+    error,
+    Error::Configuration(
+      "Sample rate 96000 is not supported by mp2, supported rates: 44100, 48000, 32000, 22050, 24000, 16000"
+        .to_string()
+    )
+  );
+
+  for sample_rate in [Rational::new(48000, 0), Rational::new(-48000, 1)] {
+    assert!(matches!(
+      new_encoder(vec![("sample_rate", ParameterValue::Rational(sample_rate))]),
+      Err(Error::Configuration(_))
+    ));
+  }
+}
+
+#[test]
+fn audio_encoder_discontinuity() {
+  use crate::stream::Disposition;
+
+  let output_stream = OutputStream {
+    label: None,
+    codec: "mp2".to_string(),
+    parameters: HashMap::new(),
+    disposition: Disposition::default(),
+    language: None,
+  };
+  let mut encoder = AudioEncoder::new("mp2".to_string(), 0, &output_stream).unwrap();
+
+  // a gap of 10000 samples between two frames of 1152 samples
+  let samples = [0i16; 2 * 1152];
+  let mut packets = vec![];
+  for pts in [0, 1152 + 10000] {
+    let frame = Frame::from_interleaved_samples(&samples, 2, 48000, pts).unwrap();
+    packets.extend(encoder.encode(&frame).unwrap());
+  }
+  packets.extend(encoder.flush().unwrap());
+
+  let pts: Vec<i64> = packets
+    .iter()
+    .map(|packet| packet.get_pts().unwrap())
+    .collect();
+  assert_eq!(pts.len(), 2);
+  assert_eq!(pts[1] - pts[0], 1152 + 10000);
+}
@@ -0,0 +1,225 @@
+use crate::{error::Error, tools};
+use ffmpeg_sys_next::*;
+use std::{
+  convert::TryFrom,
+  ffi::CString,
+  fmt,
+  mem::zeroed,
+  os::raw::{c_char, c_uint},
+  str::FromStr,
+};
+
+/// An audio channel layout: native ("5.1(side)"), custom ("FL+FR+LFE" in any order,
+/// with optional channel names), ambisonic, or only a number of channels.
+///
+/// Parsed from and serialized to the ffmpeg layout strings.
+#[derive(Deserialize, Serialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct ChannelLayout {
+  layout: AVChannelLayout,
+}
+
+// the layout owns its custom channel map
+unsafe impl Send for ChannelLayout {}
+unsafe impl Sync for ChannelLayout {}
+
+impl ChannelLayout {
+  /// Default layout for a number of channels, or an unspecified order when there is none.
+  pub fn with_nb_channels(nb_channels: usize) -> Self {
+    let mut channel_layout = ChannelLayout::empty();
+    unsafe {
+      av_channel_layout_default(&mut channel_layout.layout, nb_channels as i32);
+    }
+    channel_layout
+  }
+
+  /// Only a number of channels, without any position.
+  pub fn unspecified(nb_channels: usize) -> Self {
+    let mut channel_layout = ChannelLayout::empty();
+    channel_layout.layout.order = AVChannelOrder::AV_CHANNEL_ORDER_UNSPEC;
+    channel_layout.layout.nb_channels = nb_channels as i32;
+    channel_layout
+  }
+
+  /// Native layout from a deprecated `AV_CH_LAYOUT_*` mask.
+  pub fn from_mask(mask: u64) -> Result<Self, Error> {
+    let mut channel_layout = ChannelLayout::empty();
+    unsafe {
+      check_result!(av_channel_layout_from_mask(
+        &mut channel_layout.layout,
+        mask
+      ));
+    }
+    Ok(channel_layout)
+  }
+
+  /// # Safety
+  /// `layout` must point to a valid `AVChannelLayout`, which is copied.
+  pub unsafe fn from_av_layout(layout: *const AVChannelLayout) -> Self {
+    let mut channel_layout = ChannelLayout::empty();
+    if av_channel_layout_copy(&mut channel_layout.layout, layout) < 0 {
+      return ChannelLayout::unspecified((*layout).nb_channels.max(0) as usize);
+    }
+    channel_layout
+  }
+
+  /// # Safety
+  /// `destination` must point to an initialized or zeroed `AVChannelLayout`.
+  pub unsafe fn copy_to(&self, destination: *mut AVChannelLayout) -> Result<(), Error> {
+    check_result!(av_channel_layout_copy(destination, &self.layout));
+    Ok(())
+  }
+
+  pub fn as_ptr(&self) -> *const AVChannelLayout {
+    &self.layout
+  }
+
+  pub fn get_nb_channels(&self) -> usize {
+    self.layout.nb_channels.max(0) as usize
+  }
+
+  pub fn get_order(&self) -> AVChannelOrder {
+    self.layout.order
+  }
+
+  /// `false` when only the number of channels is known.
+  pub fn is_specified(&self) -> bool {
+    self.layout.order != AVChannelOrder::AV_CHANNEL_ORDER_UNSPEC
+  }
+
+  /// Mask of the native layouts.
+  pub fn get_mask(&self) -> Option<u64> {
+    if self.layout.order == AVChannelOrder::AV_CHANNEL_ORDER_NATIVE {
+      Some(unsafe { self.layout.u.mask })
+    } else {
+      None
+    }
+  }
+
+  /// Name of each channel ("FL", "FR", "LFE", "AMBI0"...), or the custom names.
+  /// Empty for unspecified layouts.
+  pub fn get_channel_names(&self) -> Vec<String> {
+    if !self.is_specified() {
+      return vec![];
+    }
+    (0..self.get_nb_channels())
+      .map(|index| unsafe {
+        if self.layout.order == AVChannelOrder::AV_CHANNEL_ORDER_CUSTOM {
+          let custom = &*self.layout.u.map.add(index);
+          if custom.name[0] != 0 {
+            return tools::to_string(custom.name.as_ptr());
+          }
+        }
+        let channel = av_channel_layout_channel_from_index(&self.layout, index as c_uint);
+        let mut name = [0 as c_char; 32];
+        av_channel_name(name.as_mut_ptr(), name.len(), channel);
+        tools::to_string(name.as_ptr())
+      })
+      .collect()
+  }
+
+  fn empty() -> Self {
+    ChannelLayout {
+      layout: unsafe { zeroed() },
+    }
+  }
+}
+
+impl Clone for ChannelLayout {
+  fn clone(&self) -> Self {
+    unsafe { ChannelLayout::from_av_layout(&self.layout) }
+  }
+}
+
+impl Drop for ChannelLayout {
+  fn drop(&mut self) {
+    unsafe {
+      av_channel_layout_uninit(&mut self.layout);
+    }
+  }
+}
+
+impl PartialEq for ChannelLayout {
+  fn eq(&self, other: &Self) -> bool {
+    unsafe { av_channel_layout_compare(&self.layout, &other.layout) == 0 }
+  }
+}
+
+impl Eq for ChannelLayout {}
+
+impl fmt::Display for ChannelLayout {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut description = vec![0 as c_char; 64];
+    unsafe {
+      let size =
+        av_channel_layout_describe(&self.layout, description.as_mut_ptr(), description.len());
+      if size > description.len() as i32 {
+        description.resize(size as usize, 0);
+        av_channel_layout_describe(&self.layout, description.as_mut_ptr(), description.len());
+      }
+      write!(f, "{}", tools::to_string(description.as_ptr()))
+    }
+  }
+}
+
+impl fmt::Debug for ChannelLayout {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "ChannelLayout({self})")
+  }
+}
+
+impl FromStr for ChannelLayout {
+  type Err = Error;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    let error =
+      || Error::Configuration(format!("'{value}' is not a valid value for ChannelLayout"));
+    let name = CString::new(value).map_err(|_| error())?;
+    let mut channel_layout = ChannelLayout::empty();
+    unsafe {
+      if av_channel_layout_from_string(&mut channel_layout.layout, name.as_ptr()) < 0
+        || av_channel_layout_check(&channel_layout.layout) == 0
+      {
+        return Err(error());
+      }
+    }
+    Ok(channel_layout)
+  }
+}
+
+impl TryFrom<String> for ChannelLayout {
+  type Error = Error;
+
+  fn try_from(value: String) -> Result<Self, Self::Error> {
+    value.parse()
+  }
+}
+
+impl From<ChannelLayout> for String {
+  fn from(channel_layout: ChannelLayout) -> String {
+    channel_layout.to_string()
+  }
+}
+
+#[test]
+fn channel_layouts() {
+  let layout: ChannelLayout = "5.1".parse().unwrap();
+  assert_eq!(layout.get_nb_channels(), 6);
+  assert_eq!(layout.get_mask(), Some(AV_CH_LAYOUT_5POINT1));
+  assert_eq!(
+    layout.get_channel_names(),
+    vec!["FL", "FR", "FC", "LFE", "SL", "SR"]
+  );
+  assert_eq!(
+    layout,
+    ChannelLayout::from_mask(AV_CH_LAYOUT_5POINT1).unwrap()
+  );
+
+  let layout = ChannelLayout::unspecified(16);
+  assert_eq!(layout.get_nb_channels(), 16);
+  assert!(layout.get_channel_names().is_empty());
+  assert_eq!(layout.clone(), layout);
+
+  assert_eq!(ChannelLayout::with_nb_channels(2).to_string(), "stereo");
+  assert!("not a layout".parse::<ChannelLayout>().is_err());
+}
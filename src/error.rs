@@ -1,20 +1,182 @@
+use crate::tools;
+use ffmpeg_sys_next::*;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+  /// The end of the stream has been reached (`AVERROR_EOF`).
+  EndOfStream,
+  /// No output is available in the current state, more input is required (`AVERROR(EAGAIN)`).
+  Again,
+  /// Invalid data found when processing input (`AVERROR_INVALIDDATA`).
+  InvalidData(String),
+  /// No decoder, encoder, filter or format matches the requested name,
+  /// with the matching `AVERROR_*_NOT_FOUND` code.
+  CodecNotFound { code: i32, message: String },
+  /// Input/output error, with the originating AVERROR code.
+  Io { code: i32, message: String },
+  /// Invalid order, parameter or option value.
+  Configuration(String),
+  /// The option is unknown to the codec, filter or format (`AVERROR_OPTION_NOT_FOUND`).
+  OptionNotFound(String),
+  /// Any other FFmpeg error, with its AVERROR code.
+  FFmpeg { code: i32, message: String },
+}
+
+impl Error {
+  /// Classify an AVERROR code returned by FFmpeg.
+  pub fn from_av_error(code: i32) -> Self {
+    if code == AVERROR_EOF {
+      return Error::EndOfStream;
+    }
+    if code == AVERROR(EAGAIN) {
+      return Error::Again;
+    }
+
+    let message = av_error_string(code);
+    if code == AVERROR_INVALIDDATA {
+      Error::InvalidData(message)
+    } else if code == AVERROR_DECODER_NOT_FOUND
+      || code == AVERROR_ENCODER_NOT_FOUND
+      || code == AVERROR_FILTER_NOT_FOUND
+      || code == AVERROR_MUXER_NOT_FOUND
+      || code == AVERROR_DEMUXER_NOT_FOUND
+    {
+      Error::CodecNotFound { code, message }
+    } else if code == AVERROR_OPTION_NOT_FOUND {
+      Error::OptionNotFound(message)
+    } else if code == AVERROR(EIO)
+      || code == AVERROR(ENOENT)
+      || code == AVERROR(EACCES)
+      || code == AVERROR(EPIPE)
+      || code == AVERROR(ENOSPC)
+    {
+      Error::Io { code, message }
+    } else {
+      Error::FFmpeg { code, message }
+    }
+  }
+
+  /// The AVERROR code behind this error, if it originates from FFmpeg.
+  pub fn code(&self) -> Option<i32> {
+    match self {
+      Error::EndOfStream => Some(AVERROR_EOF),
+      Error::Again => Some(AVERROR(EAGAIN)),
+      Error::InvalidData(_) => Some(AVERROR_INVALIDDATA),
+      Error::OptionNotFound(_) => Some(AVERROR_OPTION_NOT_FOUND),
+      Error::CodecNotFound { code, .. } | Error::Io { code, .. } | Error::FFmpeg { code, .. } => {
+        Some(*code)
+      }
+      Error::Configuration(_) => None,
+    }
+  }
+
+  pub fn is_eof(&self) -> bool {
+    *self == Error::EndOfStream
+  }
+
+  pub fn is_again(&self) -> bool {
+    *self == Error::Again
+  }
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Error::EndOfStream => write!(f, "End of data stream"),
+      Error::Again => write!(f, "Resource temporarily unavailable"),
+      Error::InvalidData(message) => write!(f, "Invalid data: {message}"),
+      Error::CodecNotFound { message, .. } => write!(f, "Not found: {message}"),
+      Error::Io { code, message } => write!(f, "I/O error ({code}): {message}"),
+      Error::Configuration(message) | Error::OptionNotFound(message) => {
+        write!(f, "Configuration error: {message}")
+      }
+      Error::FFmpeg { code, message } => write!(f, "FFmpeg error ({code}): {message}"),
+    }
+  }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+  fn from(error: std::io::Error) -> Self {
+    let code = error.raw_os_error().map(AVERROR).unwrap_or(AVERROR(EIO));
+    Error::Io {
+      code,
+      message: error.to_string(),
+    }
+  }
+}
+
+impl From<serde_json::Error> for Error {
+  fn from(error: serde_json::Error) -> Self {
+    Error::Configuration(error.to_string())
+  }
+}
+
+fn av_error_string(code: i32) -> String {
+  let mut data = [0; AV_ERROR_MAX_STRING_SIZE];
+  unsafe {
+    av_strerror(code, data.as_mut_ptr(), AV_ERROR_MAX_STRING_SIZE);
+    tools::to_string(data.as_ptr())
+  }
+}
+
 #[macro_export]
 macro_rules! check_result {
   ($condition: expr, $block: block) => {
     let errnum = $condition;
     if errnum < 0 {
-      let mut data = [0; AV_ERROR_MAX_STRING_SIZE];
-      av_strerror(errnum, data.as_mut_ptr(), AV_ERROR_MAX_STRING_SIZE);
       $block;
-      return Err(tools::to_string(data.as_ptr()));
+      return Err($crate::error::Error::from_av_error(errnum));
     }
   };
   ($condition: expr) => {
     let errnum = $condition;
     if errnum < 0 {
-      let mut data = [0; AV_ERROR_MAX_STRING_SIZE];
-      av_strerror(errnum, data.as_mut_ptr(), AV_ERROR_MAX_STRING_SIZE);
-      return Err(tools::to_string(data.as_ptr()));
+      return Err($crate::error::Error::from_av_error(errnum));
     }
   };
 }
+
+#[test]
+fn error_classification() {
+  assert_eq!(Error::from_av_error(AVERROR_EOF), Error::EndOfStream);
+  assert_eq!(Error::from_av_error(AVERROR(EAGAIN)), Error::Again);
+  assert!(matches!(
+    Error::from_av_error(AVERROR_INVALIDDATA),
+    Error::InvalidData(_)
+  ));
+  assert!(matches!(
+    Error::from_av_error(AVERROR_DECODER_NOT_FOUND),
+    Error::CodecNotFound { .. }
+  ));
+  assert!(matches!(
+    Error::from_av_error(AVERROR_OPTION_NOT_FOUND),
+    Error::OptionNotFound(_)
+  ));
+  assert!(matches!(
+    Error::from_av_error(AVERROR(EIO)),
+    Error::Io { .. }
+  ));
+  // the code is kept whatever the classification
+  for code in [
+    AVERROR_EOF,
+    AVERROR(EAGAIN),
+    AVERROR_INVALIDDATA,
+    AVERROR_DECODER_NOT_FOUND,
+    AVERROR_ENCODER_NOT_FOUND,
+    AVERROR_FILTER_NOT_FOUND,
+    AVERROR_MUXER_NOT_FOUND,
+    AVERROR_DEMUXER_NOT_FOUND,
+    AVERROR_OPTION_NOT_FOUND,
+    AVERROR(EIO),
+    AVERROR(ENOENT),
+    AVERROR(ENOMEM),
+    AVERROR(EINVAL),
+    AVERROR_BUG,
+    -1,
+  ] {
+    assert_eq!(Error::from_av_error(code).code(), Some(code));
+  }
+}
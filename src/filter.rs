@@ -1,4 +1,4 @@
-use crate::tools;
+use crate::{error::Error, tools};
 use ffmpeg_sys_next::AVOptionType::*;
 use ffmpeg_sys_next::*;
 use libc::c_char;
@@ -11,7 +11,7 @@ pub struct Filter {
 
 impl Filter {
   /// # Safety
-  pub unsafe fn new(filter_graph: *mut AVFilterGraph, plugin_name: &str) -> Result<Self, String> {
+  pub unsafe fn new(filter_graph: *mut AVFilterGraph, plugin_name: &str) -> Result<Self, Error> {
     Filter::new_with_label(filter_graph, plugin_name, "")
   }
 
@@ -20,14 +20,14 @@ impl Filter {
     filter_graph: *mut AVFilterGraph,
     plugin_name: &str,
     instance_name: &str,
-  ) -> Result<Self, String> {
+  ) -> Result<Self, Error> {
     let label = CString::new(plugin_name).unwrap();
     let filter = avfilter_get_by_name(label.as_ptr());
     if filter.is_null() {
-      return Err(format!(
-        "Could not find the {} filter",
-        label.into_string().unwrap()
-      ));
+      return Err(Error::CodecNotFound {
+        code: AVERROR_FILTER_NOT_FOUND,
+        message: format!("Could not find the {} filter", label.into_string().unwrap()),
+      });
     }
 
     let context = if instance_name.is_empty() {
@@ -38,10 +38,7 @@ impl Filter {
     };
 
     if context.is_null() {
-      return Err(format!(
-        "Could not allocate the {} instance",
-        label.into_string().unwrap()
-      ));
+      return Err(Error::from_av_error(AVERROR(ENOMEM)));
     }
 
     Ok(Filter { context })
@@ -57,7 +54,7 @@ impl Filter {
     }
   }
 
-  pub fn init(&self) -> Result<(), String> {
+  pub fn init(&self) -> Result<(), Error> {
     unsafe {
       check_result!(avfilter_init_str(self.context, null_mut()));
     }
@@ -1,10 +1,11 @@
 use crate::{
-  audio_decoder::AudioDecoder, filter::Filter, frame::Frame, order::*, tools,
-  tools::rational::Rational, video_decoder::VideoDecoder,
+  audio_decoder::AudioDecoder, audio_encoder::AudioEncoder, channel_layout::ChannelLayout,
+  error::Error, filter::Filter, frame::Frame, order::*, tools, tools::rational::Rational,
+  video_decoder::VideoDecoder, video_encoder::VideoEncoder,
 };
 use ffmpeg_sys_next::*;
 use libc::c_void;
-use std::{fmt, ptr::null_mut};
+use std::{ffi::CString, fmt, mem::size_of, ptr::null_mut};
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum GraphKind {
@@ -12,6 +13,24 @@ pub enum GraphKind {
   Audio,
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub struct VideoInputParameters {
+  pub width: i32,
+  pub height: i32,
+  pub pix_fmt: String,
+  pub time_base: Rational,
+  pub sample_aspect_ratio: Rational,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AudioInputParameters {
+  pub sample_rate: i32,
+  pub sample_fmt: String,
+  pub channel_layout: ChannelLayout,
+  /// Defaults to `1/sample_rate` when not set.
+  pub time_base: Option<Rational>,
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct FilterGraph {
   pub kind: GraphKind,
@@ -29,11 +48,11 @@ impl Default for FilterGraph {
 }
 
 impl FilterGraph {
-  pub fn new() -> Result<Self, String> {
+  pub fn new() -> Result<Self, Error> {
     unsafe {
       let filter_graph = avfilter_graph_alloc();
       if filter_graph.is_null() {
-        return Err("Unable to create filter graph".to_string());
+        return Err(Error::from_av_error(AVERROR(ENOMEM)));
       }
 
       Ok(FilterGraph {
@@ -51,27 +70,65 @@ impl FilterGraph {
     &mut self,
     label: &str,
     video_decoder: &VideoDecoder,
-  ) -> Result<(), String> {
+  ) -> Result<(), Error> {
+    let time_base = video_decoder.get_packet_time_base().unwrap_or_else(|| {
+      let (num, den) = video_decoder.get_frame_rate();
+      if num == 0 {
+        Rational::new(1, 25)
+      } else {
+        Rational::new(den, num)
+      }
+    });
+    let (sar_num, sar_den) = video_decoder.get_aspect_ratio();
+
+    let parameters = VideoInputParameters {
+      width: video_decoder.get_width(),
+      height: video_decoder.get_height(),
+      pix_fmt: video_decoder.get_pix_fmt_name(),
+      time_base,
+      sample_aspect_ratio: Rational {
+        num: sar_num,
+        den: sar_den,
+      },
+    };
+    self.add_video_input(label, &parameters)
+  }
+
+  pub fn add_input_from_audio_decoder(
+    &mut self,
+    label: &str,
+    audio_decoder: &AudioDecoder,
+  ) -> Result<(), Error> {
+    let parameters = AudioInputParameters {
+      sample_rate: audio_decoder.get_sample_rate(),
+      sample_fmt: audio_decoder.get_sample_fmt_name(),
+      channel_layout: audio_decoder.get_channel_layout(),
+      time_base: audio_decoder.get_packet_time_base(),
+    };
+    self.add_audio_input(label, &parameters)
+  }
+
+  /// Declare a `buffer` source fed with frames built by the caller.
+  pub fn add_video_input(
+    &mut self,
+    label: &str,
+    parameters: &VideoInputParameters,
+  ) -> Result<(), Error> {
     let buffer = unsafe { Filter::new_with_label(self.graph, "buffer", label)? };
 
-    let width = ParameterValue::Int64(i64::from(video_decoder.get_width()));
+    let width = ParameterValue::Int64(i64::from(parameters.width));
     width.set("width", buffer.context as *mut c_void)?;
 
-    let height = ParameterValue::Int64(i64::from(video_decoder.get_height()));
+    let height = ParameterValue::Int64(i64::from(parameters.height));
     height.set("height", buffer.context as *mut c_void)?;
 
-    let (mut num, den) = video_decoder.get_frame_rate();
-    if num == 0 {
-      num = 25;
-    }
-    let time_base = ParameterValue::Rational(Rational { num, den });
+    let time_base = ParameterValue::Rational(parameters.time_base);
     time_base.set("time_base", buffer.context as *mut c_void)?;
 
-    let (num, den) = video_decoder.get_aspect_ratio();
-    let pixel_aspect = ParameterValue::Rational(Rational { num, den });
+    let pixel_aspect = ParameterValue::Rational(parameters.sample_aspect_ratio);
     pixel_aspect.set("pixel_aspect", buffer.context as *mut c_void)?;
 
-    let pix_fmt = ParameterValue::String(video_decoder.get_pix_fmt_name());
+    let pix_fmt = ParameterValue::String(parameters.pix_fmt.clone());
     pix_fmt.set("pix_fmt", buffer.context as *mut c_void)?;
     buffer.init()?;
 
@@ -79,35 +136,53 @@ impl FilterGraph {
     Ok(())
   }
 
-  pub fn add_input_from_audio_decoder(
+  /// Declare an `abuffer` source fed with frames built by the caller.
+  pub fn add_audio_input(
     &mut self,
     label: &str,
-    audio_decoder: &AudioDecoder,
-  ) -> Result<(), String> {
+    parameters: &AudioInputParameters,
+  ) -> Result<(), Error> {
     let abuffer = unsafe { Filter::new_with_label(self.graph, "abuffer", label)? };
 
-    let layout = audio_decoder.get_channel_layout();
-    if layout > 0 {
-      let channel_layout = ParameterValue::ChannelLayout(layout);
-      channel_layout.set("channel_layout", abuffer.context as *mut c_void)?;
+    unsafe {
+      // set as parameters, as the option string does not hold custom channel names
+      let mut buffer_parameters = av_buffersrc_parameters_alloc();
+      if buffer_parameters.is_null() {
+        return Err(Error::from_av_error(AVERROR(ENOMEM)));
+      }
+      let result = parameters
+        .channel_layout
+        .copy_to(&mut (*buffer_parameters).ch_layout)
+        .and_then(|_| {
+          check_result!(av_buffersrc_parameters_set(
+            abuffer.context,
+            buffer_parameters
+          ));
+          Ok(())
+        });
+      av_channel_layout_uninit(&mut (*buffer_parameters).ch_layout);
+      av_freep(&mut buffer_parameters as *mut *mut AVBufferSrcParameters as *mut c_void);
+      result?;
     }
 
-    let sample_rate = ParameterValue::Int64(i64::from(audio_decoder.get_sample_rate()));
+    let sample_rate = ParameterValue::Int64(i64::from(parameters.sample_rate));
     sample_rate.set("sample_rate", abuffer.context as *mut c_void)?;
 
-    let channels = ParameterValue::Int64(i64::from(audio_decoder.get_nb_channels()));
-    channels.set("channels", abuffer.context as *mut c_void)?;
-
-    let sample_fmt = ParameterValue::String(audio_decoder.get_sample_fmt_name());
+    let sample_fmt = ParameterValue::String(parameters.sample_fmt.clone());
     sample_fmt.set("sample_fmt", abuffer.context as *mut c_void)?;
 
+    if let Some(time_base) = &parameters.time_base {
+      let time_base = ParameterValue::Rational(*time_base);
+      time_base.set("time_base", abuffer.context as *mut c_void)?;
+    }
+
     abuffer.init()?;
 
     self.audio_inputs.push(abuffer);
     Ok(())
   }
 
-  pub fn add_video_output(&mut self, label: &str) -> Result<(), String> {
+  pub fn add_video_output(&mut self, label: &str) -> Result<(), Error> {
     let buffersink = unsafe { Filter::new_with_label(self.graph, "buffersink", label)? };
     buffersink.init()?;
 
@@ -115,7 +190,7 @@ impl FilterGraph {
     Ok(())
   }
 
-  pub fn add_audio_output(&mut self, label: &str) -> Result<(), String> {
+  pub fn add_audio_output(&mut self, label: &str) -> Result<(), Error> {
     let abuffersink = unsafe { Filter::new_with_label(self.graph, "abuffersink", label)? };
     abuffersink.init()?;
 
@@ -123,7 +198,64 @@ impl FilterGraph {
     Ok(())
   }
 
-  pub fn add_filter(&self, args: &filter::Filter) -> Result<Filter, String> {
+  /// Declare a `buffersink` converting the frames to the pixel format of the encoder.
+  pub fn add_output_from_video_encoder(
+    &mut self,
+    label: &str,
+    video_encoder: &VideoEncoder,
+  ) -> Result<(), Error> {
+    let buffersink = unsafe { Filter::new_with_label(self.graph, "buffersink", label)? };
+
+    unsafe {
+      let pix_fmt = (*video_encoder.codec_context).pix_fmt;
+      if pix_fmt != AVPixelFormat::AV_PIX_FMT_NONE {
+        set_list_parameter(buffersink.context, "pix_fmts", &[pix_fmt])?;
+      }
+    }
+    buffersink.init()?;
+
+    self.video_outputs.push(buffersink);
+    Ok(())
+  }
+
+  /// Declare an `abuffersink` converting the frames to the sample format, rate
+  /// and channel layout of the encoder.
+  pub fn add_output_from_audio_encoder(
+    &mut self,
+    label: &str,
+    audio_encoder: &AudioEncoder,
+  ) -> Result<(), Error> {
+    let abuffersink = unsafe { Filter::new_with_label(self.graph, "abuffersink", label)? };
+
+    unsafe {
+      let codec_context = audio_encoder.codec_context;
+      if (*codec_context).sample_fmt != AVSampleFormat::AV_SAMPLE_FMT_NONE {
+        set_list_parameter(
+          abuffersink.context,
+          "sample_fmts",
+          &[(*codec_context).sample_fmt],
+        )?;
+      }
+      if (*codec_context).sample_rate > 0 {
+        set_list_parameter(
+          abuffersink.context,
+          "sample_rates",
+          &[(*codec_context).sample_rate],
+        )?;
+      }
+      let channel_layout = ChannelLayout::from_av_layout(&(*codec_context).ch_layout);
+      if channel_layout.get_nb_channels() > 0 {
+        let ch_layouts = ParameterValue::String(channel_layout.to_string());
+        ch_layouts.set("ch_layouts", abuffersink.context as *mut c_void)?;
+      }
+    }
+    abuffersink.init()?;
+
+    self.audio_outputs.push(abuffersink);
+    Ok(())
+  }
+
+  pub fn add_filter(&self, args: &filter::Filter) -> Result<Filter, Error> {
     let filter = if let Some(ref label) = args.label {
       unsafe { Filter::new_with_label(self.graph, &args.name, label)? }
     } else {
@@ -142,7 +274,7 @@ impl FilterGraph {
     src_index: u32,
     dst: &Filter,
     dst_index: u32,
-  ) -> Result<(), String> {
+  ) -> Result<(), Error> {
     unsafe {
       check_result!(avfilter_link(
         src.context,
@@ -160,7 +292,7 @@ impl FilterGraph {
     src_index: u32,
     dst: &Filter,
     dst_index: u32,
-  ) -> Result<(), String> {
+  ) -> Result<(), Error> {
     for audio_input in &self.audio_inputs {
       if audio_input.get_label() == label {
         unsafe {
@@ -189,7 +321,9 @@ impl FilterGraph {
       }
     }
 
-    Err("Unable to connect".to_string())
+    Err(Error::Configuration(format!(
+      "Unable to connect: no input labelled {label}"
+    )))
   }
 
   pub fn connect_output(
@@ -198,7 +332,7 @@ impl FilterGraph {
     src_index: u32,
     label: &str,
     dst_index: u32,
-  ) -> Result<(), String> {
+  ) -> Result<(), Error> {
     for audio_output in &self.audio_outputs {
       if audio_output.get_label() == label {
         unsafe {
@@ -227,10 +361,51 @@ impl FilterGraph {
       }
     }
 
-    Err("Unable to connect".to_string())
+    Err(Error::Configuration(format!(
+      "Unable to connect: no output labelled {label}"
+    )))
   }
 
-  pub fn validate(&mut self) -> Result<(), String> {
+  /// Parse a filtergraph description, with the `-filter_complex` syntax of ffmpeg.
+  /// Its labelled open pads are linked to the inputs and outputs with the same label.
+  pub fn parse(&mut self, description: &str) -> Result<(), Error> {
+    let description = CString::new(description).map_err(|_| {
+      Error::Configuration(format!("Invalid filter graph description: {description}"))
+    })?;
+    unsafe {
+      // the inputs of the graph feed the open inputs of the description, and conversely
+      let mut outputs = filter_in_out(self.audio_inputs.iter().chain(&self.video_inputs))?;
+      let mut inputs = match filter_in_out(self.audio_outputs.iter().chain(&self.video_outputs)) {
+        Ok(inputs) => inputs,
+        Err(error) => {
+          avfilter_inout_free(&mut outputs);
+          return Err(error);
+        }
+      };
+      let result = avfilter_graph_parse_ptr(
+        self.graph,
+        description.as_ptr(),
+        &mut inputs,
+        &mut outputs,
+        null_mut(),
+      );
+      let mut unconnected = in_out_names(inputs);
+      unconnected.extend(in_out_names(outputs));
+      avfilter_inout_free(&mut inputs);
+      avfilter_inout_free(&mut outputs);
+      check_result!(result);
+
+      if !unconnected.is_empty() {
+        return Err(Error::Configuration(format!(
+          "Unconnected filter graph pads: {}",
+          unconnected.join(", ")
+        )));
+      }
+    }
+    Ok(())
+  }
+
+  pub fn validate(&mut self) -> Result<(), Error> {
     unsafe {
       check_result!(avfilter_graph_config(self.graph, null_mut()));
       Ok(())
@@ -241,25 +416,22 @@ impl FilterGraph {
     &self,
     in_audio_frames: &[Frame],
     in_video_frames: &[Frame],
-  ) -> Result<(Vec<Frame>, Vec<Frame>), String> {
+  ) -> Result<(Vec<Frame>, Vec<Frame>), Error> {
     if in_audio_frames.len() != self.audio_inputs.len() {
-      return Err(format!(
+      return Err(Error::Configuration(format!(
         "unable to process graph, mistmatch input frames ({}) with graph inputs ({})",
         in_audio_frames.len(),
         self.audio_inputs.len()
-      ));
+      )));
     }
     if in_video_frames.len() != self.video_inputs.len() {
-      return Err(format!(
+      return Err(Error::Configuration(format!(
         "unable to process graph, mistmatch input frames ({}) with graph inputs ({})",
         in_video_frames.len(),
         self.video_inputs.len()
-      ));
+      )));
     }
 
-    let mut output_audio_frames = vec![];
-    let mut output_video_frames = vec![];
-
     unsafe {
       for (index, frame) in in_audio_frames.iter().enumerate() {
         check_result!(av_buffersrc_add_frame(
@@ -273,40 +445,130 @@ impl FilterGraph {
           frame.frame
         ));
       }
+    }
 
-      for (index, output_filter) in self.audio_outputs.iter().enumerate() {
-        let output_frame = av_frame_alloc();
-        let result = av_buffersink_get_frame(output_filter.context, output_frame);
-        if result == AVERROR(EAGAIN) || result == AVERROR_EOF {
-          break;
-        } else {
-          check_result!(result);
-        }
-        output_audio_frames.push(Frame {
-          name: Some(output_filter.get_label()),
-          frame: output_frame,
-          index,
-        });
-      }
+    self.receive_frames()
+  }
+
+  /// Push a frame into the input labelled with the frame name.
+  pub fn send_frame(&self, frame: &Frame) -> Result<(), Error> {
+    let label = frame.name.as_deref().unwrap_or_default();
+    let input = self.get_input(label)?;
+    unsafe {
+      check_result!(av_buffersrc_add_frame(input.context, frame.frame));
+    }
+    Ok(())
+  }
 
-      for (index, output_filter) in self.video_outputs.iter().enumerate() {
-        let output_frame = av_frame_alloc();
+  /// Signal the end of the stream on an input, to flush the filters buffering frames.
+  pub fn send_eof(&self, label: &str) -> Result<(), Error> {
+    let input = self.get_input(label)?;
+    unsafe {
+      check_result!(av_buffersrc_add_frame(input.context, null_mut()));
+    }
+    Ok(())
+  }
+
+  /// Pull every frame available on the outputs.
+  pub fn receive_frames(&self) -> Result<(Vec<Frame>, Vec<Frame>), Error> {
+    let output_audio_frames = receive_output_frames(&self.audio_outputs)?;
+    let output_video_frames = receive_output_frames(&self.video_outputs)?;
+    Ok((output_audio_frames, output_video_frames))
+  }
+
+  fn get_input(&self, label: &str) -> Result<&Filter, Error> {
+    self
+      .audio_inputs
+      .iter()
+      .chain(&self.video_inputs)
+      .find(|input| input.get_label() == label)
+      .ok_or_else(|| Error::Configuration(format!("No graph input labelled {label}")))
+  }
+}
+
+/// Chain the filters in an `AVFilterInOut` list, named with their labels.
+unsafe fn filter_in_out<'a>(
+  filters: impl DoubleEndedIterator<Item = &'a Filter>,
+) -> Result<*mut AVFilterInOut, Error> {
+  let mut list = null_mut();
+  for filter in filters.rev() {
+    let label = CString::new(filter.get_label()).map_err(|_| {
+      avfilter_inout_free(&mut list);
+      Error::Configuration(format!("Invalid filter label: {}", filter.get_label()))
+    })?;
+    let mut in_out = avfilter_inout_alloc();
+    if in_out.is_null() {
+      avfilter_inout_free(&mut list);
+      return Err(Error::from_av_error(AVERROR(ENOMEM)));
+    }
+    (*in_out).name = av_strdup(label.as_ptr());
+    if (*in_out).name.is_null() {
+      avfilter_inout_free(&mut in_out);
+      avfilter_inout_free(&mut list);
+      return Err(Error::from_av_error(AVERROR(ENOMEM)));
+    }
+    (*in_out).filter_ctx = filter.context;
+    (*in_out).pad_idx = 0;
+    (*in_out).next = list;
+    list = in_out;
+  }
+  Ok(list)
+}
+
+unsafe fn in_out_names(mut in_out: *mut AVFilterInOut) -> Vec<String> {
+  let mut names = vec![];
+  while !in_out.is_null() {
+    if (*in_out).name.is_null() {
+      names.push("(unlabelled)".to_string());
+    } else {
+      names.push(tools::to_string((*in_out).name));
+    }
+    in_out = (*in_out).next;
+  }
+  names
+}
+
+/// Set a binary list option, like the `av_opt_set_int_list` macro.
+unsafe fn set_list_parameter<T>(
+  context: *mut AVFilterContext,
+  key: &str,
+  values: &[T],
+) -> Result<(), Error> {
+  let key = CString::new(key).unwrap();
+  check_result!(av_opt_set_bin(
+    context as *mut c_void,
+    key.as_ptr(),
+    values.as_ptr() as *const u8,
+    (values.len() * size_of::<T>()) as i32,
+    AV_OPT_SEARCH_CHILDREN
+  ));
+  Ok(())
+}
+
+fn receive_output_frames(outputs: &[Filter]) -> Result<Vec<Frame>, Error> {
+  let mut frames = vec![];
+  for (index, output_filter) in outputs.iter().enumerate() {
+    loop {
+      unsafe {
+        let mut output_frame = av_frame_alloc();
         let result = av_buffersink_get_frame(output_filter.context, output_frame);
         if result == AVERROR(EAGAIN) || result == AVERROR_EOF {
+          av_frame_free(&mut output_frame);
           break;
-        } else {
-          check_result!(result);
         }
-        output_video_frames.push(Frame {
+        check_result!(result, {
+          av_frame_free(&mut output_frame);
+        });
+        (*output_frame).time_base = av_buffersink_get_time_base(output_filter.context);
+        frames.push(Frame {
           name: Some(output_filter.get_label()),
           frame: output_frame,
           index,
         });
       }
     }
-
-    Ok((output_audio_frames, output_video_frames))
   }
+  Ok(frames)
 }
 
 impl Drop for FilterGraph {
@@ -338,3 +600,109 @@ impl fmt::Display for FilterGraph {
     Ok(())
   }
 }
+
+#[test]
+fn filter_graph_flush() {
+  use std::collections::HashMap;
+
+  let mut graph = FilterGraph::new().unwrap();
+  let parameters = AudioInputParameters {
+    sample_rate: 48000,
+    sample_fmt: "s16".to_string(),
+    channel_layout: ChannelLayout::with_nb_channels(2),
+    time_base: None,
+  };
+  graph.add_audio_input("input", &parameters).unwrap();
+  graph.add_audio_output("output").unwrap();
+
+  let mut parameters = HashMap::new();
+  parameters.insert("nb_out_samples".to_string(), ParameterValue::Int64(1024));
+  parameters.insert("pad".to_string(), ParameterValue::Bool(false));
+  let filter = graph
+    .add_filter(&filter::Filter {
+      name: "asetnsamples".to_string(),
+      label: None,
+      parameters,
+      inputs: None,
+      outputs: None,
+    })
+    .unwrap();
+  graph.connect_input("input", 0, &filter, 0).unwrap();
+  graph.connect_output(&filter, 0, "output", 0).unwrap();
+  graph.validate().unwrap();
+
+  let samples = [0i16; 2 * 1500];
+  let mut frame = Frame::from_interleaved_samples(&samples, 2, 48000, 0).unwrap();
+  frame.name = Some("input".to_string());
+  graph.send_frame(&frame).unwrap();
+  let (frames, _) = graph.receive_frames().unwrap();
+  let nb_samples: usize = frames.iter().map(Frame::get_nb_samples).sum();
+  assert_eq!(nb_samples, 1024);
+
+  // the remaining samples are only released at the end of the stream
+  graph.send_eof("input").unwrap();
+  let (frames, _) = graph.receive_frames().unwrap();
+  let nb_samples: usize = frames.iter().map(Frame::get_nb_samples).sum();
+  assert_eq!(nb_samples, 476);
+}
+
+#[test]
+fn filter_graph_encoder_output() {
+  use crate::order::output::{OutputStream, SampleFormat};
+  use crate::stream::Disposition;
+  use std::collections::HashMap;
+
+  let mut graph = FilterGraph::new().unwrap();
+  let parameters = AudioInputParameters {
+    sample_rate: 44100,
+    sample_fmt: "flt".to_string(),
+    channel_layout: ChannelLayout::with_nb_channels(2),
+    time_base: None,
+  };
+  graph.add_audio_input("input", &parameters).unwrap();
+
+  let mut parameters = HashMap::new();
+  parameters.insert(
+    "channel_layout".to_string(),
+    ParameterValue::String("mono".to_string()),
+  );
+  let output_stream = OutputStream {
+    label: None,
+    codec: "mp2".to_string(),
+    parameters,
+    disposition: Disposition::default(),
+    language: None,
+  };
+  let encoder = AudioEncoder::new("output".to_string(), 0, &output_stream).unwrap();
+  graph
+    .add_output_from_audio_encoder("output", &encoder)
+    .unwrap();
+
+  let filter = graph
+    .add_filter(&filter::Filter {
+      name: "anull".to_string(),
+      label: None,
+      parameters: HashMap::new(),
+      inputs: None,
+      outputs: None,
+    })
+    .unwrap();
+  graph.connect_input("input", 0, &filter, 0).unwrap();
+  graph.connect_output(&filter, 0, "output", 0).unwrap();
+  graph.validate().unwrap();
+
+  let samples = [0f32; 2 * 1000];
+  let mut frame = Frame::from_interleaved_samples(&samples, 2, 44100, 0).unwrap();
+  frame.name = Some("input".to_string());
+  graph.send_frame(&frame).unwrap();
+  graph.send_eof("input").unwrap();
+
+  // the conversions are inserted by ffmpeg, without any aformat filter
+  let (frames, _) = graph.receive_frames().unwrap();
+  assert!(!frames.is_empty());
+  for frame in frames {
+    assert_eq!(frame.get_nb_channels(), 1);
+    assert_eq!(frame.get_sample_rate(), 48000);
+    assert_eq!(frame.get_sample_format(), Some(SampleFormat::Signed16));
+  }
+}
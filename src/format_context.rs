@@ -1,14 +1,38 @@
 use crate::{
-  audio_encoder::AudioEncoder, order::frame::FrameAddress, order::*, packet::Packet,
-  subtitle_encoder::SubtitleEncoder, tools, video_encoder::VideoEncoder,
+  audio_encoder::AudioEncoder, error::Error, frame::Frame, io_context::IoContext,
+  order::chapter::Chapter, order::frame::FrameAddress, order::parameters::Dictionary, order::*,
+  packet::Packet, stream::Stream, subtitle_encoder::SubtitleEncoder, tools,
+  tools::timecode::Timecode, video_encoder::VideoEncoder,
 };
 use ffmpeg_sys_next::*;
 use std::{
   collections::{BTreeMap, HashMap},
   ffi::{c_void, CString},
+  io::{Read, Seek, Write},
+  mem::size_of,
+  os::raw::c_int,
   ptr::null_mut,
+  slice,
 };
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SeekMode {
+  /// Stop on the closest keyframe at or before the target.
+  Keyframe,
+  /// Decode and discard frames from that keyframe up to the target.
+  Accurate,
+}
+
+/// A decoder of one stream, repositioned by the seek helpers of the format context.
+pub(crate) trait StreamDecoder {
+  fn get_stream_index(&self) -> isize;
+  fn decode(&self, packet: &Packet) -> Result<Vec<Frame>, Error>;
+  fn drain(&self) -> Result<Vec<Frame>, Error>;
+  fn flush(&self);
+  /// Keep frames decoded after the seek target, to return them on the next decode.
+  fn set_pending_frames(&self, frames: Vec<Frame>);
+}
+
 #[derive(Debug)]
 pub struct FormatContext {
   pub filename: String,
@@ -16,35 +40,78 @@ pub struct FormatContext {
   streams: Vec<*mut AVStream>,
   frames: Vec<FrameAddress>,
   frame_index: usize,
+  io_context: Option<IoContext>,
 }
 
 impl FormatContext {
-  pub fn new(filename: &str) -> Result<FormatContext, String> {
+  pub fn new(filename: &str) -> Result<FormatContext, Error> {
     Ok(FormatContext {
       filename: filename.to_string(),
       format_context: null_mut(),
       streams: vec![],
       frames: vec![],
       frame_index: 0,
+      io_context: None,
     })
   }
 
+  /// Demux from a reader instead of a path. The filename is only used as a format hint.
+  pub fn from_reader<R: Read + Seek + Send + 'static>(
+    filename: &str,
+    reader: R,
+  ) -> Result<FormatContext, Error> {
+    Self::with_io_context(filename, IoContext::new_reader(reader)?)
+  }
+
+  /// Mux into a writer instead of a path. The filename extension selects the muxer.
+  pub fn from_writer<W: Write + Seek + Send + 'static>(
+    filename: &str,
+    writer: W,
+  ) -> Result<FormatContext, Error> {
+    Self::with_io_context(filename, IoContext::new_writer(writer)?)
+  }
+
+  pub fn with_io_context(filename: &str, io_context: IoContext) -> Result<FormatContext, Error> {
+    let mut format_context = Self::new(filename)?;
+    format_context.io_context = Some(io_context);
+    Ok(format_context)
+  }
+
+  pub fn has_custom_io(&self) -> bool {
+    self.io_context.is_some()
+  }
+
+  fn attach_io_context(&mut self) {
+    if let Some(io_context) = &self.io_context {
+      unsafe {
+        (*self.format_context).pb = io_context.avio_context;
+        (*self.format_context).flags |= AVFMT_FLAG_CUSTOM_IO as i32;
+      }
+    }
+  }
+
   pub fn set_frames_addresses(&mut self, frames: &[FrameAddress]) {
     self.frames = frames.to_vec();
   }
 
-  pub fn open_input(&mut self) -> Result<(), String> {
+  pub fn has_frames_addresses(&self) -> bool {
+    !self.frames.is_empty()
+  }
+
+  pub fn open_input(&mut self) -> Result<(), Error> {
     unsafe {
       self.format_context = avformat_alloc_context();
+      self.attach_io_context();
       let filename = CString::new(self.filename.to_owned()).unwrap();
-      if avformat_open_input(
+      let ret = avformat_open_input(
         &mut self.format_context,
         filename.as_ptr(),
         null_mut(),
         null_mut(),
-      ) < 0
-      {
-        return Err(format!("Unable to open input file {:?}", self.filename));
+      );
+      if ret < 0 {
+        debug!("Unable to open input file {:?}", self.filename);
+        return Err(Error::from_av_error(ret));
       }
       avformat_find_stream_info(self.format_context, null_mut());
     }
@@ -57,33 +124,59 @@ impl FormatContext {
     }
   }
 
-  pub fn open_output(
-    &mut self,
-    parameters: &HashMap<String, ParameterValue>,
-  ) -> Result<(), String> {
+  pub fn open_output(&mut self) -> Result<(), Error> {
     unsafe {
       let filename = CString::new(self.filename.to_owned()).unwrap();
 
-      if avformat_alloc_output_context2(
+      let ret = avformat_alloc_output_context2(
         &mut self.format_context,
         null_mut(),
         null_mut(),
         filename.as_ptr(),
-      ) < 0
-      {
-        return Err(format!("Unable to open output file {:?}", self.filename));
+      );
+      if ret < 0 {
+        debug!("Unable to open output file {:?}", self.filename);
+        return Err(Error::from_av_error(ret));
       }
-
-      set_parameters(self.format_context as *mut c_void, parameters)?;
+      self.attach_io_context();
     }
     Ok(())
   }
 
-  pub fn add_video_stream(&mut self, encoder: &VideoEncoder) -> Result<(), String> {
+  /// Open the output file, unless writing to a custom IO, and write the header
+  /// with the muxer options. Options the muxer does not consume are an error.
+  pub fn write_header(
+    &mut self,
+    parameters: &HashMap<String, ParameterValue>,
+  ) -> Result<(), Error> {
+    let mut options = Dictionary::new(parameters)?;
+    unsafe {
+      let filename = CString::new(self.filename.to_owned()).unwrap();
+      av_dump_format(self.format_context, 0, filename.as_ptr(), 1);
+
+      if !self.has_custom_io() {
+        check_result!(avio_open(
+          &mut (*self.format_context).pb as *mut _,
+          filename.as_ptr(),
+          AVIO_FLAG_WRITE
+        ));
+      }
+      check_result!(avformat_write_header(
+        self.format_context,
+        &mut options.dictionary
+      ));
+      let format_name = tools::to_string((*(*self.format_context).oformat).name);
+      options.check_consumed(&format!("format {format_name}"))
+    }
+  }
+
+  pub fn add_video_stream(&mut self, encoder: &VideoEncoder) -> Result<(), Error> {
     unsafe {
       let av_stream = avformat_new_stream(self.format_context, null_mut());
       if av_stream.is_null() {
-        return Err("Unable to create new stream".to_owned());
+        return Err(Error::Configuration(
+          "Unable to create new stream".to_owned(),
+        ));
       }
 
       (*av_stream).id = ((*self.format_context).nb_streams - 1) as i32;
@@ -94,11 +187,13 @@ impl FormatContext {
     Ok(())
   }
 
-  pub fn add_audio_stream(&mut self, encoder: &AudioEncoder) -> Result<(), String> {
+  pub fn add_audio_stream(&mut self, encoder: &AudioEncoder) -> Result<(), Error> {
     unsafe {
       let av_stream = avformat_new_stream(self.format_context, null_mut());
       if av_stream.is_null() {
-        return Err("Unable to create new stream".to_owned());
+        return Err(Error::Configuration(
+          "Unable to create new stream".to_owned(),
+        ));
       }
 
       (*av_stream).id = ((*self.format_context).nb_streams - 1) as i32;
@@ -109,11 +204,13 @@ impl FormatContext {
     Ok(())
   }
 
-  pub fn add_subtitle_stream(&mut self, encoder: &SubtitleEncoder) -> Result<(), String> {
+  pub fn add_subtitle_stream(&mut self, encoder: &SubtitleEncoder) -> Result<(), Error> {
     unsafe {
       let av_stream = avformat_new_stream(self.format_context, null_mut());
       if av_stream.is_null() {
-        return Err("Unable to create new stream".to_owned());
+        return Err(Error::Configuration(
+          "Unable to create new stream".to_owned(),
+        ));
       }
 
       (*av_stream).id = ((*self.format_context).nb_streams - 1) as i32;
@@ -124,10 +221,224 @@ impl FormatContext {
     Ok(())
   }
 
+  /// Add a stream with the codec parameters of an input stream, to mux its packets as is.
+  pub fn add_copy_stream(&mut self, source: *const AVStream) -> Result<(), Error> {
+    unsafe {
+      let av_stream = avformat_new_stream(self.format_context, null_mut());
+      if av_stream.is_null() {
+        return Err(Error::Configuration(
+          "Unable to create new stream".to_owned(),
+        ));
+      }
+
+      (*av_stream).id = ((*self.format_context).nb_streams - 1) as i32;
+      (*av_stream).time_base = (*source).time_base;
+      (*av_stream).avg_frame_rate = (*source).avg_frame_rate;
+      (*av_stream).sample_aspect_ratio = (*source).sample_aspect_ratio;
+      check_result!(avcodec_parameters_copy(
+        (*av_stream).codecpar,
+        (*source).codecpar
+      ));
+      // the tag of the input container may not be valid in the output one
+      (*(*av_stream).codecpar).codec_tag = 0;
+      self.streams.push(av_stream);
+    }
+    Ok(())
+  }
+
   pub fn get_stream(&self, stream_index: isize) -> *mut AVStream {
     unsafe { *(*self.format_context).streams.offset(stream_index) }
   }
 
+  /// Seek to the keyframe at or before a timestamp, expressed in the time base of the stream.
+  pub fn seek(&mut self, stream_index: isize, timestamp: i64) -> Result<(), Error> {
+    if !self.frames.is_empty() {
+      return Err(Error::Configuration(
+        "Unable to seek by timestamp on frame addresses, seek by frame number".to_string(),
+      ));
+    }
+    self.check_stream_index(stream_index)?;
+    unsafe {
+      check_result!(avformat_seek_file(
+        self.format_context,
+        stream_index as i32,
+        i64::MIN,
+        timestamp,
+        timestamp,
+        0
+      ));
+    }
+    Ok(())
+  }
+
+  /// Seek to the keyframe at or before a position in seconds from the start of the file.
+  pub fn seek_to_seconds(&mut self, seconds: f64) -> Result<(), Error> {
+    if !self.frames.is_empty() {
+      return Err(Error::Configuration(
+        "Unable to seek by timestamp on frame addresses, seek by frame number".to_string(),
+      ));
+    }
+    unsafe {
+      let mut timestamp = (seconds * f64::from(AV_TIME_BASE)).round() as i64;
+      if (*self.format_context).start_time != AV_NOPTS_VALUE {
+        timestamp += (*self.format_context).start_time;
+      }
+      check_result!(avformat_seek_file(
+        self.format_context,
+        -1,
+        i64::MIN,
+        timestamp,
+        timestamp,
+        0
+      ));
+    }
+    Ok(())
+  }
+
+  /// Seek to the keyframe at or before a frame number of the stream.
+  pub fn seek_to_frame(&mut self, stream_index: isize, frame_number: i64) -> Result<(), Error> {
+    if !self.frames.is_empty() {
+      if frame_number < 0 || frame_number as usize >= self.frames.len() {
+        return Err(Error::Configuration(format!(
+          "Frame {frame_number} is out of the {} frame addresses",
+          self.frames.len()
+        )));
+      }
+      self.frame_index = frame_number as usize;
+      return Ok(());
+    }
+    let timestamp = self.get_frame_timestamp(stream_index, frame_number)?;
+    self.seek(stream_index, timestamp)
+  }
+
+  /// Convert seconds from the start of the stream into a timestamp in its time base.
+  pub fn get_seconds_timestamp(&self, stream_index: isize, seconds: f64) -> Result<i64, Error> {
+    self.check_stream_index(stream_index)?;
+    unsafe {
+      let stream = self.get_stream(stream_index);
+      let time_base = (*stream).time_base;
+      let offset = (seconds * f64::from(time_base.den) / f64::from(time_base.num)).round() as i64;
+      Ok(get_stream_start_time(stream) + offset)
+    }
+  }
+
+  /// Convert a frame number of the stream into a timestamp in its time base.
+  pub fn get_frame_timestamp(&self, stream_index: isize, frame_number: i64) -> Result<i64, Error> {
+    self.check_stream_index(stream_index)?;
+    unsafe {
+      let stream = self.get_stream(stream_index);
+      let mut frame_rate = (*stream).avg_frame_rate;
+      if frame_rate.num == 0 || frame_rate.den == 0 {
+        frame_rate = (*stream).r_frame_rate;
+      }
+      if frame_rate.num == 0 || frame_rate.den == 0 {
+        return Err(Error::Configuration(format!(
+          "Unable to seek by frame number, unknown frame rate for stream {stream_index}"
+        )));
+      }
+
+      let frame_duration = AVRational {
+        num: frame_rate.den,
+        den: frame_rate.num,
+      };
+      let offset = av_rescale_q(frame_number, frame_duration, (*stream).time_base);
+      Ok(get_stream_start_time(stream) + offset)
+    }
+  }
+
+  /// Seek a decoder to a timestamp in the stream time base and return the first decoded frame.
+  pub(crate) fn seek_decoder<D: StreamDecoder>(
+    &mut self,
+    decoder: &D,
+    timestamp: i64,
+    mode: SeekMode,
+  ) -> Result<Frame, Error> {
+    self.seek(decoder.get_stream_index(), timestamp)?;
+    decoder.flush();
+    self.decode_after_seek(decoder, timestamp, mode)
+  }
+
+  pub(crate) fn seek_decoder_to_seconds<D: StreamDecoder>(
+    &mut self,
+    decoder: &D,
+    seconds: f64,
+    mode: SeekMode,
+  ) -> Result<Frame, Error> {
+    let timestamp = self.get_seconds_timestamp(decoder.get_stream_index(), seconds)?;
+    self.seek_decoder(decoder, timestamp, mode)
+  }
+
+  pub(crate) fn seek_decoder_to_frame<D: StreamDecoder>(
+    &mut self,
+    decoder: &D,
+    frame_number: i64,
+    mode: SeekMode,
+  ) -> Result<Frame, Error> {
+    if self.has_frames_addresses() {
+      // each address holds a whole frame, nothing to decode before the target
+      self.seek_to_frame(decoder.get_stream_index(), frame_number)?;
+      decoder.flush();
+      return self.decode_after_seek(decoder, 0, SeekMode::Keyframe);
+    }
+    let timestamp = self.get_frame_timestamp(decoder.get_stream_index(), frame_number)?;
+    self.seek_decoder(decoder, timestamp, mode)
+  }
+
+  /// Read and decode packets of a stream after a seek, until the frame covering the timestamp.
+  /// The frames decoded after it are kept by the decoder.
+  fn decode_after_seek<D: StreamDecoder>(
+    &mut self,
+    decoder: &D,
+    timestamp: i64,
+    mode: SeekMode,
+  ) -> Result<Frame, Error> {
+    let stream_index = decoder.get_stream_index();
+    let time_base = unsafe { (*self.get_stream(stream_index)).time_base };
+    loop {
+      // the last frames of codecs with a decoding delay are only output when draining
+      let (frames, ended) = match self.next_packet() {
+        Ok(packet) => {
+          if packet.get_stream_index() != stream_index {
+            continue;
+          }
+          (decoder.decode(&packet)?, false)
+        }
+        Err(Error::EndOfStream) => (decoder.drain()?, true),
+        Err(error) => return Err(error),
+      };
+
+      let mut frames = frames.into_iter();
+      while let Some(frame) = frames.next() {
+        let reached = mode == SeekMode::Keyframe
+          || match frame.get_best_effort_timestamp() {
+            Some(pts) => pts + frame.get_duration(time_base).max(1) > timestamp,
+            None => true,
+          };
+        if reached {
+          decoder.set_pending_frames(frames.collect());
+          return Ok(frame);
+        }
+      }
+      if ended {
+        return Err(Error::EndOfStream);
+      }
+    }
+  }
+
+  fn check_stream_index(&self, stream_index: isize) -> Result<(), Error> {
+    if self.format_context.is_null() {
+      return Err(Error::Configuration(
+        "Unable to seek, the format is not opened".to_string(),
+      ));
+    }
+    if stream_index < 0 || stream_index as u32 >= unsafe { (*self.format_context).nb_streams } {
+      return Err(Error::Configuration(format!(
+        "Unable to seek, no stream with index {stream_index}"
+      )));
+    }
+    Ok(())
+  }
+
   pub fn get_nb_streams(&self) -> u32 {
     if !self.frames.is_empty() {
       return 1;
@@ -194,34 +505,108 @@ impl FormatContext {
   }
 
   pub fn get_metadata(&self) -> BTreeMap<String, String> {
+    unsafe { get_dictionary((*self.format_context).metadata) }
+  }
+
+  pub fn get_chapters(&self) -> Vec<Chapter> {
     unsafe {
-      let mut tag = null_mut();
-      let key = CString::new("").unwrap();
-      let mut metadata = BTreeMap::new();
-
-      loop {
-        tag = av_dict_get(
-          (*self.format_context).metadata,
-          key.as_ptr(),
-          tag,
-          AV_DICT_IGNORE_SUFFIX,
-        );
-        if tag.is_null() {
-          break;
+      if self.format_context.is_null() || (*self.format_context).chapters.is_null() {
+        return vec![];
+      }
+      let chapters = slice::from_raw_parts(
+        (*self.format_context).chapters,
+        (*self.format_context).nb_chapters as usize,
+      );
+      chapters
+        .iter()
+        .filter(|chapter| !chapter.is_null())
+        .map(|chapter| {
+          let mut metadata = get_dictionary((**chapter).metadata);
+          Chapter {
+            id: (**chapter).id,
+            start: (**chapter).start,
+            end: (**chapter).end,
+            time_base: (**chapter).time_base.into(),
+            title: metadata.remove("title"),
+            metadata,
+          }
+        })
+        .collect()
+    }
+  }
+
+  /// Add chapters to an output. They must be set before the header is written.
+  pub fn set_chapters(&mut self, chapters: &[Chapter]) -> Result<(), Error> {
+    if self.format_context.is_null() {
+      return Err(Error::Configuration(
+        "Unable to set chapters, the format is not opened".to_string(),
+      ));
+    }
+    for chapter in chapters {
+      if chapter.time_base.num <= 0 || chapter.time_base.den <= 0 || chapter.end < chapter.start {
+        return Err(Error::Configuration(format!(
+          "Invalid chapter {}: {} to {} in {} time base",
+          chapter.id, chapter.start, chapter.end, chapter.time_base
+        )));
+      }
+      let mut metadata = chapter.metadata.clone();
+      if let Some(title) = &chapter.title {
+        metadata.insert("title".to_string(), title.clone());
+      }
+
+      unsafe {
+        let mut av_chapter = av_mallocz(size_of::<AVChapter>()) as *mut AVChapter;
+        if av_chapter.is_null() {
+          return Err(Error::from_av_error(AVERROR(ENOMEM)));
+        }
+        (*av_chapter).id = chapter.id;
+        (*av_chapter).start = chapter.start;
+        (*av_chapter).end = chapter.end;
+        (*av_chapter).time_base = chapter.time_base.into();
+
+        let result = set_dictionary(&mut (*av_chapter).metadata, &metadata).and_then(|_| {
+          check_result!(av_dynarray_add_nofree(
+            &mut (*self.format_context).chapters as *mut *mut *mut AVChapter as *mut c_void,
+            &mut (*self.format_context).nb_chapters as *mut u32 as *mut c_int,
+            av_chapter as *mut c_void,
+          ));
+          Ok(())
+        });
+        if result.is_err() {
+          av_dict_free(&mut (*av_chapter).metadata);
+          av_freep(&mut av_chapter as *mut *mut AVChapter as *mut c_void);
+          return result;
         }
-        let k = tools::to_string((*tag).key);
-        let v = tools::to_string((*tag).value);
-        metadata.insert(k.to_string(), v.to_string());
       }
+    }
+    Ok(())
+  }
 
-      metadata
+  /// Timecode of the file, from the `timecode` metadata (e.g. the MXF material package)
+  /// at the rate of the first video stream, or else the timecode of the first stream having one.
+  pub fn get_timecode(&self) -> Option<Timecode> {
+    let streams: Vec<Stream> = (0..self.get_nb_streams())
+      .filter_map(|index| Stream::new(self.get_stream(index as isize)).ok())
+      .collect();
+
+    if let Some(timecode) = self.get_metadata().get("timecode") {
+      let frame_rate = streams
+        .iter()
+        .filter(|stream| unsafe {
+          (*(*stream.stream).codecpar).codec_type == AVMediaType::AVMEDIA_TYPE_VIDEO
+        })
+        .find_map(Stream::get_timecode_frame_rate);
+      if let Some(timecode) = frame_rate.and_then(|rate| Timecode::parse(timecode, rate).ok()) {
+        return Some(timecode);
+      }
     }
+    streams.iter().find_map(Stream::get_timecode)
   }
 
-  pub fn next_packet(&mut self) -> Result<Packet, String> {
+  pub fn next_packet(&mut self) -> Result<Packet, Error> {
     if !self.frames.is_empty() {
       if self.frame_index >= self.frames.len() {
-        return Err("End of data stream".to_string());
+        return Err(Error::EndOfStream);
       }
       let frame = &self.frames[self.frame_index];
       unsafe {
@@ -232,14 +617,28 @@ impl FormatContext {
           filename.as_ptr(),
           AVIO_FLAG_READ
         ));
-        if avio_seek(avio_context, frame.offset as i64, 0) < 0 {
-          println!("ERROR !");
-        };
+        let ret = avio_seek(avio_context, frame.offset as i64, libc::SEEK_SET);
+        if ret < 0 {
+          avio_closep(&mut avio_context);
+          return Err(Error::from_av_error(ret as i32));
+        }
 
-        let packet = av_packet_alloc();
-        check_result!(av_new_packet(packet, frame.size as i32));
-        check_result!(avio_read(avio_context, (*packet).data, (*packet).size));
-        check_result!(avio_close(avio_context));
+        let mut packet = av_packet_alloc();
+        if packet.is_null() {
+          avio_closep(&mut avio_context);
+          return Err(Error::from_av_error(AVERROR(ENOMEM)));
+        }
+        check_result!(av_new_packet(packet, frame.size as i32), {
+          av_packet_free(&mut packet);
+          avio_closep(&mut avio_context);
+        });
+        check_result!(avio_read(avio_context, (*packet).data, (*packet).size), {
+          av_packet_free(&mut packet);
+          avio_closep(&mut avio_context);
+        });
+        check_result!(avio_closep(&mut avio_context), {
+          av_packet_free(&mut packet);
+        });
 
         self.frame_index += 1;
 
@@ -251,9 +650,10 @@ impl FormatContext {
       let mut packet = av_packet_alloc();
       av_init_packet(packet);
 
-      if av_read_frame(self.format_context, packet) < 0 {
+      let ret = av_read_frame(self.format_context, packet);
+      if ret < 0 {
         av_packet_free(&mut packet);
-        return Err("Unable to read next packet".to_string());
+        return Err(Error::from_av_error(ret));
       }
 
       Ok(Packet { name: None, packet })
@@ -261,6 +661,14 @@ impl FormatContext {
   }
 }
 
+unsafe fn get_stream_start_time(stream: *mut AVStream) -> i64 {
+  if (*stream).start_time == AV_NOPTS_VALUE {
+    0
+  } else {
+    (*stream).start_time
+  }
+}
+
 unsafe impl Send for FormatContext {}
 
 impl From<*mut AVFormatContext> for FormatContext {
@@ -271,10 +679,40 @@ impl From<*mut AVFormatContext> for FormatContext {
       streams: vec![],
       frames: vec![],
       frame_index: 0,
+      io_context: None,
     }
   }
 }
 
+unsafe fn get_dictionary(dictionary: *const AVDictionary) -> BTreeMap<String, String> {
+  let mut tag = null_mut();
+  let key = CString::new("").unwrap();
+  let mut entries = BTreeMap::new();
+
+  loop {
+    tag = av_dict_get(dictionary, key.as_ptr(), tag, AV_DICT_IGNORE_SUFFIX);
+    if tag.is_null() {
+      break;
+    }
+    entries.insert(tools::to_string((*tag).key), tools::to_string((*tag).value));
+  }
+  entries
+}
+
+unsafe fn set_dictionary(
+  dictionary: *mut *mut AVDictionary,
+  entries: &BTreeMap<String, String>,
+) -> Result<(), Error> {
+  for (key, value) in entries {
+    let key = CString::new(key.as_str())
+      .map_err(|_| Error::Configuration(format!("Invalid metadata key: {key:?}")))?;
+    let value = CString::new(value.as_str())
+      .map_err(|_| Error::Configuration(format!("Invalid metadata value: {value:?}")))?;
+    check_result!(av_dict_set(dictionary, key.as_ptr(), value.as_ptr(), 0));
+  }
+  Ok(())
+}
+
 impl Drop for FormatContext {
   fn drop(&mut self) {
     unsafe {
@@ -1,6 +1,20 @@
-use crate::tools;
-use ffmpeg_sys_next::{av_dict_get, av_frame_free, AVFrame};
-use std::{ffi::CString, ptr::null_mut};
+use crate::{
+  channel_layout::ChannelLayout,
+  error::Error,
+  hdr::{ContentLightLevel, HdrMetadata, MasteringDisplay},
+  order::output::SampleFormat,
+  tools,
+  tools::{rational::Rational, timecode::Timecode},
+};
+use ffmpeg_sys_next::*;
+use std::{
+  convert::{TryFrom, TryInto},
+  ffi::CString,
+  fmt,
+  mem::size_of,
+  ptr::null_mut,
+  slice,
+};
 
 pub struct Frame {
   pub name: Option<String>,
@@ -8,7 +22,282 @@ pub struct Frame {
   pub index: usize,
 }
 
+/// A borrowed view on one plane of a video frame.
+///
+/// `stride` is expressed in elements of `T`, so row `y` starts at `data[y * stride]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Plane<'a, T> {
+  pub data: &'a [T],
+  pub stride: usize,
+  pub width: usize,
+  pub height: usize,
+}
+
+impl<'a, T> Plane<'a, T> {
+  pub fn row(&self, y: usize) -> Option<&'a [T]> {
+    if y >= self.height {
+      return None;
+    }
+    let start = y * self.stride;
+    self.data.get(start..start + self.width.min(self.stride))
+  }
+}
+
+mod private {
+  /// Restricts `Sample` to the primitive types matching an ffmpeg sample format.
+  pub trait Sealed {}
+
+  impl Sealed for u8 {}
+  impl Sealed for i16 {}
+  impl Sealed for i32 {}
+  impl Sealed for i64 {}
+  impl Sealed for f32 {}
+  impl Sealed for f64 {}
+}
+
+/// Sample types that can be read from an audio frame.
+///
+/// Sealed: the frame data is reinterpreted as `T`, so its size must match the formats.
+pub trait Sample: Copy + private::Sealed {
+  fn packed_format() -> AVSampleFormat;
+  fn planar_format() -> AVSampleFormat;
+}
+
+impl Sample for u8 {
+  fn packed_format() -> AVSampleFormat {
+    AVSampleFormat::AV_SAMPLE_FMT_U8
+  }
+  fn planar_format() -> AVSampleFormat {
+    AVSampleFormat::AV_SAMPLE_FMT_U8P
+  }
+}
+
+impl Sample for i16 {
+  fn packed_format() -> AVSampleFormat {
+    AVSampleFormat::AV_SAMPLE_FMT_S16
+  }
+  fn planar_format() -> AVSampleFormat {
+    AVSampleFormat::AV_SAMPLE_FMT_S16P
+  }
+}
+
+impl Sample for i32 {
+  fn packed_format() -> AVSampleFormat {
+    AVSampleFormat::AV_SAMPLE_FMT_S32
+  }
+  fn planar_format() -> AVSampleFormat {
+    AVSampleFormat::AV_SAMPLE_FMT_S32P
+  }
+}
+
+impl Sample for i64 {
+  fn packed_format() -> AVSampleFormat {
+    AVSampleFormat::AV_SAMPLE_FMT_S64
+  }
+  fn planar_format() -> AVSampleFormat {
+    AVSampleFormat::AV_SAMPLE_FMT_S64P
+  }
+}
+
+impl Sample for f32 {
+  fn packed_format() -> AVSampleFormat {
+    AVSampleFormat::AV_SAMPLE_FMT_FLT
+  }
+  fn planar_format() -> AVSampleFormat {
+    AVSampleFormat::AV_SAMPLE_FMT_FLTP
+  }
+}
+
+impl Sample for f64 {
+  fn packed_format() -> AVSampleFormat {
+    AVSampleFormat::AV_SAMPLE_FMT_DBL
+  }
+  fn planar_format() -> AVSampleFormat {
+    AVSampleFormat::AV_SAMPLE_FMT_DBLP
+  }
+}
+
 impl Frame {
+  /// Build a video frame by copying a picture buffer whose planes are stored
+  /// one after another without padding (the layout of `av_image_copy_to_buffer` with align 1).
+  pub fn from_video_buffer(
+    width: i32,
+    height: i32,
+    pix_fmt: AVPixelFormat,
+    buffer: &[u8],
+    pts: i64,
+  ) -> Result<Self, Error> {
+    unsafe {
+      let expected_size = av_image_get_buffer_size(pix_fmt, width, height, 1);
+      check_result!(expected_size);
+      if buffer.len() != expected_size as usize {
+        return Err(Error::InvalidData(format!(
+          "picture buffer holds {} bytes, {} expected for {}x{} {}",
+          buffer.len(),
+          expected_size,
+          width,
+          height,
+          tools::to_string(av_get_pix_fmt_name(pix_fmt))
+        )));
+      }
+
+      let frame = Frame::allocate()?;
+      (*frame.frame).width = width;
+      (*frame.frame).height = height;
+      (*frame.frame).format = pix_fmt as i32;
+      (*frame.frame).pts = pts;
+      check_result!(av_frame_get_buffer(frame.frame, 0));
+
+      let mut src_data = [std::ptr::null_mut(); 4];
+      let mut src_linesize = [0; 4];
+      check_result!(av_image_fill_arrays(
+        src_data.as_mut_ptr(),
+        src_linesize.as_mut_ptr(),
+        buffer.as_ptr(),
+        pix_fmt,
+        width,
+        height,
+        1
+      ));
+      av_image_copy(
+        (*frame.frame).data.as_mut_ptr(),
+        (*frame.frame).linesize.as_mut_ptr(),
+        src_data.as_mut_ptr() as *mut *const u8,
+        src_linesize.as_ptr(),
+        pix_fmt,
+        width,
+        height,
+      );
+
+      Ok(frame)
+    }
+  }
+
+  /// Build a packed audio frame from samples interleaved by channel.
+  pub fn from_interleaved_samples<T: Sample>(
+    samples: &[T],
+    channels: i32,
+    sample_rate: i32,
+    pts: i64,
+  ) -> Result<Self, Error> {
+    if channels <= 0 || samples.len() % channels as usize != 0 {
+      return Err(Error::InvalidData(format!(
+        "{} samples cannot be split into {} channels",
+        samples.len(),
+        channels
+      )));
+    }
+    let nb_samples = samples.len() / channels as usize;
+
+    unsafe {
+      let frame =
+        Frame::allocate_audio(T::packed_format(), channels, sample_rate, nb_samples, pts)?;
+      debug_assert_eq!(
+        size_of::<T>(),
+        av_get_bytes_per_sample(T::packed_format()) as usize
+      );
+      std::ptr::copy_nonoverlapping(
+        samples.as_ptr(),
+        *(*frame.frame).extended_data as *mut T,
+        samples.len(),
+      );
+      Ok(frame)
+    }
+  }
+
+  /// Build a planar audio frame, one slice of samples per channel.
+  pub fn from_planar_samples<T: Sample>(
+    channels: &[&[T]],
+    sample_rate: i32,
+    pts: i64,
+  ) -> Result<Self, Error> {
+    let nb_samples = channels.first().map(|channel| channel.len()).unwrap_or(0);
+    if channels.is_empty() || channels.iter().any(|channel| channel.len() != nb_samples) {
+      return Err(Error::InvalidData(
+        "planar channels must be non empty and hold the same number of samples".to_string(),
+      ));
+    }
+
+    unsafe {
+      let frame = Frame::allocate_audio(
+        T::planar_format(),
+        channels.len() as i32,
+        sample_rate,
+        nb_samples,
+        pts,
+      )?;
+      debug_assert_eq!(
+        size_of::<T>(),
+        av_get_bytes_per_sample(T::planar_format()) as usize
+      );
+      for (index, channel) in channels.iter().enumerate() {
+        std::ptr::copy_nonoverlapping(
+          channel.as_ptr(),
+          *(*frame.frame).extended_data.add(index) as *mut T,
+          nb_samples,
+        );
+      }
+      Ok(frame)
+    }
+  }
+
+  fn allocate() -> Result<Self, Error> {
+    let frame = unsafe { av_frame_alloc() };
+    if frame.is_null() {
+      return Err(Error::FFmpeg {
+        code: AVERROR(ENOMEM),
+        message: "Unable to allocate frame".to_string(),
+      });
+    }
+    Ok(Frame {
+      name: None,
+      frame,
+      index: 0,
+    })
+  }
+
+  /// Receive every frame available from a decoder, until it needs more input
+  /// or reaches the end of the stream.
+  pub(crate) unsafe fn receive_all(
+    codec_context: *mut AVCodecContext,
+    name: &str,
+    index: usize,
+  ) -> Result<Vec<Frame>, Error> {
+    let mut frames = vec![];
+    loop {
+      let mut frame = Frame::allocate()?;
+      let result = avcodec_receive_frame(codec_context, frame.frame);
+      if result == AVERROR(EAGAIN) || result == AVERROR_EOF {
+        return Ok(frames);
+      }
+      check_result!(result);
+
+      if (*frame.frame).pts == AV_NOPTS_VALUE {
+        (*frame.frame).pts = (*frame.frame).best_effort_timestamp;
+      }
+      frame.name = Some(name.to_string());
+      frame.index = index;
+      frames.push(frame);
+    }
+  }
+
+  unsafe fn allocate_audio(
+    sample_fmt: AVSampleFormat,
+    channels: i32,
+    sample_rate: i32,
+    nb_samples: usize,
+    pts: i64,
+  ) -> Result<Self, Error> {
+    let frame = Frame::allocate()?;
+    (*frame.frame).format = sample_fmt as i32;
+    av_channel_layout_default(&mut (*frame.frame).ch_layout, channels);
+    (*frame.frame).sample_rate = sample_rate;
+    (*frame.frame).nb_samples = nb_samples as i32;
+    (*frame.frame).pts = pts;
+    check_result!(av_frame_get_buffer(frame.frame, 0));
+    Ok(frame)
+  }
+
   pub fn get_metadata(&self, key: &str) -> Option<String> {
     unsafe {
       let metadata = (*self.frame).metadata;
@@ -21,9 +310,291 @@ impl Frame {
     }
   }
 
+  /// Timecode attached by the decoder, from S12M or MPEG-2 GOP side data.
+  pub fn get_timecode(&self, frame_rate: Rational) -> Option<Timecode> {
+    if let Some(data) = self.get_side_data(AVFrameSideDataType::AV_FRAME_DATA_S12M_TIMECODE) {
+      if let Some(timecode) = Timecode::from_s12m_side_data(data, frame_rate)
+        .into_iter()
+        .next()
+      {
+        return Some(timecode);
+      }
+    }
+
+    let data = self.get_side_data(AVFrameSideDataType::AV_FRAME_DATA_GOP_TIMECODE)?;
+    let gop_timecode = i64::from_ne_bytes(data.get(0..8)?.try_into().ok()?);
+    Timecode::from_gop(gop_timecode as u32, frame_rate).ok()
+  }
+
+  /// HDR metadata attached by the decoder, e.g. from HEVC SEI messages.
+  pub fn get_hdr_metadata(&self) -> HdrMetadata {
+    let mut metadata = HdrMetadata::default();
+    if let Some(data) =
+      self.get_side_data(AVFrameSideDataType::AV_FRAME_DATA_MASTERING_DISPLAY_METADATA)
+    {
+      if data.len() >= size_of::<AVMasteringDisplayMetadata>() {
+        metadata.mastering_display = Some(unsafe {
+          MasteringDisplay::from_av_metadata(data.as_ptr() as *const AVMasteringDisplayMetadata)
+        });
+      }
+    }
+    metadata.content_light_level = self
+      .get_side_data(AVFrameSideDataType::AV_FRAME_DATA_CONTENT_LIGHT_LEVEL)
+      .and_then(ContentLightLevel::from_bytes);
+    metadata.dolby_vision_rpu = self
+      .get_side_data(AVFrameSideDataType::AV_FRAME_DATA_DOVI_RPU_BUFFER)
+      .is_some();
+    metadata.hdr10_plus = self
+      .get_side_data(AVFrameSideDataType::AV_FRAME_DATA_DYNAMIC_HDR_PLUS)
+      .is_some();
+    metadata
+  }
+
+  pub fn get_side_data(&self, kind: AVFrameSideDataType) -> Option<&[u8]> {
+    unsafe {
+      let side_data = av_frame_get_side_data(self.frame, kind);
+      if side_data.is_null() || (*side_data).data.is_null() {
+        return None;
+      }
+      Some(slice::from_raw_parts((*side_data).data, (*side_data).size))
+    }
+  }
+
   pub fn get_pts(&self) -> i64 {
     unsafe { (*self.frame).pts }
   }
+
+  /// Time base of the timestamps, set on the frames received from a filter graph.
+  pub fn get_time_base(&self) -> Option<Rational> {
+    unsafe {
+      let time_base = (*self.frame).time_base;
+      if time_base.num > 0 && time_base.den > 0 {
+        Some(time_base.into())
+      } else {
+        None
+      }
+    }
+  }
+
+  /// Timestamp estimated by the decoder, in the stream time base.
+  pub fn get_best_effort_timestamp(&self) -> Option<i64> {
+    unsafe {
+      if (*self.frame).best_effort_timestamp == AV_NOPTS_VALUE {
+        None
+      } else {
+        Some((*self.frame).best_effort_timestamp)
+      }
+    }
+  }
+
+  /// Duration of a decoded frame in the time base of its stream, 0 when unknown.
+  pub fn get_duration(&self, time_base: AVRational) -> i64 {
+    unsafe {
+      if (*self.frame).nb_samples > 0 && (*self.frame).sample_rate > 0 {
+        let sample_time_base = AVRational {
+          num: 1,
+          den: (*self.frame).sample_rate,
+        };
+        av_rescale_q(
+          i64::from((*self.frame).nb_samples),
+          sample_time_base,
+          time_base,
+        )
+      } else {
+        (*self.frame).pkt_duration
+      }
+    }
+  }
+
+  pub fn get_width(&self) -> i32 {
+    unsafe { (*self.frame).width }
+  }
+
+  pub fn get_height(&self) -> i32 {
+    unsafe { (*self.frame).height }
+  }
+
+  pub fn get_pixel_format(&self) -> Option<AVPixelFormat> {
+    unsafe {
+      if self.get_pixel_format_descriptor().is_null() {
+        return None;
+      }
+      Some(std::mem::transmute::<i32, AVPixelFormat>(
+        (*self.frame).format,
+      ))
+    }
+  }
+
+  pub fn get_pix_fmt_name(&self) -> Option<String> {
+    self
+      .get_pixel_format()
+      .map(|format| unsafe { tools::to_string(av_get_pix_fmt_name(format)) })
+  }
+
+  /// Number of data planes of a video frame, 0 for hardware or unknown formats.
+  pub fn get_nb_planes(&self) -> usize {
+    match self.get_pixel_format() {
+      Some(format) if !self.is_hardware_frame() => unsafe {
+        av_pix_fmt_count_planes(format).max(0) as usize
+      },
+      _ => 0,
+    }
+  }
+
+  /// Borrow a plane of a video frame as bytes.
+  pub fn get_plane(&self, index: usize) -> Option<Plane<'_, u8>> {
+    self.get_plane_of::<u8>(index)
+  }
+
+  /// Borrow a plane of a high bit depth video frame (e.g. `yuv422p10le`) as 16-bit words.
+  ///
+  /// Returns `None` for formats of 8 bits or less per component.
+  pub fn get_plane_u16(&self, index: usize) -> Option<Plane<'_, u16>> {
+    let descriptor = self.get_pixel_format_descriptor();
+    if descriptor.is_null() || unsafe { (*descriptor).comp[0].depth } <= 8 {
+      return None;
+    }
+    self.get_plane_of::<u16>(index)
+  }
+
+  pub fn get_nb_samples(&self) -> usize {
+    unsafe { (*self.frame).nb_samples.max(0) as usize }
+  }
+
+  pub fn get_sample_rate(&self) -> i32 {
+    unsafe { (*self.frame).sample_rate }
+  }
+
+  pub fn get_nb_channels(&self) -> usize {
+    unsafe { (*self.frame).ch_layout.nb_channels.max(0) as usize }
+  }
+
+  pub fn get_channel_layout(&self) -> ChannelLayout {
+    unsafe { ChannelLayout::from_av_layout(&(*self.frame).ch_layout) }
+  }
+
+  pub fn get_sample_format(&self) -> Option<SampleFormat> {
+    SampleFormat::try_from(self.get_av_sample_format() as i32).ok()
+  }
+
+  pub fn is_planar(&self) -> bool {
+    unsafe { av_sample_fmt_is_planar(self.get_av_sample_format()) == 1 }
+  }
+
+  /// Borrow the samples of one channel of a planar audio frame.
+  ///
+  /// Returns `None` if the frame is packed or if `T` does not match its sample format.
+  pub fn get_channel_samples<T: Sample>(&self, channel: usize) -> Option<&[T]> {
+    if self.get_av_sample_format() != T::planar_format() || channel >= self.get_nb_channels() {
+      return None;
+    }
+    unsafe {
+      let data = *(*self.frame).extended_data.add(channel);
+      if data.is_null() {
+        return None;
+      }
+      Some(slice::from_raw_parts(
+        data as *const T,
+        self.get_nb_samples(),
+      ))
+    }
+  }
+
+  /// Borrow the samples of a packed audio frame, interleaved by channel.
+  ///
+  /// Returns `None` if the frame is planar or if `T` does not match its sample format.
+  pub fn get_interleaved_samples<T: Sample>(&self) -> Option<&[T]> {
+    if self.get_av_sample_format() != T::packed_format() {
+      return None;
+    }
+    unsafe {
+      let data = *(*self.frame).extended_data;
+      if data.is_null() {
+        return None;
+      }
+      Some(slice::from_raw_parts(
+        data as *const T,
+        self.get_nb_samples() * self.get_nb_channels(),
+      ))
+    }
+  }
+
+  fn get_av_sample_format(&self) -> AVSampleFormat {
+    unsafe {
+      if self.frame.is_null() || (*self.frame).nb_samples == 0 {
+        return AVSampleFormat::AV_SAMPLE_FMT_NONE;
+      }
+      std::mem::transmute::<i32, AVSampleFormat>((*self.frame).format)
+    }
+  }
+
+  fn get_pixel_format_descriptor(&self) -> *const AVPixFmtDescriptor {
+    unsafe {
+      if self.frame.is_null() || (*self.frame).width == 0 || (*self.frame).format < 0 {
+        return std::ptr::null();
+      }
+      av_pix_fmt_desc_get(std::mem::transmute::<i32, AVPixelFormat>(
+        (*self.frame).format,
+      ))
+    }
+  }
+
+  fn is_hardware_frame(&self) -> bool {
+    unsafe {
+      let descriptor = self.get_pixel_format_descriptor();
+      descriptor.is_null() || (*descriptor).flags & AV_PIX_FMT_FLAG_HWACCEL as u64 != 0
+    }
+  }
+
+  fn get_plane_of<T>(&self, index: usize) -> Option<Plane<'_, T>> {
+    if index >= self.get_nb_planes() {
+      return None;
+    }
+    unsafe {
+      let descriptor = self.get_pixel_format_descriptor();
+      let data = (*self.frame).data[index];
+      let linesize = (*self.frame).linesize[index];
+      if data.is_null() || linesize <= 0 {
+        return None;
+      }
+
+      let (log2_chroma_w, log2_chroma_h) = if index == 1 || index == 2 {
+        (
+          (*descriptor).log2_chroma_w as i32,
+          (*descriptor).log2_chroma_h as i32,
+        )
+      } else {
+        (0, 0)
+      };
+
+      let element_size = std::mem::size_of::<T>();
+      let stride = linesize as usize / element_size;
+      let width = (-((-(*self.frame).width) >> log2_chroma_w)) as usize;
+      let height = (-((-(*self.frame).height) >> log2_chroma_h)) as usize;
+
+      let mut max_pixel_step = [0; 4];
+      av_image_fill_max_pixsteps(max_pixel_step.as_mut_ptr(), null_mut(), descriptor);
+      let bytes_per_pixel = max_pixel_step[index].max(1) as usize;
+      let width = (width * bytes_per_pixel / element_size).min(stride);
+
+      Some(Plane {
+        data: slice::from_raw_parts(data as *const T, stride * height),
+        stride,
+        width,
+        height,
+      })
+    }
+  }
+}
+
+impl fmt::Debug for Frame {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Frame")
+      .field("name", &self.name)
+      .field("index", &self.index)
+      .field("pts", &(!self.frame.is_null()).then(|| self.get_pts()))
+      .finish()
+  }
 }
 
 impl Drop for Frame {
@@ -35,3 +606,63 @@ impl Drop for Frame {
     }
   }
 }
+
+#[test]
+fn frame_planes() {
+  unsafe {
+    let frame = av_frame_alloc();
+    (*frame).width = 64;
+    (*frame).height = 48;
+    (*frame).format = AVPixelFormat::AV_PIX_FMT_YUV420P10LE as i32;
+    assert_eq!(av_frame_get_buffer(frame, 0), 0);
+
+    let frame = Frame {
+      name: None,
+      frame,
+      index: 0,
+    };
+
+    assert_eq!(frame.get_nb_planes(), 3);
+    assert_eq!(frame.get_pix_fmt_name(), Some("yuv420p10le".to_string()));
+
+    let luma = frame.get_plane_u16(0).unwrap();
+    assert_eq!(luma.width, 64);
+    assert_eq!(luma.height, 48);
+    assert_eq!(luma.row(0).unwrap().len(), 64);
+
+    let chroma = frame.get_plane_u16(1).unwrap();
+    assert_eq!(chroma.width, 32);
+    assert_eq!(chroma.height, 24);
+    assert!(frame.get_plane(3).is_none());
+    assert!(frame.get_interleaved_samples::<i16>().is_none());
+  }
+}
+
+#[test]
+fn frame_from_buffers() {
+  let buffer: Vec<u8> = (0..(4 * 2 * 3)).map(|value| value as u8).collect();
+  let frame = Frame::from_video_buffer(4, 2, AVPixelFormat::AV_PIX_FMT_RGB24, &buffer, 12).unwrap();
+  assert_eq!(frame.get_pts(), 12);
+  let plane = frame.get_plane(0).unwrap();
+  assert_eq!(plane.row(1).unwrap(), &buffer[12..24]);
+  assert!(frame.get_plane_u16(0).is_none());
+  assert!(
+    Frame::from_video_buffer(4, 2, AVPixelFormat::AV_PIX_FMT_RGB24, &buffer[1..], 0).is_err()
+  );
+
+  let samples = [0i16, 1, 2, 3, 4, 5];
+  let frame = Frame::from_interleaved_samples(&samples, 2, 48000, 0).unwrap();
+  assert_eq!(frame.get_nb_samples(), 3);
+  assert_eq!(frame.get_interleaved_samples::<i16>().unwrap(), &samples);
+
+  let left = [0.5f32, 0.25];
+  let right = [-0.5f32, -0.25];
+  let frame = Frame::from_planar_samples(&[&left, &right], 48000, 0).unwrap();
+  assert!(frame.is_planar());
+  assert_eq!(frame.get_channel_samples::<f32>(1).unwrap(), &right);
+  assert!(frame.get_channel_samples::<i32>(1).is_none());
+
+  let samples = [i64::MIN, i64::MAX];
+  let frame = Frame::from_interleaved_samples(&samples, 1, 48000, 0).unwrap();
+  assert_eq!(frame.get_interleaved_samples::<i64>().unwrap(), &samples);
+}
@@ -0,0 +1,239 @@
+use crate::{error::Error, tools::rational::Rational};
+use ffmpeg_sys_next::*;
+use std::{convert::TryFrom, fmt, str::FromStr};
+
+/// Mastering display color volume (SMPTE ST 2086).
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Serialize)]
+pub struct MasteringDisplay {
+  /// CIE 1931 xy chromaticities of the red, green and blue primaries.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub primaries: Option<[[Rational; 2]; 3]>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub white_point: Option<[Rational; 2]>,
+  /// Luminance in cd/m².
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub min_luminance: Option<Rational>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub max_luminance: Option<Rational>,
+}
+
+impl MasteringDisplay {
+  /// # Safety
+  /// `metadata` must point to a valid `AVMasteringDisplayMetadata`.
+  pub unsafe fn from_av_metadata(metadata: *const AVMasteringDisplayMetadata) -> Self {
+    let metadata = &*metadata;
+    let mut mastering_display = MasteringDisplay::default();
+    if metadata.has_primaries != 0 {
+      mastering_display.primaries = Some(
+        metadata
+          .display_primaries
+          .map(|[x, y]| [Rational::from(x), Rational::from(y)]),
+      );
+      mastering_display.white_point = Some([
+        metadata.white_point[0].into(),
+        metadata.white_point[1].into(),
+      ]);
+    }
+    if metadata.has_luminance != 0 {
+      mastering_display.min_luminance = Some(metadata.min_luminance.into());
+      mastering_display.max_luminance = Some(metadata.max_luminance.into());
+    }
+    mastering_display
+  }
+}
+
+/// Content light level (CEA-861.3), in cd/m².
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq, Serialize)]
+pub struct ContentLightLevel {
+  pub max_cll: u32,
+  pub max_fall: u32,
+}
+
+impl ContentLightLevel {
+  /// Read the `MaxCLL` and `MaxFALL` fields of an `AVContentLightMetadata`.
+  pub fn from_bytes(data: &[u8]) -> Option<Self> {
+    let max_cll = data.get(0..4)?;
+    let max_fall = data.get(4..8)?;
+    Some(ContentLightLevel {
+      max_cll: u32::from_ne_bytes([max_cll[0], max_cll[1], max_cll[2], max_cll[3]]),
+      max_fall: u32::from_ne_bytes([max_fall[0], max_fall[1], max_fall[2], max_fall[3]]),
+    })
+  }
+}
+
+/// Dolby Vision decoder configuration record.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq, Serialize)]
+pub struct DolbyVisionConfiguration {
+  pub version_major: u8,
+  pub version_minor: u8,
+  pub profile: u8,
+  pub level: u8,
+  pub rpu_present: bool,
+  pub el_present: bool,
+  pub bl_present: bool,
+  pub bl_signal_compatibility_id: u8,
+}
+
+impl DolbyVisionConfiguration {
+  /// Read the leading `uint8_t` fields of an `AVDOVIDecoderConfigurationRecord`.
+  pub fn from_bytes(data: &[u8]) -> Option<Self> {
+    let fields = data.get(0..8)?;
+    Some(DolbyVisionConfiguration {
+      version_major: fields[0],
+      version_minor: fields[1],
+      profile: fields[2],
+      level: fields[3],
+      rpu_present: fields[4] != 0,
+      el_present: fields[5] != 0,
+      bl_present: fields[6] != 0,
+      bl_signal_compatibility_id: fields[7],
+    })
+  }
+}
+
+/// HDR side data of a stream, or of its first frame.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Serialize)]
+pub struct HdrMetadata {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub mastering_display: Option<MasteringDisplay>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub content_light_level: Option<ContentLightLevel>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub dolby_vision: Option<DolbyVisionConfiguration>,
+  /// Dolby Vision RPU found in the frames, without a configuration record.
+  #[serde(default, skip_serializing_if = "is_false")]
+  pub dolby_vision_rpu: bool,
+  /// SMPTE ST 2094-40 dynamic metadata.
+  #[serde(default, skip_serializing_if = "is_false")]
+  pub hdr10_plus: bool,
+}
+
+impl HdrMetadata {
+  pub fn is_empty(&self) -> bool {
+    *self == HdrMetadata::default()
+  }
+
+  /// Fill the missing fields from other metadata, e.g. from the first decoded frame.
+  pub fn merge(&mut self, other: HdrMetadata) {
+    self.mastering_display = self.mastering_display.or(other.mastering_display);
+    self.content_light_level = self.content_light_level.or(other.content_light_level);
+    self.dolby_vision = self.dolby_vision.or(other.dolby_vision);
+    self.dolby_vision_rpu |= other.dolby_vision_rpu;
+    self.hdr10_plus |= other.hdr10_plus;
+  }
+
+  pub fn get_format(&self, color_trc: AVColorTransferCharacteristic) -> HdrFormat {
+    if let Some(dolby_vision) = self.dolby_vision {
+      return HdrFormat::DolbyVision(Some(dolby_vision.profile));
+    }
+    if self.dolby_vision_rpu {
+      return HdrFormat::DolbyVision(None);
+    }
+    match color_trc {
+      AVColorTransferCharacteristic::AVCOL_TRC_SMPTE2084 if self.hdr10_plus => HdrFormat::Hdr10Plus,
+      AVColorTransferCharacteristic::AVCOL_TRC_SMPTE2084 => HdrFormat::Hdr10,
+      AVColorTransferCharacteristic::AVCOL_TRC_ARIB_STD_B67 => HdrFormat::Hlg,
+      _ => HdrFormat::Sdr,
+    }
+  }
+}
+
+fn is_false(value: &bool) -> bool {
+  !*value
+}
+
+/// Dynamic range of a video stream, serialized as "SDR", "HDR10", "HDR10+", "HLG"
+/// or "Dolby Vision profile X".
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum HdrFormat {
+  Sdr,
+  Hdr10,
+  Hdr10Plus,
+  Hlg,
+  DolbyVision(Option<u8>),
+}
+
+impl fmt::Display for HdrFormat {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      HdrFormat::Sdr => write!(f, "SDR"),
+      HdrFormat::Hdr10 => write!(f, "HDR10"),
+      HdrFormat::Hdr10Plus => write!(f, "HDR10+"),
+      HdrFormat::Hlg => write!(f, "HLG"),
+      HdrFormat::DolbyVision(Some(profile)) => write!(f, "Dolby Vision profile {profile}"),
+      HdrFormat::DolbyVision(None) => write!(f, "Dolby Vision"),
+    }
+  }
+}
+
+impl FromStr for HdrFormat {
+  type Err = Error;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    match value {
+      "SDR" => Ok(HdrFormat::Sdr),
+      "HDR10" => Ok(HdrFormat::Hdr10),
+      "HDR10+" => Ok(HdrFormat::Hdr10Plus),
+      "HLG" => Ok(HdrFormat::Hlg),
+      "Dolby Vision" => Ok(HdrFormat::DolbyVision(None)),
+      _ => value
+        .strip_prefix("Dolby Vision profile ")
+        .and_then(|profile| profile.parse().ok())
+        .map(|profile| HdrFormat::DolbyVision(Some(profile)))
+        .ok_or_else(|| Error::Configuration(format!("'{value}' is not a valid HDR format"))),
+    }
+  }
+}
+
+impl From<HdrFormat> for String {
+  fn from(format: HdrFormat) -> String {
+    format.to_string()
+  }
+}
+
+impl TryFrom<String> for HdrFormat {
+  type Error = Error;
+
+  fn try_from(value: String) -> Result<Self, Self::Error> {
+    value.parse()
+  }
+}
+
+#[test]
+fn hdr_format() {
+  let mut metadata = HdrMetadata {
+    content_light_level: ContentLightLevel::from_bytes(&[0xe8, 0x03, 0, 0, 0x90, 0x01, 0, 0]),
+    ..Default::default()
+  };
+  assert_eq!(
+    metadata.content_light_level,
+    Some(ContentLightLevel {
+      max_cll: 1000,
+      max_fall: 400
+    })
+  );
+  assert_eq!(
+    metadata.get_format(AVColorTransferCharacteristic::AVCOL_TRC_SMPTE2084),
+    HdrFormat::Hdr10
+  );
+  assert_eq!(
+    metadata.get_format(AVColorTransferCharacteristic::AVCOL_TRC_BT709),
+    HdrFormat::Sdr
+  );
+
+  metadata.merge(HdrMetadata {
+    dolby_vision: DolbyVisionConfiguration::from_bytes(&[1, 0, 8, 6, 1, 0, 1, 1]),
+    ..Default::default()
+  });
+  let format = metadata.get_format(AVColorTransferCharacteristic::AVCOL_TRC_SMPTE2084);
+  assert_eq!(format.to_string(), "Dolby Vision profile 8");
+  assert_eq!(
+    "Dolby Vision profile 8".parse::<HdrFormat>().unwrap(),
+    format
+  );
+  assert_eq!(
+    serde_json::to_string(&HdrFormat::Hdr10Plus).unwrap(),
+    "\"HDR10+\""
+  );
+}
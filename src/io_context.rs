@@ -0,0 +1,270 @@
+use crate::error::Error;
+use ffmpeg_sys_next::*;
+use std::{
+  ffi::c_void,
+  fmt,
+  io::{Read, Seek, SeekFrom, Write},
+  os::raw::c_int,
+  sync::{Arc, Mutex},
+};
+
+const BUFFER_SIZE: usize = 32 * 1024;
+
+pub trait ReadSeek: Read + Seek + Send {}
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+pub trait WriteSeek: Write + Seek + Send {}
+impl<T: Write + Seek + Send> WriteSeek for T {}
+
+enum Stream {
+  Reader(Box<dyn ReadSeek>),
+  Writer(Box<dyn WriteSeek>),
+}
+
+/// AVIOContext backed by a Rust reader or writer, to demux or mux without a path.
+pub struct IoContext {
+  pub avio_context: *mut AVIOContext,
+  stream: *mut Stream,
+}
+
+impl IoContext {
+  pub fn new_reader<R: Read + Seek + Send + 'static>(mut reader: R) -> Result<Self, Error> {
+    reader.seek(SeekFrom::Start(0))?;
+    Self::allocate(Stream::Reader(Box::new(reader)))
+  }
+
+  pub fn new_writer<W: Write + Seek + Send + 'static>(writer: W) -> Result<Self, Error> {
+    Self::allocate(Stream::Writer(Box::new(writer)))
+  }
+
+  fn allocate(stream: Stream) -> Result<Self, Error> {
+    let write_flag = matches!(stream, Stream::Writer(_));
+
+    unsafe {
+      let buffer = av_malloc(BUFFER_SIZE) as *mut u8;
+      if buffer.is_null() {
+        return Err(Error::from_av_error(AVERROR(ENOMEM)));
+      }
+
+      let stream = Box::into_raw(Box::new(stream));
+      let avio_context = if write_flag {
+        avio_alloc_context(
+          buffer,
+          BUFFER_SIZE as c_int,
+          1,
+          stream as *mut c_void,
+          None,
+          Some(write_packet),
+          Some(seek),
+        )
+      } else {
+        avio_alloc_context(
+          buffer,
+          BUFFER_SIZE as c_int,
+          0,
+          stream as *mut c_void,
+          Some(read_packet),
+          None,
+          Some(seek),
+        )
+      };
+
+      if avio_context.is_null() {
+        av_free(buffer as *mut c_void);
+        drop(Box::from_raw(stream));
+        return Err(Error::from_av_error(AVERROR(ENOMEM)));
+      }
+
+      Ok(IoContext {
+        avio_context,
+        stream,
+      })
+    }
+  }
+}
+
+unsafe impl Send for IoContext {}
+
+impl fmt::Debug for IoContext {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("IoContext")
+      .field("avio_context", &self.avio_context)
+      .finish()
+  }
+}
+
+impl Drop for IoContext {
+  fn drop(&mut self) {
+    unsafe {
+      if !self.avio_context.is_null() {
+        if (*self.avio_context).write_flag != 0 {
+          avio_flush(self.avio_context);
+        }
+        av_freep(&mut (*self.avio_context).buffer as *mut *mut u8 as *mut c_void);
+        avio_context_free(&mut self.avio_context);
+      }
+      if !self.stream.is_null() {
+        if let Stream::Writer(writer) = &mut *self.stream {
+          if let Err(error) = writer.flush() {
+            error!("Unable to flush output: {}", error);
+          }
+        }
+        drop(Box::from_raw(self.stream));
+      }
+    }
+  }
+}
+
+/// Reader which can be cloned into independent cursors over the same source,
+/// so several inputs of an `Order` or passes of a `DeepProbe` can share it.
+#[derive(Clone)]
+pub struct SharedReader {
+  inner: Arc<Mutex<Box<dyn ReadSeek>>>,
+  position: u64,
+}
+
+impl SharedReader {
+  pub fn new<R: Read + Seek + Send + 'static>(reader: R) -> Self {
+    SharedReader {
+      inner: Arc::new(Mutex::new(Box::new(reader))),
+      position: 0,
+    }
+  }
+
+  fn lock(&self) -> std::io::Result<std::sync::MutexGuard<Box<dyn ReadSeek>>> {
+    self
+      .inner
+      .lock()
+      .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "poisoned shared reader"))
+  }
+}
+
+impl Read for SharedReader {
+  fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut inner = self.lock()?;
+    inner.seek(SeekFrom::Start(self.position))?;
+    let size = inner.read(buffer)?;
+    self.position += size as u64;
+    Ok(size)
+  }
+}
+
+impl Seek for SharedReader {
+  fn seek(&mut self, position: SeekFrom) -> std::io::Result<u64> {
+    self.position = match position {
+      SeekFrom::Start(offset) => offset,
+      SeekFrom::Current(offset) => {
+        let position = self.position as i64 + offset;
+        if position < 0 {
+          return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "invalid seek to a negative position",
+          ));
+        }
+        position as u64
+      }
+      SeekFrom::End(offset) => self.lock()?.seek(SeekFrom::End(offset))?,
+    };
+    Ok(self.position)
+  }
+}
+
+impl fmt::Debug for SharedReader {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("SharedReader")
+      .field("position", &self.position)
+      .finish()
+  }
+}
+
+impl PartialEq for SharedReader {
+  fn eq(&self, other: &Self) -> bool {
+    Arc::ptr_eq(&self.inner, &other.inner)
+  }
+}
+
+fn io_error_code(error: &std::io::Error) -> c_int {
+  error.raw_os_error().map(AVERROR).unwrap_or(AVERROR(EIO))
+}
+
+unsafe extern "C" fn read_packet(
+  opaque: *mut c_void,
+  buffer: *mut u8,
+  buffer_size: c_int,
+) -> c_int {
+  let reader = match &mut *(opaque as *mut Stream) {
+    Stream::Reader(reader) => reader,
+    Stream::Writer(_) => return AVERROR(EINVAL),
+  };
+  let buffer = std::slice::from_raw_parts_mut(buffer, buffer_size as usize);
+
+  match reader.read(buffer) {
+    Ok(0) => AVERROR_EOF,
+    Ok(size) => size as c_int,
+    Err(error) => io_error_code(&error),
+  }
+}
+
+unsafe extern "C" fn write_packet(
+  opaque: *mut c_void,
+  buffer: *mut u8,
+  buffer_size: c_int,
+) -> c_int {
+  let writer = match &mut *(opaque as *mut Stream) {
+    Stream::Writer(writer) => writer,
+    Stream::Reader(_) => return AVERROR(EINVAL),
+  };
+  let buffer = std::slice::from_raw_parts(buffer, buffer_size as usize);
+
+  match writer.write_all(buffer) {
+    Ok(()) => buffer_size,
+    Err(error) => io_error_code(&error),
+  }
+}
+
+unsafe extern "C" fn seek(opaque: *mut c_void, offset: i64, whence: c_int) -> i64 {
+  let stream: &mut dyn Seek = match &mut *(opaque as *mut Stream) {
+    Stream::Reader(reader) => reader,
+    Stream::Writer(writer) => writer,
+  };
+
+  if whence & AVSEEK_SIZE as c_int != 0 {
+    let size = stream.stream_position().and_then(|current| {
+      let end = stream.seek(SeekFrom::End(0))?;
+      stream.seek(SeekFrom::Start(current))?;
+      Ok(end)
+    });
+    return match size {
+      Ok(size) => size as i64,
+      Err(error) => io_error_code(&error) as i64,
+    };
+  }
+
+  let position = match whence & !(AVSEEK_FORCE as c_int) {
+    libc::SEEK_SET => SeekFrom::Start(offset as u64),
+    libc::SEEK_CUR => SeekFrom::Current(offset),
+    libc::SEEK_END => SeekFrom::End(offset),
+    _ => return AVERROR(EINVAL) as i64,
+  };
+
+  match stream.seek(position) {
+    Ok(position) => position as i64,
+    Err(error) => io_error_code(&error) as i64,
+  }
+}
+
+#[test]
+fn shared_reader_cursors() {
+  let source = SharedReader::new(std::io::Cursor::new(vec![0u8, 1, 2, 3, 4, 5]));
+  let mut first = source.clone();
+  let mut second = source;
+
+  let mut buffer = [0u8; 2];
+  first.read_exact(&mut buffer).unwrap();
+  assert_eq!(buffer, [0, 1]);
+  second.seek(SeekFrom::End(-2)).unwrap();
+  second.read_exact(&mut buffer).unwrap();
+  assert_eq!(buffer, [4, 5]);
+  first.read_exact(&mut buffer).unwrap();
+  assert_eq!(buffer, [2, 3]);
+}
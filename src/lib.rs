@@ -8,15 +8,19 @@ pub mod error;
 
 pub mod audio_decoder;
 pub mod audio_encoder;
+pub mod channel_layout;
 pub mod filter;
 pub mod filter_graph;
 pub mod format_context;
 pub mod frame;
+pub mod hdr;
+pub mod io_context;
 pub mod order;
 pub mod packet;
 pub mod prelude;
 pub mod probe;
 pub mod stream;
+pub mod subtitle;
 pub mod subtitle_decoder;
 pub mod subtitle_encoder;
 pub mod tools;
@@ -0,0 +1,30 @@
+use crate::tools::rational::Rational;
+use std::collections::BTreeMap;
+
+/// A chapter marker, with `start` and `end` expressed in `time_base` units.
+#[derive(Debug, Clone, Deserialize, PartialEq, Serialize)]
+pub struct Chapter {
+  pub id: i64,
+  pub start: i64,
+  pub end: i64,
+  #[serde(default = "default_time_base")]
+  pub time_base: Rational,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub title: Option<String>,
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub metadata: BTreeMap<String, String>,
+}
+
+fn default_time_base() -> Rational {
+  Rational::new(1, 1000)
+}
+
+#[test]
+fn chapter_default_time_base() {
+  let chapter: Chapter =
+    serde_json::from_str(r#"{"id": 1, "start": 0, "end": 90000, "title": "Opening credits"}"#)
+      .unwrap();
+  assert_eq!(chapter.time_base, Rational::new(1, 1000));
+  assert_eq!(chapter.title, Some("Opening credits".to_string()));
+  assert!(chapter.metadata.is_empty());
+}
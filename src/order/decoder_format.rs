@@ -1,11 +1,14 @@
 use crate::audio_decoder::AudioDecoder;
+use crate::error::Error;
 use crate::filter_graph::FilterGraph;
 use crate::format_context::FormatContext;
+use crate::io_context::SharedReader;
 use crate::order::input::Input;
 use crate::subtitle_decoder::SubtitleDecoder;
 use crate::tools;
 use crate::video_decoder::VideoDecoder;
 use ffmpeg_sys_next::AVMediaType;
+use std::collections::HashMap;
 
 #[derive(Debug)]
 pub struct DecoderFormat {
@@ -13,10 +16,20 @@ pub struct DecoderFormat {
   pub audio_decoders: Vec<AudioDecoder>,
   pub subtitle_decoders: Vec<SubtitleDecoder>,
   pub video_decoders: Vec<VideoDecoder>,
+  /// Labels of the streams copied without decoding, by stream index.
+  pub copied_streams: HashMap<isize, String>,
+  /// Set once the end of the input is reached and its decoders are drained.
+  pub ended: bool,
 }
 
 impl DecoderFormat {
-  pub fn new(graph: &mut FilterGraph, input: &Input) -> Result<Self, String> {
+  /// Streams labelled in `copied_labels` are not decoded, their packets are read as is.
+  pub fn new(
+    graph: &mut FilterGraph,
+    input: &Input,
+    reader: Option<SharedReader>,
+    copied_labels: &[String],
+  ) -> Result<Self, Error> {
     match input {
       Input::VideoFrames {
         path,
@@ -49,13 +62,19 @@ impl DecoderFormat {
           audio_decoders,
           subtitle_decoders,
           video_decoders,
+          copied_streams: HashMap::new(),
+          ended: false,
         })
       }
       Input::Streams { path, streams, .. } => {
         let mut audio_decoders = vec![];
         let mut subtitle_decoders = vec![];
         let mut video_decoders = vec![];
-        let mut context = FormatContext::new(path)?;
+        let mut copied_streams = HashMap::new();
+        let mut context = match reader {
+          Some(reader) => FormatContext::from_reader(path, reader)?,
+          None => FormatContext::new(path)?,
+        };
         context.open_input()?;
 
         for stream in streams {
@@ -65,6 +84,11 @@ impl DecoderFormat {
             tools::random_string(8)
           };
 
+          if copied_labels.contains(&identifier) {
+            copied_streams.insert(stream.index as isize, identifier);
+            continue;
+          }
+
           match context.get_stream_type(stream.index as isize) {
             AVMediaType::AVMEDIA_TYPE_VIDEO => {
               let video_decoder =
@@ -92,6 +116,8 @@ impl DecoderFormat {
           audio_decoders,
           subtitle_decoders,
           video_decoders,
+          copied_streams,
+          ended: false,
         })
       }
     }
@@ -1,16 +1,28 @@
 use crate::audio_encoder::AudioEncoder;
+use crate::error::Error;
 use crate::filter_graph::FilterGraph;
 use crate::format_context::FormatContext;
 use crate::frame::Frame;
-use crate::order::output::Output;
+use crate::io_context::IoContext;
+use crate::order::output::{Output, OutputStream};
 use crate::order::output_kind::OutputKind;
 use crate::packet::Packet;
+use crate::stream::Stream;
+use crate::subtitle::Subtitle;
 use crate::subtitle_encoder::SubtitleEncoder;
 use crate::tools;
 use crate::video_encoder::VideoEncoder;
 use ffmpeg_sys_next::*;
-use std::ffi::CString;
-use std::ptr::null_mut;
+use std::collections::HashMap;
+
+/// An output stream muxing the packets of an input stream.
+#[derive(Debug)]
+struct StreamCopy {
+  identifier: String,
+  stream_index: isize,
+  /// Time base of the input stream.
+  time_base: AVRational,
+}
 
 #[derive(Debug)]
 pub struct EncoderFormat {
@@ -18,29 +30,47 @@ pub struct EncoderFormat {
   pub audio_encoders: Vec<AudioEncoder>,
   pub subtitle_encoders: Vec<SubtitleEncoder>,
   pub video_encoders: Vec<VideoEncoder>,
+  stream_copies: Vec<StreamCopy>,
   wrap: bool,
+  finished: bool,
 }
 
 impl Drop for EncoderFormat {
   fn drop(&mut self) {
-    unsafe {
-      av_write_trailer(self.context.format_context);
+    // outputs not finished explicitly, e.g. after an error, are still closed
+    if !self.finished {
+      unsafe {
+        av_write_trailer(self.context.format_context);
+      }
     }
   }
 }
 
 impl EncoderFormat {
-  pub fn new(graph: &mut FilterGraph, output: &Output) -> Result<Self, String> {
+  /// `subtitle_headers` holds the ASS header of each subtitle decoder, by label,
+  /// and `copied_streams` the input streams of the "copy" output streams, by label.
+  pub fn new(
+    graph: &mut FilterGraph,
+    output: &Output,
+    writer: Option<IoContext>,
+    subtitle_headers: &HashMap<String, Vec<u8>>,
+    copied_streams: &HashMap<String, *mut AVStream>,
+  ) -> Result<Self, Error> {
     let mut audio_encoders = vec![];
     let mut subtitle_encoders = vec![];
     let mut video_encoders = vec![];
+    let mut stream_copies = vec![];
     if output.path.is_none() {
-      return Err("missing output filename".to_owned());
+      return Err(Error::Configuration("missing output filename".to_owned()));
     }
 
     let path = output.path.clone().unwrap();
-    let mut format = FormatContext::new(&path)?;
-    format.open_output(&output.parameters)?;
+    let mut format = match writer {
+      Some(writer) => FormatContext::with_io_context(&path, writer)?,
+      None => FormatContext::new(&path)?,
+    };
+    format.open_output()?;
+    format.set_chapters(&output.chapters)?;
 
     for (index, stream) in output.streams.iter().enumerate() {
       let identifier = if let Some(ref identifier) = stream.label {
@@ -49,92 +79,104 @@ impl EncoderFormat {
         tools::random_string(8)
       };
 
+      if stream.is_copy() {
+        let source = *copied_streams.get(&identifier).ok_or_else(|| {
+          Error::Configuration(format!("No input stream labelled {identifier} to copy"))
+        })?;
+        format.add_copy_stream(source)?;
+        set_stream_properties(&format, stream)?;
+        stream_copies.push(StreamCopy {
+          identifier,
+          stream_index: index as isize,
+          time_base: unsafe { (*source).time_base },
+        });
+        continue;
+      }
+
       match tools::get_codec_type(&stream.codec) {
         Some(AVMediaType::AVMEDIA_TYPE_VIDEO) => {
           let video_encoder = VideoEncoder::new(identifier.clone(), index as isize, stream)?;
           format.add_video_stream(&video_encoder)?;
+          set_stream_properties(&format, stream)?;
+          graph.add_output_from_video_encoder(&identifier, &video_encoder)?;
           video_encoders.push(video_encoder);
-          graph.add_video_output(&identifier)?;
         }
         Some(AVMediaType::AVMEDIA_TYPE_AUDIO) => {
           let audio_encoder = AudioEncoder::new(identifier.clone(), index as isize, stream)?;
           format.add_audio_stream(&audio_encoder)?;
+          set_stream_properties(&format, stream)?;
+          graph.add_output_from_audio_encoder(&identifier, &audio_encoder)?;
           audio_encoders.push(audio_encoder);
-          graph.add_audio_output(&identifier)?;
         }
         Some(AVMediaType::AVMEDIA_TYPE_SUBTITLE) => {
-          let subtitle_encoder = SubtitleEncoder::new(identifier.clone(), index as isize, stream)?;
+          let subtitle_encoder = SubtitleEncoder::new(
+            identifier.clone(),
+            index as isize,
+            stream,
+            subtitle_headers.get(&identifier).map(Vec::as_slice),
+          )?;
           format.add_subtitle_stream(&subtitle_encoder)?;
+          set_stream_properties(&format, stream)?;
           subtitle_encoders.push(subtitle_encoder);
         }
         _ => {}
       }
     }
 
-    unsafe {
-      let p = CString::new(path).unwrap();
-      av_dump_format(format.format_context, 0, p.as_ptr(), 1);
-
-      check_result!(avio_open(
-        &mut (*format.format_context).pb as *mut _,
-        p.as_ptr(),
-        AVIO_FLAG_WRITE
-      ));
-      check_result!(avformat_write_header(format.format_context, null_mut()));
-    }
+    format.write_header(&output.parameters)?;
 
     Ok(EncoderFormat {
       context: format,
       audio_encoders,
       subtitle_encoders,
       video_encoders,
+      stream_copies,
       wrap: output.kind == Some(OutputKind::File),
+      finished: false,
     })
   }
 
-  pub fn wrap(&mut self, packet: &Packet) -> Result<(), String> {
+  pub fn encode_subtitle(&mut self, subtitle: &Subtitle) -> Result<Option<Packet>, Error> {
+    let mut r_packet = None;
     for subtitle_encoder in &self.subtitle_encoders {
-      if let Some(ref name) = packet.name {
+      if let Some(ref name) = subtitle.name {
         if subtitle_encoder.identifier == *name {
-          unsafe {
-            (*packet.packet).stream_index = subtitle_encoder.stream_index as i32;
-            check_result!(av_interleaved_write_frame(
-              self.context.format_context,
-              packet.packet
-            ));
+          if let Some(packet) = subtitle_encoder.encode(subtitle)? {
+            let time_base = unsafe { (*subtitle_encoder.codec_context).time_base };
+            r_packet = self.write(subtitle_encoder.stream_index, time_base, packet)?;
           }
         }
       }
     }
 
-    Ok(())
+    Ok(r_packet)
   }
 
-  pub fn encode(&mut self, frame: &Frame) -> Result<Option<Packet>, String> {
-    let mut r_packet = None;
-    for audio_encoder in &self.audio_encoders {
+  /// Mux a demuxed packet into the output stream copying its input stream, if any.
+  pub fn copy_packet(&mut self, packet: &Packet) -> Result<Option<Packet>, Error> {
+    let copy = self
+      .stream_copies
+      .iter()
+      .find(|copy| packet.name.as_ref() == Some(&copy.identifier));
+    let (stream_index, time_base) = match copy {
+      Some(copy) => (copy.stream_index, copy.time_base),
+      None => return Ok(None),
+    };
+
+    let mut packet = packet.try_clone()?;
+    packet.set_position(None);
+    self.write(stream_index, time_base, packet)
+  }
+
+  pub fn encode(&mut self, frame: &Frame) -> Result<Vec<Packet>, Error> {
+    let mut packets = vec![];
+    let mut encoded = vec![];
+    for audio_encoder in &mut self.audio_encoders {
       if let Some(ref name) = frame.name {
         if audio_encoder.identifier == *name {
-          unsafe {
-            let packet = av_packet_alloc();
-            av_init_packet(packet);
-            (*packet).data = null_mut();
-            (*packet).size = 0;
-            let p = Packet { name: None, packet };
-
-            let status = audio_encoder.encode(frame, &p)?;
-
-            if status {
-              if self.wrap {
-                (*packet).stream_index = audio_encoder.stream_index as i32;
-                check_result!(av_interleaved_write_frame(
-                  self.context.format_context,
-                  packet
-                ));
-              } else {
-                r_packet = Some(p);
-              }
-            }
+          let time_base = unsafe { (*audio_encoder.codec_context).time_base };
+          for packet in audio_encoder.encode(frame)? {
+            encoded.push((audio_encoder.stream_index, time_base, packet));
           }
         }
       }
@@ -142,30 +184,84 @@ impl EncoderFormat {
     for video_encoder in &mut self.video_encoders {
       if let Some(ref name) = frame.name {
         if video_encoder.identifier == *name {
-          unsafe {
-            let packet = av_packet_alloc();
-            av_init_packet(packet);
-            (*packet).data = null_mut();
-            (*packet).size = 0;
-            let p = Packet { name: None, packet };
-
-            let status = video_encoder.encode(frame, &p)?;
-            if status {
-              if self.wrap {
-                (*packet).stream_index = video_encoder.stream_index as i32;
-                check_result!(av_interleaved_write_frame(
-                  self.context.format_context,
-                  packet
-                ));
-              } else {
-                r_packet = Some(p);
-              }
-            }
+          let time_base = unsafe { (*video_encoder.codec_context).time_base };
+          for packet in video_encoder.encode(frame)? {
+            encoded.push((video_encoder.stream_index, time_base, packet));
           }
         }
       }
     }
 
-    Ok(r_packet)
+    for (stream_index, time_base, packet) in encoded {
+      packets.extend(self.write(stream_index, time_base, packet)?);
+    }
+    Ok(packets)
+  }
+
+  /// Drain the encoders at the end of the streams, and write the trailer.
+  pub fn finish(&mut self) -> Result<Vec<Packet>, Error> {
+    if self.finished {
+      return Ok(vec![]);
+    }
+    let mut packets = vec![];
+    let mut encoded = vec![];
+    for audio_encoder in &mut self.audio_encoders {
+      let time_base = unsafe { (*audio_encoder.codec_context).time_base };
+      for packet in audio_encoder.flush()? {
+        encoded.push((audio_encoder.stream_index, time_base, packet));
+      }
+    }
+    for video_encoder in &mut self.video_encoders {
+      let time_base = unsafe { (*video_encoder.codec_context).time_base };
+      for packet in video_encoder.flush()? {
+        encoded.push((video_encoder.stream_index, time_base, packet));
+      }
+    }
+
+    for (stream_index, time_base, packet) in encoded {
+      packets.extend(self.write(stream_index, time_base, packet)?);
+    }
+
+    self.finished = true;
+    unsafe {
+      check_result!(av_write_trailer(self.context.format_context));
+    }
+    Ok(packets)
+  }
+
+  /// Mux an encoded packet into the output file, or give it back for packet outputs.
+  fn write(
+    &mut self,
+    stream_index: isize,
+    time_base: AVRational,
+    mut packet: Packet,
+  ) -> Result<Option<Packet>, Error> {
+    if !self.wrap {
+      return Ok(Some(packet));
+    }
+    unsafe {
+      let stream = self.context.get_stream(stream_index);
+      packet.set_stream_index(stream_index);
+      packet.rescale_ts(time_base, (*stream).time_base);
+      check_result!(av_interleaved_write_frame(
+        self.context.format_context,
+        packet.packet
+      ));
+    }
+    Ok(None)
+  }
+}
+
+/// Apply the disposition and language of an output stream to the last stream added.
+fn set_stream_properties(
+  format: &FormatContext,
+  output_stream: &OutputStream,
+) -> Result<(), Error> {
+  let stream_index = format.get_nb_streams() as isize - 1;
+  let mut stream = Stream::new(format.get_stream(stream_index))?;
+  stream.set_disposition(output_stream.disposition);
+  if let Some(language) = &output_stream.language {
+    stream.set_language(language)?;
   }
+  Ok(())
 }
@@ -0,0 +1,63 @@
+use crate::order::filter::Filter;
+use serde::{
+  de::{value::SeqAccessDeserializer, Error, SeqAccess, Visitor},
+  Deserialize, Deserializer,
+};
+use std::fmt;
+
+/// Filters of an order, declared one by one or as an ffmpeg filtergraph description
+/// (`-filter_complex` syntax) whose pad labels are the stream labels.
+#[derive(Debug, PartialEq)]
+pub enum Graph {
+  Filters(Vec<Filter>),
+  Description(String),
+}
+
+impl From<Vec<Filter>> for Graph {
+  fn from(filters: Vec<Filter>) -> Self {
+    Graph::Filters(filters)
+  }
+}
+
+impl From<&str> for Graph {
+  fn from(description: &str) -> Self {
+    Graph::Description(description.to_string())
+  }
+}
+
+// dispatched on the JSON type, to keep the errors of the filters
+impl<'de> Deserialize<'de> for Graph {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    struct GraphVisitor;
+
+    impl<'de> Visitor<'de> for GraphVisitor {
+      type Value = Graph;
+
+      fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a list of filters or a filtergraph description")
+      }
+
+      fn visit_str<E: Error>(self, description: &str) -> Result<Self::Value, E> {
+        Ok(Graph::Description(description.to_string()))
+      }
+
+      fn visit_seq<A: SeqAccess<'de>>(self, filters: A) -> Result<Self::Value, A::Error> {
+        Vec::<Filter>::deserialize(SeqAccessDeserializer::new(filters)).map(Graph::Filters)
+      }
+    }
+
+    deserializer.deserialize_any(GraphVisitor)
+  }
+}
+
+#[test]
+fn graph_deserialization() {
+  let graph: Graph = serde_json::from_str(r#""[in]volume=0.5[out]""#).unwrap();
+  assert_eq!(graph, Graph::from("[in]volume=0.5[out]"));
+
+  let graph: Graph = serde_json::from_str("[]").unwrap();
+  assert_eq!(graph, Graph::Filters(vec![]));
+
+  let error = serde_json::from_str::<Graph>(r#"[{"label": "volume"}]"#).unwrap_err();
+  assert!(error.to_string().contains("missing field `name`"));
+}
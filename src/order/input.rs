@@ -18,3 +18,11 @@ pub enum Input {
     frames: Vec<FrameAddress>,
   },
 }
+
+impl Input {
+  pub fn get_path(&self) -> &str {
+    match self {
+      Input::Streams { path, .. } | Input::VideoFrames { path, .. } => path,
+    }
+  }
+}
@@ -1,12 +1,17 @@
+use crate::error::Error;
 use crate::filter_graph::FilterGraph;
+use crate::io_context::{IoContext, SharedReader};
 use std::collections::HashMap;
+use std::io::{Seek, Write};
 
+pub mod chapter;
 mod decoder_format;
 mod encoder_format;
 pub mod filter;
 pub mod filter_input;
 pub mod filter_output;
 pub mod frame;
+pub mod graph;
 pub mod input;
 pub mod input_kind;
 pub mod output;
@@ -20,6 +25,7 @@ use crate::order::decoder_format::DecoderFormat;
 use crate::order::encoder_format::EncoderFormat;
 pub use crate::order::filter::Filter;
 use crate::order::filter_input::FilterInput;
+pub use crate::order::graph::Graph;
 use crate::order::input::Input;
 use crate::order::input_kind::InputKind;
 use crate::order::output::Output;
@@ -28,41 +34,67 @@ pub use crate::order::output_result::OutputResult;
 pub use crate::order::parameters::*;
 
 use crate::packet::Packet;
-use std::ptr::null_mut;
+use crate::subtitle::Subtitle;
+use ffmpeg_sys_next::AVStream;
 
 #[derive(Debug, Deserialize)]
 pub struct Order {
   pub inputs: Vec<Input>,
   pub outputs: Vec<Output>,
-  pub graph: Vec<Filter>,
-  #[serde(skip)]
-  total_streams: u32,
+  pub graph: Graph,
   #[serde(skip)]
   input_formats: Vec<DecoderFormat>,
   #[serde(skip)]
   output_formats: Vec<EncoderFormat>,
   #[serde(skip)]
   pub filter_graph: FilterGraph,
+  #[serde(skip)]
+  readers: HashMap<String, SharedReader>,
+  #[serde(skip)]
+  writers: HashMap<String, IoContext>,
 }
 
 impl Order {
-  pub fn new(inputs: Vec<Input>, graph: Vec<Filter>, outputs: Vec<Output>) -> Result<Self, String> {
+  pub fn new<G: Into<Graph>>(
+    inputs: Vec<Input>,
+    graph: G,
+    outputs: Vec<Output>,
+  ) -> Result<Self, Error> {
     Ok(Order {
       inputs,
       outputs,
-      graph,
-      total_streams: 0,
+      graph: graph.into(),
       input_formats: vec![],
       output_formats: vec![],
       filter_graph: FilterGraph::new()?,
+      readers: HashMap::new(),
+      writers: HashMap::new(),
     })
   }
 
-  pub fn new_parse(message: &str) -> Result<Self, String> {
-    serde_json::from_str(message).map_err(|e| e.to_string())
+  pub fn new_parse(message: &str) -> Result<Self, Error> {
+    Ok(serde_json::from_str(message)?)
+  }
+
+  /// Read the stream inputs declared with this path from the reader instead of opening the path.
+  pub fn set_input_reader(&mut self, path: &str, reader: SharedReader) {
+    self.readers.insert(path.to_string(), reader);
+  }
+
+  /// Write the output declared with this path into the writer.
+  /// The path extension is still used to select the muxer.
+  pub fn set_output_writer<W: Write + Seek + Send + 'static>(
+    &mut self,
+    path: &str,
+    writer: W,
+  ) -> Result<(), Error> {
+    self
+      .writers
+      .insert(path.to_string(), IoContext::new_writer(writer)?);
+    Ok(())
   }
 
-  pub fn setup(&mut self) -> Result<(), String> {
+  pub fn setup(&mut self) -> Result<(), Error> {
     warn!("Build inputs");
     self.build_input_format()?;
     warn!("Build outputs");
@@ -75,160 +107,228 @@ impl Order {
     Ok(())
   }
 
-  pub fn process(&mut self) -> Result<Vec<OutputResult>, String> {
+  pub fn process(&mut self) -> Result<Vec<OutputResult>, Error> {
     let mut results = vec![];
 
     loop {
-      let (audio_frames, video_frames, subtitle_packets, end) = self.process_input();
+      let (frames, packets, subtitles, ended_streams) = self.process_input()?;
 
-      if end == self.total_streams {
-        break;
+      for frame in &frames {
+        self.filter_graph.send_frame(frame)?;
+      }
+      for label in &ended_streams {
+        self.filter_graph.send_eof(label)?;
       }
+      let (output_audio_frames, output_video_frames) = self.filter_graph.receive_frames()?;
 
-      if audio_frames.len() == self.filter_graph.audio_inputs.len()
-        && video_frames.len() == self.filter_graph.video_inputs.len()
-      {
-        let (output_audio_frames, output_video_frames) =
-          if audio_frames.is_empty() && video_frames.is_empty() {
-            (audio_frames, video_frames)
-          } else {
-            self.filter_graph.process(&audio_frames, &video_frames)?
-          };
-        for output_frame in output_audio_frames {
-          for output in &self.outputs {
-            if output.stream == output_frame.name {
-              if let Some(OutputKind::AudioMetadata) = output.kind {
-                if let Input::Streams { streams, .. } = &self.inputs[output_frame.index] {
-                  for stream in streams {
-                    let mut entry = HashMap::new();
-                    entry.insert("pts".to_owned(), output_frame.get_pts().to_string());
-                    entry.insert("stream_id".to_owned(), stream.index.to_string());
-
-                    for key in &output.keys {
-                      if let Some(value) = output_frame.get_metadata(key) {
-                        entry.insert(key.clone(), value);
-                      }
+      for output_frame in output_audio_frames {
+        for output in &self.outputs {
+          if output.stream == output_frame.name {
+            if let Some(OutputKind::AudioMetadata) = output.kind {
+              if let Input::Streams { streams, .. } = &self.inputs[output_frame.index] {
+                for stream in streams {
+                  let mut entry = HashMap::new();
+                  entry.insert("pts".to_owned(), output_frame.get_pts().to_string());
+                  entry.insert("stream_id".to_owned(), stream.index.to_string());
+
+                  for key in &output.keys {
+                    if let Some(value) = output_frame.get_metadata(key) {
+                      entry.insert(key.clone(), value);
                     }
-                    results.push(OutputResult::Entry(entry));
                   }
+                  results.push(OutputResult::Entry(entry));
                 }
               }
             }
           }
+        }
 
-          for output in &mut self.output_formats {
-            if let Some(packet) = output.encode(&output_frame)? {
-              results.push(OutputResult::Packet(packet));
-            };
+        for output in &mut self.output_formats {
+          for packet in output.encode(&output_frame)? {
+            results.push(OutputResult::Packet(packet));
           }
         }
+      }
 
-        for output_packet in subtitle_packets {
-          for output in &mut self.output_formats {
-            output.wrap(&output_packet)?;
+      for packet in &packets {
+        for output in &mut self.output_formats {
+          if let Some(packet) = output.copy_packet(packet)? {
+            results.push(OutputResult::Packet(packet));
           }
         }
-        for output_frame in output_video_frames {
-          for output in &self.outputs {
-            if let Some(OutputKind::VideoMetadata) = output.kind {
-              let mut entry = HashMap::new();
-              entry.insert("pts".to_owned(), output_frame.get_pts().to_string());
-              if let Input::Streams { streams, .. } = &self.inputs[output_frame.index] {
-                entry.insert("stream_id".to_owned(), streams[0].index.to_string());
-              }
+      }
 
-              for key in &output.keys {
-                if let Some(value) = output_frame.get_metadata(key) {
-                  entry.insert(key.clone(), value);
-                }
-              }
+      for subtitle in subtitles {
+        for output in &mut self.output_formats {
+          if let Some(packet) = output.encode_subtitle(&subtitle)? {
+            results.push(OutputResult::Packet(packet));
+          };
+        }
+      }
+      for output_frame in output_video_frames {
+        for output in &self.outputs {
+          if let Some(OutputKind::VideoMetadata) = output.kind {
+            let mut entry = HashMap::new();
+            entry.insert("pts".to_owned(), output_frame.get_pts().to_string());
+            if let Input::Streams { streams, .. } = &self.inputs[output_frame.index] {
+              entry.insert("stream_id".to_owned(), streams[0].index.to_string());
+            }
 
-              results.push(OutputResult::Entry(entry));
+            for key in &output.keys {
+              if let Some(value) = output_frame.get_metadata(key) {
+                entry.insert(key.clone(), value);
+              }
             }
+
+            results.push(OutputResult::Entry(entry));
           }
+        }
 
-          for output in &mut self.output_formats {
-            if let Some(packet) = output.encode(&output_frame)? {
-              results.push(OutputResult::Packet(packet));
-            };
+        for output in &mut self.output_formats {
+          for packet in output.encode(&output_frame)? {
+            results.push(OutputResult::Packet(packet));
           }
         }
       }
+
+      if self.input_formats.iter().all(|format| format.ended) {
+        for output in &mut self.output_formats {
+          for packet in output.finish()? {
+            results.push(OutputResult::Packet(packet));
+          }
+        }
+        break;
+      }
     }
 
     Ok(results)
   }
 
-  fn process_input(&mut self) -> (Vec<Frame>, Vec<Frame>, Vec<Packet>, u32) {
-    let mut audio_frames = vec![];
-    let mut subtitle_packets = vec![];
-    let mut video_frames = vec![];
-    let mut end = 0;
+  /// Read one packet per stream of each input, and decode it, or return it for copied streams.
+  /// At the end of an input, its decoders are drained and their labels returned.
+  fn process_input(
+    &mut self,
+  ) -> Result<(Vec<Frame>, Vec<Packet>, Vec<Subtitle>, Vec<String>), Error> {
+    let mut frames = vec![];
+    let mut packets = vec![];
+    let mut subtitles = vec![];
+    let mut ended_streams = vec![];
 
     for format in &mut self.input_formats {
+      if format.ended {
+        continue;
+      }
       for _ in 0..format.context.get_nb_streams() {
         match format.context.next_packet() {
           Ok(mut packet) => {
+            if let Some(label) = format.copied_streams.get(&packet.get_stream_index()) {
+              packet.name = Some(label.clone());
+              packets.push(packet);
+              continue;
+            }
             for decoder in &format.audio_decoders {
               if decoder.stream_index == packet.get_stream_index() {
-                if let Ok(frame) = decoder.decode(&packet) {
-                  audio_frames.push(frame);
+                match decoder.decode(&packet) {
+                  Ok(decoded) => frames.extend(decoded),
+                  Err(error) => warn!("unable to decode {}: {error}", decoder.identifier),
                 }
               }
             }
             for decoder in &format.video_decoders {
               if decoder.stream_index == packet.get_stream_index() {
-                if let Ok(frame) = decoder.decode(&packet) {
-                  video_frames.push(frame);
+                match decoder.decode(&packet) {
+                  Ok(decoded) => frames.extend(decoded),
+                  Err(error) => warn!("unable to decode {}: {error}", decoder.identifier),
                 }
               }
             }
             for decoder in &format.subtitle_decoders {
               if decoder.stream_index == packet.get_stream_index() {
-                packet.name = Some(decoder.identifier.clone());
-                subtitle_packets.push(packet);
-                break;
+                match decoder.decode(&packet) {
+                  Ok(Some(subtitle)) => subtitles.push(subtitle),
+                  Ok(None) => {}
+                  Err(error) => warn!("unable to decode {}: {error}", decoder.identifier),
+                }
               }
             }
           }
-          Err(msg) => {
-            if msg == "End of data stream" {
-              for decoder in &format.video_decoders {
-                let packet = null_mut();
-
-                let p = Packet { name: None, packet };
-
-                if let Ok(frame) = decoder.decode(&p) {
-                  video_frames.push(frame);
-                } else {
-                  end += 1;
-                }
-              }
-            } else {
-              end += 1;
+          Err(Error::EndOfStream) => {
+            for decoder in &format.audio_decoders {
+              frames.extend(decoder.drain()?);
+              ended_streams.push(decoder.identifier.clone());
             }
+            for decoder in &format.video_decoders {
+              frames.extend(decoder.drain()?);
+              ended_streams.push(decoder.identifier.clone());
+            }
+            for decoder in &format.subtitle_decoders {
+              subtitles.extend(decoder.drain()?);
+            }
+            format.ended = true;
+            break;
           }
+          // a truncated output must not be reported as a success
+          Err(error) => return Err(error),
         }
       }
     }
 
-    (audio_frames, video_frames, subtitle_packets, end)
+    Ok((frames, packets, subtitles, ended_streams))
   }
 
-  fn build_input_format(&mut self) -> Result<(), String> {
+  fn build_input_format(&mut self) -> Result<(), Error> {
+    let copied_labels: Vec<String> = self
+      .outputs
+      .iter()
+      .flat_map(|output| &output.streams)
+      .filter(|stream| stream.is_copy())
+      .filter_map(|stream| stream.label.clone())
+      .collect();
+
     for input in &self.inputs {
-      let decoder = DecoderFormat::new(&mut self.filter_graph, input)?;
-      self.total_streams += decoder.context.get_nb_streams();
+      let reader = self.readers.get(input.get_path()).cloned();
+      let decoder = DecoderFormat::new(&mut self.filter_graph, input, reader, &copied_labels)?;
       self.input_formats.push(decoder);
     }
     Ok(())
   }
 
-  fn build_output_format(&mut self) -> Result<(), String> {
+  fn build_output_format(&mut self) -> Result<(), Error> {
+    let subtitle_headers: HashMap<String, Vec<u8>> = self
+      .input_formats
+      .iter()
+      .flat_map(|format| &format.subtitle_decoders)
+      .filter_map(|decoder| {
+        decoder
+          .get_subtitle_header()
+          .map(|header| (decoder.identifier.clone(), header.to_vec()))
+      })
+      .collect();
+    let copied_streams: HashMap<String, *mut AVStream> = self
+      .input_formats
+      .iter()
+      .flat_map(|format| {
+        format
+          .copied_streams
+          .iter()
+          .map(move |(index, label)| (label.clone(), format.context.get_stream(*index)))
+      })
+      .collect();
+
     for output in &self.outputs {
       match output.kind {
         Some(OutputKind::File) | Some(OutputKind::Packet) => {
-          let encoder = EncoderFormat::new(&mut self.filter_graph, output)?;
+          let writer = output
+            .path
+            .as_ref()
+            .and_then(|path| self.writers.remove(path));
+          let encoder = EncoderFormat::new(
+            &mut self.filter_graph,
+            output,
+            writer,
+            &subtitle_headers,
+            &copied_streams,
+          )?;
           self.output_formats.push(encoder);
         }
         Some(OutputKind::AudioMetadata) => {
@@ -247,11 +347,59 @@ impl Order {
     Ok(())
   }
 
-  fn build_graph(&mut self) -> Result<Vec<crate::filter::Filter>, String> {
+  fn build_graph(&mut self) -> Result<Vec<crate::filter::Filter>, Error> {
+    let descriptions = match &self.graph {
+      Graph::Filters(descriptions) => descriptions,
+      Graph::Description(description) => {
+        self.filter_graph.parse(description)?;
+        return Ok(vec![]);
+      }
+    };
+
     let mut filters = vec![];
+    for filter_description in descriptions {
+      filters.push(self.filter_graph.add_filter(filter_description)?);
+    }
+
+    // labels of the filter outputs consumed by another filter, not by an encoder
+    let linked_labels: Vec<&String> = descriptions
+      .iter()
+      .flat_map(|filter_description| filter_description.inputs.iter().flatten())
+      .filter(|input| input.kind == InputKind::Filter)
+      .map(|input| &input.stream_label)
+      .collect();
 
-    for filter_description in &self.graph {
-      let filter = self.filter_graph.add_filter(filter_description)?;
+    let mut filter_outputs = HashMap::new();
+    for (filter_index, filter_description) in descriptions.iter().enumerate() {
+      if let Some(ref outputs) = filter_description.outputs {
+        for (index, output) in outputs.iter().enumerate() {
+          if !linked_labels.contains(&&output.stream_label) {
+            let encoder_stream_index = 0;
+            debug!(
+              "connect output {} ({})",
+              output.stream_label, encoder_stream_index
+            );
+            self.filter_graph.connect_output(
+              &filters[filter_index],
+              index as u32,
+              &output.stream_label,
+              encoder_stream_index,
+            )?;
+          } else if filter_outputs
+            .insert(output.stream_label.clone(), (filter_index, index as u32))
+            .is_some()
+          {
+            return Err(Error::Configuration(format!(
+              "filter output {} is declared twice",
+              output.stream_label
+            )));
+          }
+        }
+      }
+    }
+
+    for (filter_index, filter_description) in descriptions.iter().enumerate() {
+      let filter = &filters[filter_index];
       if let Some(ref inputs) = filter_description.inputs {
         for (index, input) in inputs.iter().enumerate() {
           match *input {
@@ -261,47 +409,39 @@ impl Order {
             } => {
               let decoder_stream_index = 0;
               debug!("connect input {} ({})", label, decoder_stream_index);
-              if let Err(msg) =
-                self
-                  .filter_graph
-                  .connect_input(label, decoder_stream_index, &filter, index as u32)
-              {
-                return Err(format!(
-                  "unable to connect input stream {label} ({decoder_stream_index}): {msg}"
-                ));
-              }
+              self
+                .filter_graph
+                .connect_input(label, decoder_stream_index, filter, index as u32)?;
             }
             FilterInput {
               kind: InputKind::Filter,
-              stream_label: ref _label,
-            } => {}
+              stream_label: ref label,
+            } => {
+              // an output pad feeds a single input, use split or asplit to duplicate it
+              let (source_index, source_pad) = filter_outputs.remove(label).ok_or_else(|| {
+                Error::Configuration(format!(
+                  "unable to connect input filter {label}: no filter output labelled {label}, or already connected"
+                ))
+              })?;
+              debug!("connect filter {} ({})", label, source_pad);
+              self.filter_graph.connect(
+                &filters[source_index],
+                source_pad,
+                filter,
+                index as u32,
+              )?;
+            }
           }
         }
-      } else if let Some(last_filter) = filters.last() {
-        if let Err(msg) = self.filter_graph.connect(last_filter, 0, &filter, 0) {
-          return Err(format!("unable to auto-connect : {msg}"));
-        }
-      } else if let Err(msg) = self.filter_graph.connect_input("", 0, &filter, 0) {
-        return Err(format!("unable to auto-connect with input: {msg}"));
-      }
-
-      if let Some(ref outputs) = filter_description.outputs {
-        for (index, output) in outputs.iter().enumerate() {
-          let encoder_stream_index = 0;
-          debug!(
-            "connect output {} ({})",
-            output.stream_label, encoder_stream_index
-          );
-          self.filter_graph.connect_output(
-            &filter,
-            index as u32,
-            &output.stream_label,
-            encoder_stream_index,
-          )?;
-        }
+      } else if filter_index > 0 {
+        debug!("auto-connect filter {}", filter_index);
+        self
+          .filter_graph
+          .connect(&filters[filter_index - 1], 0, filter, 0)?;
+      } else {
+        debug!("auto-connect input");
+        self.filter_graph.connect_input("", 0, filter, 0)?;
       }
-
-      filters.push(filter);
     }
 
     Ok(filters)
@@ -316,6 +456,7 @@ fn parse_sample_audio_encoding_graph() {
   use crate::order::output_kind::OutputKind;
   use crate::order::stream::Stream;
   use crate::order::ParameterValue;
+  use crate::stream::Disposition;
   use crate::tools::rational::Rational;
   use serde_json;
   use std::fs::File;
@@ -373,14 +514,17 @@ fn parse_sample_audio_encoding_graph() {
       streams: vec![OutputStream {
         label: Some("output1".to_string()),
         codec: "pcm_s24le".to_string(),
-        parameters: output_params
-      }]
+        parameters: output_params,
+        disposition: Disposition::default(),
+        language: None,
+      }],
+      chapters: vec![],
     }],
     order.outputs
   );
 
   assert_eq!(
-    vec![
+    Graph::Filters(vec![
       Filter {
         name: "amerge".to_string(),
         label: Some("amerge_filter".to_string()),
@@ -406,7 +550,7 @@ fn parse_sample_audio_encoding_graph() {
           stream_label: "output1".to_string()
         }])
       }
-    ],
+    ]),
     order.graph
   );
 }
@@ -419,6 +563,7 @@ fn parse_sample_video_encoding_graph() {
   use crate::order::output_kind::OutputKind;
   use crate::order::stream::Stream;
   use crate::order::ParameterValue;
+  use crate::stream::Disposition;
   use crate::tools::rational::Rational;
   use serde_json;
   use std::fs::File;
@@ -513,25 +658,32 @@ fn parse_sample_video_encoding_graph() {
         OutputStream {
           label: Some("output1".to_string()),
           codec: "mpeg2video".to_string(),
-          parameters: output_video_params
+          parameters: output_video_params,
+          disposition: Disposition::default(),
+          language: None,
         },
         OutputStream {
           label: Some("audio_output1".to_string()),
           codec: "pcm_s24le".to_string(),
-          parameters: output_audio1_params
+          parameters: output_audio1_params,
+          disposition: Disposition::default(),
+          language: None,
         },
         OutputStream {
           label: Some("audio_output2".to_string()),
           codec: "pcm_s24le".to_string(),
-          parameters: output_audio2_params
+          parameters: output_audio2_params,
+          disposition: Disposition::default(),
+          language: None,
         }
-      ]
+      ],
+      chapters: vec![],
     }],
     order.outputs
   );
 
   assert_eq!(
-    vec![
+    Graph::Filters(vec![
       Filter {
         name: "idet".to_string(),
         label: Some("idet_filter".to_string()),
@@ -575,7 +727,232 @@ fn parse_sample_video_encoding_graph() {
           stream_label: "audio_output2".to_string()
         }])
       }
-    ],
+    ]),
     order.graph
   );
 }
+
+#[test]
+fn process_subtitle_conversion() {
+  use std::fs;
+
+  let message = fs::read_to_string("tests/subtitle.json").unwrap();
+  let mut order = Order::new_parse(&message).unwrap();
+  let path = std::env::temp_dir().join("stainless_ffmpeg_subtitle.vtt");
+  order.outputs[0].path = Some(path.to_string_lossy().to_string());
+  order.outputs[0].streams[0]
+    .parameters
+    .insert("time_offset".to_string(), ParameterValue::Int64(2000));
+
+  order.setup().unwrap();
+  order.process().unwrap();
+  // the trailer is written by process at the end of the inputs
+  drop(order);
+
+  let content = fs::read_to_string(&path).unwrap();
+  assert!(content.starts_with("WEBVTT"));
+  assert!(content.contains("00:00:05.000 --> 00:00:17.000"));
+  assert!(content.contains("And it's going to happen"));
+}
+
+/// Run an order fixture with its first output written into a temporary file, and return its path.
+#[cfg(test)]
+fn run_order_fixture(fixture: &str, output_name: &str) -> String {
+  let message = std::fs::read_to_string(fixture).unwrap();
+  let mut order = Order::new_parse(&message).unwrap();
+  let path = std::env::temp_dir().join(output_name);
+  let path = path.to_string_lossy().to_string();
+  order.outputs[0].path = Some(path.clone());
+
+  order.setup().unwrap();
+  order.process().unwrap();
+  path
+}
+
+/// Start time, end time in seconds and number of packets of a stream.
+#[cfg(test)]
+fn get_stream_packets_range(path: &str, stream_index: isize) -> (f64, f64, usize) {
+  use crate::format_context::FormatContext;
+  use crate::tools::rational::Rational;
+
+  let mut format = FormatContext::new(path).unwrap();
+  format.open_input().unwrap();
+  let time_base = Rational::from(unsafe { (*format.get_stream(stream_index)).time_base });
+
+  let (mut start, mut end, mut count) = (i64::MAX, i64::MIN, 0);
+  while let Ok(packet) = format.next_packet() {
+    if packet.get_stream_index() == stream_index {
+      let pts = packet.get_pts().unwrap();
+      start = start.min(pts);
+      end = end.max(pts + packet.get_duration());
+      count += 1;
+    }
+  }
+  (
+    start as f64 * time_base.to_f64(),
+    end as f64 * time_base.to_f64(),
+    count,
+  )
+}
+
+#[test]
+fn process_stream_copy() {
+  use crate::format_context::FormatContext;
+  use ffmpeg_sys_next::AVMediaType;
+
+  let path = run_order_fixture("tests/stream_copy.json", "stainless_ffmpeg_stream_copy.mov");
+
+  let mut format = FormatContext::new(&path).unwrap();
+  format.open_input().unwrap();
+  assert_eq!(format.get_nb_streams(), 1);
+  assert_eq!(format.get_stream_type(0), AVMediaType::AVMEDIA_TYPE_AUDIO);
+
+  // every packet is copied, with the same timing
+  let (input_start, input_end, input_count) =
+    get_stream_packets_range("tests/PAL_1080i_MPEG_XDCAM-HD_colorbar.mxf", 1);
+  let (start, end, count) = get_stream_packets_range(&path, 0);
+  assert_eq!(count, input_count);
+  assert!((end - start - (input_end - input_start)).abs() < 0.001);
+}
+
+#[test]
+fn process_stream_copy_with_encoding() {
+  let path = run_order_fixture(
+    "tests/stream_copy_encoding.json",
+    "stainless_ffmpeg_stream_copy_encoding.mkv",
+  );
+
+  // the copied video and the encoded audio stay aligned, within a frame
+  let (video_start, video_end, video_count) = get_stream_packets_range(&path, 0);
+  let (audio_start, audio_end, _) = get_stream_packets_range(&path, 1);
+  let (_, _, input_count) =
+    get_stream_packets_range("tests/PAL_1080i_MPEG_XDCAM-HD_colorbar.mxf", 0);
+  assert_eq!(video_count, input_count);
+  assert!((video_start - audio_start).abs() < 0.04);
+  assert!((video_end - audio_end).abs() < 0.04);
+}
+
+/// Peak sample and number of samples of an audio stream, decoded as packed 32-bit samples.
+#[cfg(test)]
+fn get_audio_peak(path: &str, stream_index: isize) -> (i64, usize) {
+  use crate::audio_decoder::AudioDecoder;
+  use crate::format_context::FormatContext;
+
+  let mut format = FormatContext::new(path).unwrap();
+  format.open_input().unwrap();
+  let decoder = AudioDecoder::new("audio".to_string(), &format, stream_index).unwrap();
+
+  let (mut peak, mut nb_samples) = (0, 0);
+  loop {
+    let (frames, ended) = match format.next_packet() {
+      Ok(packet) if packet.get_stream_index() == stream_index => {
+        (decoder.decode(&packet).unwrap(), false)
+      }
+      Ok(_) => continue,
+      Err(_) => (decoder.drain().unwrap(), true),
+    };
+    for frame in frames {
+      let samples = frame.get_interleaved_samples::<i32>().unwrap();
+      for sample in samples {
+        peak = peak.max(i64::from(*sample).abs());
+      }
+      nb_samples += frame.get_nb_samples();
+    }
+    if ended {
+      break;
+    }
+  }
+  (peak, nb_samples)
+}
+
+/// Set up an order with its first output written into a temporary file, and return the error.
+#[cfg(test)]
+fn get_setup_error(mut order: Order) -> String {
+  let path = std::env::temp_dir().join("stainless_ffmpeg_setup_error.wav");
+  order.outputs[0].path = Some(path.to_string_lossy().to_string());
+  match order.setup() {
+    Err(Error::Configuration(message)) => message,
+    result => panic!("unexpected setup result: {result:?}"),
+  }
+}
+
+#[test]
+fn process_filter_links() {
+  let path = run_order_fixture(
+    "tests/filter_links.json",
+    "stainless_ffmpeg_filter_links.wav",
+  );
+
+  // amix averages the direct and the attenuated branches: (1 + 0.5) / 2
+  let (input_peak, input_samples) = get_audio_peak("tests/PAL_1080i_MPEG_XDCAM-HD_colorbar.mxf", 1);
+  let (peak, nb_samples) = get_audio_peak(&path, 0);
+  assert!(input_peak > 0);
+  assert_eq!(nb_samples, input_samples);
+  assert!((peak as f64 / input_peak as f64 - 0.75).abs() < 0.01);
+}
+
+#[test]
+fn process_filter_links_errors() {
+  use crate::order::filter_output::FilterOutput;
+
+  let message = std::fs::read_to_string("tests/filter_links.json").unwrap();
+  let filter_input = |label: &str| FilterInput {
+    kind: InputKind::Filter,
+    stream_label: label.to_string(),
+  };
+  let filter_output = |label: &str| FilterOutput {
+    stream_label: label.to_string(),
+  };
+
+  // no filter outputs "missing"
+  let mut order = Order::new_parse(&message).unwrap();
+  if let Graph::Filters(filters) = &mut order.graph {
+    filters[1]
+      .inputs
+      .as_mut()
+      .unwrap()
+      .push(filter_input("missing"));
+  }
+  assert!(get_setup_error(order).contains("no filter output labelled missing"));
+
+  // "dry" feeds both amix and volume
+  let mut order = Order::new_parse(&message).unwrap();
+  if let Graph::Filters(filters) = &mut order.graph {
+    filters[0].outputs = Some(vec![filter_output("dry")]);
+    filters[2].inputs = Some(vec![filter_input("dry")]);
+  }
+  assert!(get_setup_error(order).contains("or already connected"));
+
+  // two asplit outputs labelled "dry"
+  let mut order = Order::new_parse(&message).unwrap();
+  if let Graph::Filters(filters) = &mut order.graph {
+    filters[0].outputs = Some(vec![filter_output("dry"), filter_output("dry")]);
+  }
+  assert!(get_setup_error(order).contains("filter output dry is declared twice"));
+}
+
+#[test]
+fn process_filter_complex() {
+  let message = std::fs::read_to_string("tests/filter_complex.json").unwrap();
+  let order = Order::new_parse(&message).unwrap();
+  assert_eq!(
+    order.graph,
+    Graph::Description("[my_audio1]volume=0.5,aresample=48000[output1]".to_string())
+  );
+
+  let path = run_order_fixture(
+    "tests/filter_complex.json",
+    "stainless_ffmpeg_filter_complex.wav",
+  );
+  let (input_peak, input_samples) = get_audio_peak("tests/PAL_1080i_MPEG_XDCAM-HD_colorbar.mxf", 1);
+  let (peak, nb_samples) = get_audio_peak(&path, 0);
+  assert!(input_peak > 0);
+  assert_eq!(nb_samples, input_samples);
+  assert!((peak as f64 / input_peak as f64 - 0.5).abs() < 0.01);
+
+  let mut order = Order::new_parse(&message).unwrap();
+  order.graph = Graph::from("[my_audio1]volume=0.5[unknown]");
+  let error = get_setup_error(order);
+  assert!(error.starts_with("Unconnected filter graph pads"));
+  assert!(error.contains("unknown"));
+}
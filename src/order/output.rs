@@ -1,6 +1,12 @@
-use crate::order::{output_kind::OutputKind, parameters::ParameterValue};
+pub use crate::channel_layout::ChannelLayout;
+use crate::{
+  error::Error,
+  order::{chapter::Chapter, output_kind::OutputKind, parameters::ParameterValue},
+  stream::Disposition,
+  tools,
+};
 use ffmpeg_sys_next::*;
-use std::{collections::HashMap, convert::TryFrom, str::FromStr};
+use std::{collections::HashMap, convert::TryFrom, ffi::CString, str::FromStr};
 
 #[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
 pub enum SampleFormat {
@@ -27,7 +33,7 @@ pub enum SampleFormat {
 }
 
 impl TryFrom<i32> for SampleFormat {
-  type Error = String;
+  type Error = Error;
   fn try_from(value: i32) -> Result<Self, Self::Error> {
     match value {
       0 => Ok(SampleFormat::Unsigned8),
@@ -40,13 +46,15 @@ impl TryFrom<i32> for SampleFormat {
       7 => Ok(SampleFormat::Signed32Planar),
       8 => Ok(SampleFormat::FloatPlanar),
       9 => Ok(SampleFormat::DoublePlanar),
-      _ => Err(format!("'{value}' is not a valid value for SampleFormat")),
+      _ => Err(Error::Configuration(format!(
+        "'{value}' is not a valid value for SampleFormat"
+      ))),
     }
   }
 }
 
 impl FromStr for SampleFormat {
-  type Err = String;
+  type Err = Error;
   fn from_str(s: &str) -> Result<Self, Self::Err> {
     match s {
       "s8" => Ok(SampleFormat::Unsigned8),
@@ -59,7 +67,9 @@ impl FromStr for SampleFormat {
       "floatp" => Ok(SampleFormat::FloatPlanar),
       "double" => Ok(SampleFormat::Double),
       "doublep" => Ok(SampleFormat::DoublePlanar),
-      _ => Err(format!("'{s}' is not a valid value for SampleFormat")),
+      _ => Err(Error::Configuration(format!(
+        "'{s}' is not a valid value for SampleFormat"
+      ))),
     }
   }
 }
@@ -81,43 +91,129 @@ impl From<SampleFormat> for AVSampleFormat {
   }
 }
 
-#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
-pub enum PixelFormat {
-  #[serde(rename = "yuv420p")]
-  Yuv420p,
-  #[serde(rename = "yuv422p")]
-  Yuv422p,
-  #[serde(rename = "rgb24")]
-  Rgb24,
-  #[serde(rename = "rgb48be")]
-  Rgb48be,
-  #[serde(rename = "rgb48le")]
-  Rgb48le,
+/// Any pixel format known by ffmpeg, named as in the ffmpeg command line
+/// ("yuv420p", "yuv422p10le", "v210", "p010le", "gbrp", "yuva444p"...).
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct PixelFormat(AVPixelFormat);
+
+impl PixelFormat {
+  // formats of the former `PixelFormat` enum
+  pub const YUV420P: PixelFormat = PixelFormat(AVPixelFormat::AV_PIX_FMT_YUV420P);
+  pub const YUV422P: PixelFormat = PixelFormat(AVPixelFormat::AV_PIX_FMT_YUV422P);
+  pub const RGB24: PixelFormat = PixelFormat(AVPixelFormat::AV_PIX_FMT_RGB24);
+  pub const RGB48BE: PixelFormat = PixelFormat(AVPixelFormat::AV_PIX_FMT_RGB48BE);
+  pub const RGB48LE: PixelFormat = PixelFormat(AVPixelFormat::AV_PIX_FMT_RGB48LE);
+
+  pub fn get_name(&self) -> String {
+    unsafe { tools::to_string(av_get_pix_fmt_name(self.0)) }
+  }
+
+  /// Largest number of bits per component.
+  pub fn get_bit_depth(&self) -> u32 {
+    self
+      .get_components()
+      .iter()
+      .map(|component| component.depth as u32)
+      .max()
+      .unwrap_or(0)
+  }
+
+  /// Log2 of the horizontal and vertical chroma subsampling, e.g. (1, 1) for 4:2:0.
+  pub fn get_chroma_shift(&self) -> (u8, u8) {
+    let descriptor = self.get_descriptor();
+    (descriptor.log2_chroma_w, descriptor.log2_chroma_h)
+  }
+
+  pub fn get_nb_components(&self) -> usize {
+    self.get_descriptor().nb_components as usize
+  }
+
+  pub fn get_nb_planes(&self) -> usize {
+    unsafe { av_pix_fmt_count_planes(self.0).max(0) as usize }
+  }
+
+  pub fn has_alpha(&self) -> bool {
+    self.has_flag(AV_PIX_FMT_FLAG_ALPHA)
+  }
+
+  pub fn is_rgb(&self) -> bool {
+    self.has_flag(AV_PIX_FMT_FLAG_RGB)
+  }
+
+  pub fn is_planar(&self) -> bool {
+    self.has_flag(AV_PIX_FMT_FLAG_PLANAR)
+  }
+
+  pub fn is_big_endian(&self) -> bool {
+    self.has_flag(AV_PIX_FMT_FLAG_BE)
+  }
+
+  /// Frames stored in GPU memory, such as "cuda" or "vaapi".
+  pub fn is_hardware(&self) -> bool {
+    self.has_flag(AV_PIX_FMT_FLAG_HWACCEL)
+  }
+
+  fn has_flag(&self, flag: u32) -> bool {
+    self.get_descriptor().flags & u64::from(flag) != 0
+  }
+
+  fn get_components(&self) -> &[AVComponentDescriptor] {
+    let descriptor = self.get_descriptor();
+    &descriptor.comp[..(descriptor.nb_components as usize).min(descriptor.comp.len())]
+  }
+
+  fn get_descriptor(&self) -> &'static AVPixFmtDescriptor {
+    // never null, the format is checked on creation
+    unsafe { &*av_pix_fmt_desc_get(self.0) }
+  }
 }
 
 impl std::str::FromStr for PixelFormat {
-  type Err = String;
+  type Err = Error;
   fn from_str(s: &str) -> Result<Self, Self::Err> {
-    match s {
-      "yuv420p" => Ok(PixelFormat::Yuv420p),
-      "yuv422p" => Ok(PixelFormat::Yuv422p),
-      "rgb24" => Ok(PixelFormat::Rgb24),
-      "rgb48be" => Ok(PixelFormat::Rgb48be),
-      "rgb48le" => Ok(PixelFormat::Rgb48le),
-      _ => Err(format!("'{s}' is not a valid value for PixelFormat")),
+    let error = || Error::Configuration(format!("'{s}' is not a valid value for PixelFormat"));
+    let name = CString::new(s).map_err(|_| error())?;
+    let format = unsafe { av_get_pix_fmt(name.as_ptr()) };
+    PixelFormat::try_from(format).map_err(|_| error())
+  }
+}
+
+impl TryFrom<AVPixelFormat> for PixelFormat {
+  type Error = Error;
+  fn try_from(format: AVPixelFormat) -> Result<Self, Self::Error> {
+    if format == AVPixelFormat::AV_PIX_FMT_NONE || unsafe { av_pix_fmt_desc_get(format) }.is_null()
+    {
+      return Err(Error::Configuration(format!(
+        "{format:?} is not a valid PixelFormat"
+      )));
     }
+    Ok(PixelFormat(format))
+  }
+}
+
+impl TryFrom<String> for PixelFormat {
+  type Error = Error;
+  fn try_from(value: String) -> Result<Self, Self::Error> {
+    value.parse()
+  }
+}
+
+impl From<PixelFormat> for String {
+  fn from(format: PixelFormat) -> String {
+    format.get_name()
   }
 }
 
 impl From<PixelFormat> for AVPixelFormat {
   fn from(format: PixelFormat) -> AVPixelFormat {
-    match format {
-      PixelFormat::Yuv420p => AVPixelFormat::AV_PIX_FMT_YUV420P,
-      PixelFormat::Yuv422p => AVPixelFormat::AV_PIX_FMT_YUV422P,
-      PixelFormat::Rgb24 => AVPixelFormat::AV_PIX_FMT_RGB24,
-      PixelFormat::Rgb48be => AVPixelFormat::AV_PIX_FMT_RGB48BE,
-      PixelFormat::Rgb48le => AVPixelFormat::AV_PIX_FMT_RGB48LE,
-    }
+    format.0
+  }
+}
+
+impl std::fmt::Display for PixelFormat {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    write!(f, "{}", self.get_name())
   }
 }
 
@@ -142,7 +238,7 @@ pub enum Colorspace {
 }
 
 impl std::str::FromStr for Colorspace {
-  type Err = String;
+  type Err = Error;
   fn from_str(s: &str) -> Result<Self, Self::Err> {
     match s {
       "rgb" => Ok(Colorspace::Rgb),
@@ -153,7 +249,9 @@ impl std::str::FromStr for Colorspace {
       "bt709" => Ok(Colorspace::Bt709),
       "bt2020_ncl" => Ok(Colorspace::Bt2020Ncl),
       "bt2020_cl" => Ok(Colorspace::Bt2020Cl),
-      _ => Err(format!("'{s}' is not a valid value for Colorspace")),
+      _ => Err(Error::Configuration(format!(
+        "'{s}' is not a valid value for Colorspace"
+      ))),
     }
   }
 }
@@ -182,12 +280,14 @@ pub enum ColorRange {
 }
 
 impl std::str::FromStr for ColorRange {
-  type Err = String;
+  type Err = Error;
   fn from_str(s: &str) -> Result<Self, Self::Err> {
     match s {
       "head" => Ok(ColorRange::Head),
       "full" => Ok(ColorRange::Full),
-      _ => Err(format!("'{s}' is not a valid value for ColorRange")),
+      _ => Err(Error::Configuration(format!(
+        "'{s}' is not a valid value for ColorRange"
+      ))),
     }
   }
 }
@@ -201,38 +301,30 @@ impl From<ColorRange> for AVColorRange {
   }
 }
 
-#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
-pub enum ChannelLayout {
-  #[serde(rename = "mono")]
-  Mono,
-  #[serde(rename = "stereo")]
-  Stereo,
-  #[serde(rename = "5.1")]
-  Multi5_1,
-  #[serde(rename = "7.1")]
-  Multi7_1,
+/// Video timestamps policy of an encoder, as the `-vsync` option of ffmpeg.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+pub enum VideoSync {
+  /// Keep the timestamps of the frames, without duplicating or dropping any frame.
+  #[serde(rename = "passthrough")]
+  Passthrough,
+  /// Duplicate and drop frames to output exactly one frame per `1/frame_rate`.
+  #[serde(rename = "cfr")]
+  Cfr,
+  /// Keep the timestamps of the frames, and drop the frames with the same timestamp.
+  #[serde(rename = "vfr")]
+  Vfr,
 }
 
-impl std::str::FromStr for ChannelLayout {
-  type Err = String;
+impl std::str::FromStr for VideoSync {
+  type Err = Error;
   fn from_str(s: &str) -> Result<Self, Self::Err> {
     match s {
-      "mono" => Ok(ChannelLayout::Mono),
-      "stereo" => Ok(ChannelLayout::Stereo),
-      "5.1" => Ok(ChannelLayout::Multi5_1),
-      "7.1" => Ok(ChannelLayout::Multi7_1),
-      _ => Err(format!("'{s}' is not a valid value for ChannelLayout")),
-    }
-  }
-}
-
-impl From<ChannelLayout> for u64 {
-  fn from(layout: ChannelLayout) -> u64 {
-    match layout {
-      ChannelLayout::Mono => AV_CH_LAYOUT_MONO,
-      ChannelLayout::Stereo => AV_CH_LAYOUT_STEREO,
-      ChannelLayout::Multi5_1 => AV_CH_LAYOUT_5POINT1,
-      ChannelLayout::Multi7_1 => AV_CH_LAYOUT_7POINT1,
+      "passthrough" => Ok(VideoSync::Passthrough),
+      "cfr" => Ok(VideoSync::Cfr),
+      "vfr" => Ok(VideoSync::Vfr),
+      _ => Err(Error::Configuration(format!(
+        "'{s}' is not a valid value for VideoSync"
+      ))),
     }
   }
 }
@@ -242,6 +334,20 @@ pub struct OutputStream {
   pub label: Option<String>,
   pub codec: String,
   pub parameters: HashMap<String, ParameterValue>,
+  #[serde(default)]
+  pub disposition: Disposition,
+  /// ISO 639-1 or ISO 639-2 code, written as ISO 639-2/B.
+  #[serde(default)]
+  pub language: Option<String>,
+}
+
+impl OutputStream {
+  /// Codec of the streams copied from an input without decoding, matched by label.
+  pub const COPY_CODEC: &'static str = "copy";
+
+  pub fn is_copy(&self) -> bool {
+    self.codec == OutputStream::COPY_CODEC
+  }
 }
 
 #[derive(Debug, Deserialize, PartialEq)]
@@ -255,4 +361,37 @@ pub struct Output {
   pub stream: Option<String>,
   #[serde(default)]
   pub streams: Vec<OutputStream>,
+  /// Chapter markers written to containers supporting them, such as MKV and MP4.
+  #[serde(default)]
+  pub chapters: Vec<Chapter>,
+}
+
+#[test]
+fn pixel_formats() {
+  let format: PixelFormat = "yuv422p10le".parse().unwrap();
+  assert_eq!(format.get_bit_depth(), 10);
+  assert_eq!(format.get_chroma_shift(), (1, 0));
+  assert_eq!(format.get_nb_planes(), 3);
+  assert!(!format.has_alpha());
+  assert_eq!(
+    AVPixelFormat::from(format),
+    AVPixelFormat::AV_PIX_FMT_YUV422P10LE
+  );
+
+  let format: PixelFormat = "yuva444p12le".parse().unwrap();
+  assert_eq!(format.get_nb_planes(), 4);
+  assert!(format.has_alpha());
+
+  let format: PixelFormat = "gbrp".parse().unwrap();
+  assert!(format.is_rgb() && format.is_planar());
+  assert_eq!(format.to_string(), "gbrp");
+  assert_eq!("yuv420p".parse::<PixelFormat>(), Ok(PixelFormat::YUV420P));
+
+  assert_eq!("v210".parse::<PixelFormat>().unwrap().get_bit_depth(), 10);
+  assert_eq!(
+    "p010le".parse::<PixelFormat>().unwrap().get_chroma_shift(),
+    (1, 1)
+  );
+  assert!("yuv421p".parse::<PixelFormat>().is_err());
+  assert!(PixelFormat::try_from(AVPixelFormat::AV_PIX_FMT_NONE).is_err());
 }
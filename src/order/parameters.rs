@@ -1,7 +1,12 @@
-use crate::{tools, tools::rational::Rational};
+use crate::{channel_layout::ChannelLayout, error::Error, tools, tools::rational::Rational};
 use ffmpeg_sys_next::*;
-use libc::{c_char, c_void};
-use std::{collections::HashMap, ffi::CString, hash::BuildHasher};
+use libc::c_void;
+use std::{
+  collections::HashMap,
+  ffi::CString,
+  hash::BuildHasher,
+  ptr::{null, null_mut},
+};
 
 #[derive(Clone, Debug, Deserialize, PartialEq)]
 #[serde(untagged)]
@@ -11,13 +16,105 @@ pub enum ParameterValue {
   Float(f64),
   Rational(Rational),
   String(String),
-  ChannelLayout(u64),
+  ChannelLayout(ChannelLayout),
+}
+
+/// Options passed to `avcodec_open2` or `avformat_write_header`,
+/// which leave in the dictionary the options they did not consume.
+pub(crate) struct Dictionary {
+  pub(crate) dictionary: *mut AVDictionary,
+}
+
+impl Dictionary {
+  pub(crate) fn new<'a, I>(parameters: I) -> Result<Self, Error>
+  where
+    I: IntoIterator<Item = (&'a String, &'a ParameterValue)>,
+  {
+    let mut dictionary = Dictionary {
+      dictionary: null_mut(),
+    };
+    for (key, value) in parameters {
+      let key_str = CString::new(key.as_str())
+        .map_err(|_| Error::Configuration(format!("Invalid option name {key:?}")))?;
+      let value_str = CString::new(value.to_option_string())
+        .map_err(|_| Error::Configuration(format!("Invalid value for option {key}")))?;
+      unsafe {
+        check_result!(av_dict_set(
+          &mut dictionary.dictionary,
+          key_str.as_ptr(),
+          value_str.as_ptr(),
+          0
+        ));
+      }
+    }
+    Ok(dictionary)
+  }
+
+  /// Keys of the options left in the dictionary, sorted.
+  pub(crate) fn get_keys(&self) -> Vec<String> {
+    let mut keys = vec![];
+    unsafe {
+      let empty = CString::new("").unwrap();
+      let mut entry = null();
+      loop {
+        entry = av_dict_get(
+          self.dictionary,
+          empty.as_ptr(),
+          entry,
+          AV_DICT_IGNORE_SUFFIX as i32,
+        );
+        if entry.is_null() {
+          break;
+        }
+        keys.push(tools::to_string((*entry).key));
+      }
+    }
+    keys.sort();
+    keys
+  }
+
+  /// Report the options not consumed by the codec or the format as an error.
+  pub(crate) fn check_consumed(&self, context: &str) -> Result<(), Error> {
+    let keys = self.get_keys();
+    if keys.is_empty() {
+      return Ok(());
+    }
+    Err(Error::Configuration(format!(
+      "Unknown options for {context}: {}",
+      keys.join(", ")
+    )))
+  }
+}
+
+impl Drop for Dictionary {
+  fn drop(&mut self) {
+    unsafe {
+      av_dict_free(&mut self.dictionary);
+    }
+  }
+}
+
+/// Open an encoder, with the parameters not in `known_parameters` as codec options.
+/// Options the codec does not consume are an error.
+pub(crate) unsafe fn open_encoder<S: BuildHasher>(
+  codec_context: *mut AVCodecContext,
+  codec: *const AVCodec,
+  parameters: &HashMap<String, ParameterValue, S>,
+  known_parameters: &[&str],
+) -> Result<(), Error> {
+  let mut options = Dictionary::new(
+    parameters
+      .iter()
+      .filter(|(key, _)| !known_parameters.contains(&key.as_str())),
+  )?;
+  check_result!(avcodec_open2(codec_context, codec, &mut options.dictionary));
+  options.check_consumed(&format!("codec {}", tools::to_string((*codec).name)))
 }
 
 pub fn set_parameters<S: BuildHasher>(
   context: *mut c_void,
   parameters: &HashMap<String, ParameterValue, S>,
-) -> Result<(), String> {
+) -> Result<(), Error> {
   for (key, value) in parameters {
     value.set(key, context)?;
   }
@@ -25,7 +122,7 @@ pub fn set_parameters<S: BuildHasher>(
 }
 
 impl ParameterValue {
-  pub fn set(&self, key: &str, context: *mut c_void) -> Result<(), String> {
+  pub fn set(&self, key: &str, context: *mut c_void) -> Result<(), Error> {
     match self {
       ParameterValue::Bool(data) => self.set_int_parameter(context, key, *data as i64),
       ParameterValue::Int64(data) => self.set_int_parameter(context, key, *data),
@@ -34,35 +131,41 @@ impl ParameterValue {
         self.set_rational_parameter(context, key, data.num, data.den)
       }
       ParameterValue::String(data) => self.set_str_parameter(context, key, data),
-      ParameterValue::ChannelLayout(data) => {
-        let mut ch_layout = [0; 64];
-        unsafe {
-          av_get_channel_layout_string(ch_layout.as_mut_ptr(), 64, 0, *data);
-        }
-        self.set_parameter(context, key, ch_layout.as_ptr())
-      }
+      ParameterValue::ChannelLayout(data) => self.set_channel_layout_parameter(context, key, data),
     }
   }
 
-  fn set_parameter(
+  /// Value as written in an `AVDictionary`, parsed by ffmpeg like the command line options.
+  pub fn to_option_string(&self) -> String {
+    match self {
+      ParameterValue::Bool(data) => (*data as i64).to_string(),
+      ParameterValue::Int64(data) => data.to_string(),
+      ParameterValue::Float(data) => data.to_string(),
+      ParameterValue::Rational(data) => format!("{}/{}", data.num, data.den),
+      ParameterValue::String(data) => data.clone(),
+      ParameterValue::ChannelLayout(data) => data.to_string(),
+    }
+  }
+
+  fn set_channel_layout_parameter(
     &self,
     context: *mut c_void,
     key: &str,
-    value: *const c_char,
-  ) -> Result<(), String> {
+    value: &ChannelLayout,
+  ) -> Result<(), Error> {
     let key_str = CString::new(key).unwrap();
     unsafe {
-      check_result!(av_opt_set(
+      check_result!(av_opt_set_chlayout(
         context,
         key_str.as_ptr(),
-        value,
+        value.as_ptr(),
         AV_OPT_SEARCH_CHILDREN
       ));
     }
     Ok(())
   }
 
-  fn set_str_parameter(&self, context: *mut c_void, key: &str, value: &str) -> Result<(), String> {
+  fn set_str_parameter(&self, context: *mut c_void, key: &str, value: &str) -> Result<(), Error> {
     let key_str = CString::new(key).unwrap();
     let value_str = CString::new(value).unwrap();
     unsafe {
@@ -76,7 +179,7 @@ impl ParameterValue {
     Ok(())
   }
 
-  fn set_int_parameter(&self, context: *mut c_void, key: &str, value: i64) -> Result<(), String> {
+  fn set_int_parameter(&self, context: *mut c_void, key: &str, value: i64) -> Result<(), Error> {
     let key_str = CString::new(key).unwrap();
     unsafe {
       check_result!(av_opt_set_int(
@@ -89,7 +192,7 @@ impl ParameterValue {
     Ok(())
   }
 
-  fn set_float_parameter(&self, context: *mut c_void, key: &str, value: f64) -> Result<(), String> {
+  fn set_float_parameter(&self, context: *mut c_void, key: &str, value: f64) -> Result<(), Error> {
     let key_str = CString::new(key).unwrap();
     unsafe {
       check_result!(av_opt_set_double(
@@ -108,7 +211,7 @@ impl ParameterValue {
     key: &str,
     num: i32,
     den: i32,
-  ) -> Result<(), String> {
+  ) -> Result<(), Error> {
     let key_str = CString::new(key).unwrap();
     let rational = AVRational { num, den };
 
@@ -123,3 +226,31 @@ impl ParameterValue {
     Ok(())
   }
 }
+
+#[test]
+fn parameters_dictionary() {
+  let mut parameters = HashMap::new();
+  parameters.insert("crf".to_string(), ParameterValue::Int64(18));
+  parameters.insert(
+    "preset".to_string(),
+    ParameterValue::String("slow".to_string()),
+  );
+  parameters.insert(
+    "aspect".to_string(),
+    ParameterValue::Rational(Rational::new(16, 9)),
+  );
+
+  let dictionary = Dictionary::new(&parameters).unwrap();
+  assert_eq!(dictionary.get_keys(), vec!["aspect", "crf", "preset"]);
+  assert_eq!(
+    dictionary.check_consumed("codec libx264"),
+    Err(Error::Configuration(
+      "Unknown options for codec libx264: aspect, crf, preset".to_string()
+    ))
+  );
+  assert_eq!(ParameterValue::Bool(true).to_option_string(), "1");
+  assert_eq!(
+    ParameterValue::Rational(Rational::new(16, 9)).to_option_string(),
+    "16/9"
+  );
+}
@@ -1,17 +1,239 @@
-use ffmpeg_sys_next::{av_packet_free, AVPacket};
+use crate::error::Error;
+use crate::tools;
+use ffmpeg_sys_next::*;
+use std::slice;
 
 pub struct Packet {
   pub name: Option<String>,
   pub packet: *mut AVPacket,
 }
 
+/// A side data entry attached to a packet.
+#[derive(Debug)]
+pub struct PacketSideData<'a> {
+  pub kind: AVPacketSideDataType,
+  pub name: String,
+  pub data: &'a [u8],
+}
+
 impl Packet {
+  /// Allocate an empty packet, to be filled by an encoder or a demuxer.
+  pub fn new() -> Result<Self, Error> {
+    let packet = unsafe { av_packet_alloc() };
+    if packet.is_null() {
+      return Err(Error::from_av_error(AVERROR(ENOMEM)));
+    }
+    Ok(Packet { name: None, packet })
+  }
+
+  /// New reference on the same data, with a copy of the properties.
+  pub fn try_clone(&self) -> Result<Self, Error> {
+    let packet = unsafe { av_packet_clone(self.packet) };
+    if packet.is_null() {
+      return Err(Error::from_av_error(AVERROR(ENOMEM)));
+    }
+    Ok(Packet {
+      name: self.name.clone(),
+      packet,
+    })
+  }
+
   pub fn get_stream_index(&self) -> isize {
     if self.packet.is_null() {
       return 0;
     }
     unsafe { (*self.packet).stream_index as isize }
   }
+
+  pub fn set_stream_index(&mut self, stream_index: isize) {
+    if !self.packet.is_null() {
+      unsafe { (*self.packet).stream_index = stream_index as i32 }
+    }
+  }
+
+  pub fn get_pts(&self) -> Option<i64> {
+    self.get_timestamp(|packet| packet.pts)
+  }
+
+  pub fn set_pts(&mut self, pts: Option<i64>) {
+    if !self.packet.is_null() {
+      unsafe { (*self.packet).pts = pts.unwrap_or(AV_NOPTS_VALUE) }
+    }
+  }
+
+  pub fn get_dts(&self) -> Option<i64> {
+    self.get_timestamp(|packet| packet.dts)
+  }
+
+  pub fn set_dts(&mut self, dts: Option<i64>) {
+    if !self.packet.is_null() {
+      unsafe { (*self.packet).dts = dts.unwrap_or(AV_NOPTS_VALUE) }
+    }
+  }
+
+  /// Duration in the packet time base, 0 when unknown.
+  pub fn get_duration(&self) -> i64 {
+    if self.packet.is_null() {
+      return 0;
+    }
+    unsafe { (*self.packet).duration }
+  }
+
+  pub fn set_duration(&mut self, duration: i64) {
+    if !self.packet.is_null() {
+      unsafe { (*self.packet).duration = duration }
+    }
+  }
+
+  /// Byte position in the input, if known.
+  pub fn get_position(&self) -> Option<i64> {
+    if self.packet.is_null() {
+      return None;
+    }
+    unsafe {
+      if (*self.packet).pos < 0 {
+        None
+      } else {
+        Some((*self.packet).pos)
+      }
+    }
+  }
+
+  pub fn set_position(&mut self, position: Option<i64>) {
+    if !self.packet.is_null() {
+      unsafe { (*self.packet).pos = position.unwrap_or(-1) }
+    }
+  }
+
+  pub fn get_size(&self) -> usize {
+    if self.packet.is_null() {
+      return 0;
+    }
+    unsafe { (*self.packet).size.max(0) as usize }
+  }
+
+  pub fn is_keyframe(&self) -> bool {
+    self.has_flag(AV_PKT_FLAG_KEY as i32)
+  }
+
+  pub fn set_keyframe(&mut self, keyframe: bool) {
+    self.set_flag(AV_PKT_FLAG_KEY as i32, keyframe);
+  }
+
+  pub fn is_corrupt(&self) -> bool {
+    self.has_flag(AV_PKT_FLAG_CORRUPT as i32)
+  }
+
+  pub fn set_corrupt(&mut self, corrupt: bool) {
+    self.set_flag(AV_PKT_FLAG_CORRUPT as i32, corrupt);
+  }
+
+  pub fn is_discard(&self) -> bool {
+    self.has_flag(AV_PKT_FLAG_DISCARD as i32)
+  }
+
+  pub fn set_discard(&mut self, discard: bool) {
+    self.set_flag(AV_PKT_FLAG_DISCARD as i32, discard);
+  }
+
+  pub fn get_data(&self) -> &[u8] {
+    unsafe {
+      if self.packet.is_null() || (*self.packet).data.is_null() {
+        return &[];
+      }
+      slice::from_raw_parts((*self.packet).data, self.get_size())
+    }
+  }
+
+  /// Mutable payload, copied first if the buffer is shared with another packet.
+  pub fn get_data_mut(&mut self) -> Result<&mut [u8], Error> {
+    unsafe {
+      if self.packet.is_null() || (*self.packet).data.is_null() {
+        return Ok(&mut []);
+      }
+      check_result!(av_packet_make_writable(self.packet));
+      Ok(slice::from_raw_parts_mut(
+        (*self.packet).data,
+        self.get_size(),
+      ))
+    }
+  }
+
+  pub fn get_side_data(&self) -> Vec<PacketSideData<'_>> {
+    if self.packet.is_null() {
+      return vec![];
+    }
+    unsafe {
+      let count = (*self.packet).side_data_elems.max(0) as usize;
+      if count == 0 || (*self.packet).side_data.is_null() {
+        return vec![];
+      }
+      slice::from_raw_parts((*self.packet).side_data, count)
+        .iter()
+        .map(|side_data| PacketSideData {
+          kind: side_data.type_,
+          name: tools::to_string(av_packet_side_data_name(side_data.type_)),
+          data: if side_data.data.is_null() {
+            &[]
+          } else {
+            slice::from_raw_parts(side_data.data, side_data.size as usize)
+          },
+        })
+        .collect()
+    }
+  }
+
+  pub fn get_side_data_of(&self, kind: AVPacketSideDataType) -> Option<&[u8]> {
+    if self.packet.is_null() {
+      return None;
+    }
+    unsafe {
+      let mut size = 0;
+      let data = av_packet_get_side_data(self.packet, kind, &mut size);
+      if data.is_null() {
+        None
+      } else {
+        Some(slice::from_raw_parts(data, size as usize))
+      }
+    }
+  }
+
+  /// Convert pts, dts and duration from one time base to another,
+  /// e.g. from the encoder time base to the output stream time base.
+  pub fn rescale_ts<R: Into<AVRational>>(&mut self, from: R, to: R) {
+    if !self.packet.is_null() {
+      unsafe { av_packet_rescale_ts(self.packet, from.into(), to.into()) }
+    }
+  }
+
+  fn get_timestamp(&self, field: fn(&AVPacket) -> i64) -> Option<i64> {
+    if self.packet.is_null() {
+      return None;
+    }
+    let timestamp = unsafe { field(&*self.packet) };
+    if timestamp == AV_NOPTS_VALUE {
+      None
+    } else {
+      Some(timestamp)
+    }
+  }
+
+  fn has_flag(&self, flag: i32) -> bool {
+    !self.packet.is_null() && unsafe { (*self.packet).flags & flag != 0 }
+  }
+
+  fn set_flag(&mut self, flag: i32, enabled: bool) {
+    if self.packet.is_null() {
+      return;
+    }
+    unsafe {
+      if enabled {
+        (*self.packet).flags |= flag;
+      } else {
+        (*self.packet).flags &= !flag;
+      }
+    }
+  }
 }
 
 impl Drop for Packet {
@@ -23,3 +245,25 @@ impl Drop for Packet {
     }
   }
 }
+
+#[test]
+fn packet_accessors() {
+  let mut packet = Packet::new().unwrap();
+  assert_eq!(packet.get_pts(), None);
+  assert_eq!(packet.get_position(), None);
+  assert!(packet.get_data().is_empty());
+
+  packet.set_pts(Some(7200));
+  packet.set_dts(Some(3600));
+  packet.set_duration(3600);
+  packet.set_keyframe(true);
+  packet.rescale_ts(
+    AVRational { num: 1, den: 90000 },
+    AVRational { num: 1, den: 25 },
+  );
+  assert_eq!(packet.get_pts(), Some(2));
+  assert_eq!(packet.get_dts(), Some(1));
+  assert_eq!(packet.get_duration(), 1);
+  assert!(packet.is_keyframe());
+  assert!(!packet.is_corrupt());
+}
@@ -15,12 +15,17 @@
 pub use crate::{
   audio_decoder::AudioDecoder,
   audio_encoder::AudioEncoder,
+  channel_layout::ChannelLayout,
   check_result,
-  filter_graph::FilterGraph,
-  format_context::FormatContext,
-  frame::Frame,
-  order::{output::SampleFormat, Filter, ParameterValue},
-  packet::Packet,
+  error::Error,
+  filter_graph::{AudioInputParameters, FilterGraph, VideoInputParameters},
+  format_context::{FormatContext, SeekMode},
+  frame::{Frame, Plane, Sample},
+  io_context::{IoContext, SharedReader},
+  order::{chapter::Chapter, output::SampleFormat, Filter, ParameterValue},
+  packet::{Packet, PacketSideData},
+  subtitle::{Bitmap, Subtitle, SubtitleRect},
+  subtitle_decoder::SubtitleDecoder,
   tools,
   video_decoder::VideoDecoder,
   video_encoder::VideoEncoder,
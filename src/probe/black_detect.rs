@@ -1,4 +1,5 @@
 use crate::{
+  error::Error,
   format_context::FormatContext,
   order::{
     filter_input::FilterInput, filter_output::FilterOutput, input::Input, input_kind::InputKind,
@@ -6,7 +7,9 @@ use crate::{
     ParameterValue,
   },
   probe::deep::{BlackResult, CheckParameterValue, StreamProbeResult},
+  probe::get_rational_metadata,
   stream::Stream as ContextStream,
+  tools::{rational::Rational, timestamp::Timestamp},
 };
 use ffmpeg_sys_next::AVMediaType;
 use std::collections::HashMap;
@@ -15,7 +18,7 @@ pub fn create_graph(
   filename: &str,
   video_indexes: Vec<u32>,
   params: HashMap<String, CheckParameterValue>,
-) -> Result<Order, String> {
+) -> Result<Order, Error> {
   let mut filters = vec![];
   let mut inputs = vec![];
   let mut outputs = vec![];
@@ -71,6 +74,7 @@ pub fn create_graph(
       path: None,
       streams: vec![],
       parameters: HashMap::new(),
+      chapters: vec![],
     });
   }
 
@@ -78,12 +82,13 @@ pub fn create_graph(
 }
 
 pub fn detect_black_frames(
-  filename: &str,
+  source: &MediaSource,
   streams: &mut [StreamProbeResult],
   video_indexes: Vec<u32>,
   params: HashMap<String, CheckParameterValue>,
 ) {
-  let mut order = create_graph(filename, video_indexes.clone(), params.clone()).unwrap();
+  let mut order = create_graph(&source.filename, video_indexes.clone(), params.clone()).unwrap();
+  source.register(&mut order);
   if let Err(msg) = order.setup() {
     error!("{:?}", msg);
     return;
@@ -97,8 +102,8 @@ pub fn detect_black_frames(
       info!("END OF PROCESS");
       info!("-> {:?} frames processed", results.len());
       let mut duration = 0;
-      let mut time_base = 1.0;
-      let mut frame_rate = 1.0;
+      let mut time_base = Rational::new(1, 1);
+      let mut frame_rate = Rational::new(1, 1);
       let mut black_duration = 0;
       let mut max_duration = None;
       let mut min_duration = None;
@@ -106,7 +111,7 @@ pub fn detect_black_frames(
         max_duration = duration.max;
         min_duration = duration.min;
       }
-      let mut context = FormatContext::new(filename).unwrap();
+      let mut context = source.format_context().unwrap();
       if let Err(msg) = context.open_input() {
         context.close_input();
         error!("{:?}", msg);
@@ -115,12 +120,11 @@ pub fn detect_black_frames(
       for index in 0..context.get_nb_streams() {
         if let Ok(stream) = ContextStream::new(context.get_stream(index as isize)) {
           if let AVMediaType::AVMEDIA_TYPE_VIDEO = context.get_stream_type(index as isize) {
-            let rational_frame_rate = stream.get_frame_rate();
-            frame_rate = rational_frame_rate.num as f32 / rational_frame_rate.den as f32;
-            if let Some(stream_duration) = stream.get_duration() {
-              duration = (stream_duration * 1000.0) as i64;
+            frame_rate = stream.get_frame_rate();
+            if let Some(stream_duration) = stream.get_duration_timestamp() {
+              duration = stream_duration.to_millis();
             } else {
-              duration = (results.len() as f32 / frame_rate * 1000.0) as i64;
+              duration = Timestamp::new(results.len() as i64, frame_rate.invert()).to_millis();
             }
             time_base = stream.get_time_base();
           }
@@ -140,16 +144,14 @@ pub fn detect_black_frames(
               end: duration,
             };
 
-            if let Some(value) = entry_map.get("lavfi.black_start") {
-              black.start =
-                (value.parse::<f32>().unwrap() * time_base / frame_rate * 1000.0) as i64;
+            if let Some(value) = get_rational_metadata(&entry_map, "lavfi.black_start") {
+              black.start = (value * time_base / frame_rate * 1000).to_i64();
               black_duration = black.start;
               detected_black.push(black);
             }
-            if let Some(value) = entry_map.get("lavfi.black_end") {
+            if let Some(value) = get_rational_metadata(&entry_map, "lavfi.black_end") {
               if let Some(last_detect) = detected_black.last_mut() {
-                last_detect.end =
-                  (value.parse::<f32>().unwrap() * time_base / frame_rate * 1000.0) as i64;
+                last_detect.end = (value * time_base / frame_rate * 1000).to_i64();
                 black_duration = last_detect.end - black_duration;
                 if let Some(max) = max_duration {
                   if black_duration > max as i64 {
@@ -1,12 +1,13 @@
-use crate::format_context::FormatContext;
+use crate::error::Error;
 use crate::order::{
   filter_input::FilterInput, filter_output::FilterOutput, input::Input, input_kind::InputKind,
   output::Output, output_kind::OutputKind, stream::Stream,
 };
 use crate::order::{Filter, Order, OutputResult::Entry, ParameterValue};
 use crate::probe::deep::{CheckParameterValue, CropResult, StreamProbeResult};
+use crate::probe::source::MediaSource;
 use crate::stream::Stream as ContextStream;
-use crate::tools::rational::Rational;
+use crate::tools::{rational::Rational, timestamp::Timestamp};
 use ffmpeg_sys_next::AVMediaType;
 use std::collections::HashMap;
 
@@ -16,7 +17,7 @@ pub fn create_graph(
   params: HashMap<String, CheckParameterValue>,
   nb_frames: i64,
   limit: i32,
-) -> Result<Order, String> {
+) -> Result<Order, Error> {
   let mut filters = vec![];
   let mut inputs = vec![];
   let mut outputs = vec![];
@@ -80,6 +81,7 @@ pub fn create_graph(
       path: None,
       streams: vec![],
       parameters: HashMap::new(),
+      chapters: vec![],
     });
   }
 
@@ -87,12 +89,12 @@ pub fn create_graph(
 }
 
 pub fn detect_black_borders(
-  filename: &str,
+  source: &MediaSource,
   streams: &mut [StreamProbeResult],
   video_indexes: Vec<u32>,
   params: HashMap<String, CheckParameterValue>,
 ) {
-  let mut context = FormatContext::new(filename).unwrap();
+  let mut context = source.format_context().unwrap();
   if let Err(msg) = context.open_input() {
     context.close_input();
     error!("{:?}", msg);
@@ -116,7 +118,15 @@ pub fn detect_black_borders(
       }
     }
   }
-  let mut order = create_graph(filename, video_indexes.clone(), params, nb_frames, limit).unwrap();
+  let mut order = create_graph(
+    &source.filename,
+    video_indexes.clone(),
+    params,
+    nb_frames,
+    limit,
+  )
+  .unwrap();
+  source.register(&mut order);
   if let Err(msg) = order.setup() {
     error!("{:?}", msg);
     return;
@@ -129,7 +139,7 @@ pub fn detect_black_borders(
     Ok(results) => {
       info!("END OF PROCESS");
       info!("-> {:?} frames processed", results.len());
-      let mut time_base = 1.0;
+      let mut time_base = Rational::new(1, 1);
       let mut metadata_width = 0;
       let mut metadata_height = 0;
       let mut real_width = 0;
@@ -188,10 +198,9 @@ pub fn detect_black_borders(
               if w_changed || h_changed {
                 crop.width = real_width;
                 crop.height = real_height;
-                crop.pts = (pts.parse::<f32>().unwrap() * time_base * 1000.0) as i64;
-                let real_aspect =
-                  (real_width * pict_size.num) as f32 / (real_height * pict_size.den) as f32;
-                crop.aspect_ratio = real_aspect;
+                crop.pts = Timestamp::new(pts.parse().unwrap(), time_base).to_millis();
+                let real_aspect = Rational::new(real_width, real_height) * pict_size;
+                crop.aspect_ratio = real_aspect.to_f64() as f32;
                 detected_crop.push(crop);
                 w_changed = false;
                 h_changed = false;
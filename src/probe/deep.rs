@@ -1,4 +1,7 @@
-use crate::format_context::FormatContext;
+use crate::error::Error;
+use crate::hdr::{HdrFormat, HdrMetadata};
+use crate::io_context::SharedReader;
+use crate::packet::Packet;
 use crate::probe::black_and_silence::detect_black_and_silence;
 use crate::probe::black_detect::detect_black_frames;
 use crate::probe::crop_detect::detect_black_borders;
@@ -8,16 +11,25 @@ use crate::probe::ocr_detect::detect_ocr;
 use crate::probe::scene_detect::detect_scene;
 use crate::probe::silence_detect::detect_silence;
 use crate::probe::sine_detect::detect_sine;
+use crate::probe::source::MediaSource;
 use crate::stream::Stream;
+use crate::tools::{rational::Rational, timecode::Timecode};
 use ffmpeg_sys_next::*;
 use log::LevelFilter;
-use std::{cmp, collections::HashMap, fmt};
+use std::{
+  cmp,
+  collections::HashMap,
+  fmt,
+  io::{Read, Seek},
+};
 use uuid::Uuid;
 
 #[derive(Debug, Deserialize, PartialEq, Serialize)]
 pub struct DeepProbe {
   #[serde(skip_serializing)]
   filename: String,
+  #[serde(skip)]
+  reader: Option<SharedReader>,
   id: Uuid,
   pub result: Option<DeepProbeResult>,
 }
@@ -107,6 +119,12 @@ pub struct StreamProbeResult {
   pub color_trc: Option<String>,
   pub color_matrix: Option<String>,
   #[serde(skip_serializing_if = "Option::is_none")]
+  pub timecode: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub hdr_format: Option<HdrFormat>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub hdr_metadata: Option<HdrMetadata>,
+  #[serde(skip_serializing_if = "Option::is_none")]
   pub detected_silence: Option<Vec<SilenceResult>>,
   #[serde(skip_serializing_if = "Option::is_none")]
   pub silent_stream: Option<bool>,
@@ -201,6 +219,9 @@ impl fmt::Display for DeepProbeResult {
         "{:30} : {:?}",
         "Matrix coefficients", stream.color_matrix
       )?;
+      writeln!(f, "{:30} : {:?}", "Timecode", stream.timecode)?;
+      writeln!(f, "{:30} : {:?}", "HDR format", stream.hdr_format)?;
+      writeln!(f, "{:30} : {:?}", "HDR metadata", stream.hdr_metadata)?;
       writeln!(
         f,
         "{:30} : {:?}",
@@ -255,6 +276,9 @@ impl StreamProbeResult {
       color_primaries: None,
       color_trc: None,
       color_matrix: None,
+      timecode: None,
+      hdr_format: None,
+      hdr_metadata: None,
       min_packet_size: std::i32::MAX,
       max_packet_size: std::i32::MIN,
       detected_silence: None,
@@ -304,12 +328,27 @@ impl DeepProbe {
   pub fn new(filename: &str, id: Uuid) -> Self {
     DeepProbe {
       filename: filename.to_owned(),
+      reader: None,
+      id,
+      result: None,
+    }
+  }
+
+  /// Probe media read from `reader`. Each detection pass reads it through its own cursor.
+  pub fn new_with_reader<R: Read + Seek + Send + 'static>(
+    filename: &str,
+    reader: R,
+    id: Uuid,
+  ) -> Self {
+    DeepProbe {
+      filename: filename.to_owned(),
+      reader: Some(SharedReader::new(reader)),
       id,
       result: None,
     }
   }
 
-  pub fn process(&mut self, log_level: LevelFilter, check: DeepProbeCheck) -> Result<(), String> {
+  pub fn process(&mut self, log_level: LevelFilter, check: DeepProbeCheck) -> Result<(), Error> {
     let av_log_level = match log_level {
       LevelFilter::Error => AV_LOG_ERROR,
       LevelFilter::Warn => AV_LOG_WARNING,
@@ -323,7 +362,11 @@ impl DeepProbe {
       av_log_set_level(av_log_level);
     }
 
-    let mut context = FormatContext::new(&self.filename).unwrap();
+    let source = MediaSource {
+      filename: self.filename.clone(),
+      reader: self.reader.clone(),
+    };
+    let mut context = source.format_context()?;
     if context.open_input().is_err() {
       self.result = None;
       context.close_input();
@@ -332,28 +375,70 @@ impl DeepProbe {
 
     let mut streams = vec![];
     streams.resize(context.get_nb_streams() as usize, StreamProbeResult::new());
+
+    let mut timecode_searches = HashMap::new();
+    for stream_index in 0..context.get_nb_streams() as usize {
+      if context.get_stream_type(stream_index as isize) != AVMediaType::AVMEDIA_TYPE_VIDEO {
+        continue;
+      }
+      if let Ok(stream) = Stream::new(context.get_stream(stream_index as isize)) {
+        let first_frame = source.decode_first_video_frame(stream_index as isize).ok();
+
+        let mut hdr_metadata = stream.get_hdr_metadata();
+        if let Some(frame) = &first_frame {
+          hdr_metadata.merge(frame.get_hdr_metadata());
+        }
+        streams[stream_index].hdr_format = Some(stream.get_hdr_format(&hdr_metadata));
+        if !hdr_metadata.is_empty() {
+          streams[stream_index].hdr_metadata = Some(hdr_metadata);
+        }
+
+        streams[stream_index].timecode = stream.get_timecode().map(|timecode| timecode.to_string());
+        if let (None, Some(frame_rate)) = (
+          &streams[stream_index].timecode,
+          stream.get_timecode_frame_rate(),
+        ) {
+          // the first frame side data is only used when no packet carries a timecode
+          let frame_timecode = first_frame.and_then(|frame| frame.get_timecode(frame_rate));
+          timecode_searches.insert(stream_index, (frame_rate, frame_timecode));
+        }
+      }
+    }
+
     while let Ok(packet) = context.next_packet() {
-      unsafe {
-        let stream_index = (*packet.packet).stream_index as usize;
-        let packet_size = (*packet.packet).size;
-
-        streams[stream_index].stream_index = stream_index;
-        streams[stream_index].count_packets += 1;
-        streams[stream_index].min_packet_size =
-          cmp::min(packet_size, streams[stream_index].min_packet_size);
-        streams[stream_index].max_packet_size =
-          cmp::max(packet_size, streams[stream_index].max_packet_size);
-
-        if context.get_stream_type(stream_index as isize) == AVMediaType::AVMEDIA_TYPE_VIDEO {
-          if let Ok(stream) = Stream::new(context.get_stream(stream_index as isize)) {
-            streams[stream_index].color_space = stream.get_color_space();
-            streams[stream_index].color_range = stream.get_color_range();
-            streams[stream_index].color_primaries = stream.get_color_primaries();
-            streams[stream_index].color_trc = stream.get_color_trc();
-            streams[stream_index].color_matrix = stream.get_color_matrix();
-          }
+      let stream_index = packet.get_stream_index() as usize;
+      let packet_size = packet.get_size() as i32;
+
+      streams[stream_index].stream_index = stream_index;
+      streams[stream_index].count_packets += 1;
+      streams[stream_index].min_packet_size =
+        cmp::min(packet_size, streams[stream_index].min_packet_size);
+      streams[stream_index].max_packet_size =
+        cmp::max(packet_size, streams[stream_index].max_packet_size);
+
+      if let Some((frame_rate, _)) = timecode_searches.get(&stream_index) {
+        let codec_id = context.get_codec_id(stream_index as isize);
+        if let Some(timecode) = find_timecode(codec_id, &packet, *frame_rate) {
+          // back to the first frame, when the first packets carry no timecode
+          let offset = streams[stream_index].count_packets as i64 - 1;
+          streams[stream_index].timecode = Some((timecode - offset).to_string());
+          timecode_searches.remove(&stream_index);
         }
       }
+
+      if context.get_stream_type(stream_index as isize) == AVMediaType::AVMEDIA_TYPE_VIDEO {
+        if let Ok(stream) = Stream::new(context.get_stream(stream_index as isize)) {
+          streams[stream_index].color_space = stream.get_color_space();
+          streams[stream_index].color_range = stream.get_color_range();
+          streams[stream_index].color_primaries = stream.get_color_primaries();
+          streams[stream_index].color_trc = stream.get_color_trc();
+          streams[stream_index].color_matrix = stream.get_color_matrix();
+        }
+      }
+    }
+
+    for (stream_index, (_, frame_timecode)) in timecode_searches {
+      streams[stream_index].timecode = frame_timecode.map(|timecode| timecode.to_string());
     }
 
     let mut audio_indexes = vec![];
@@ -369,7 +454,7 @@ impl DeepProbe {
 
     if let Some(silence_parameters) = check.silence_detect.clone() {
       detect_silence(
-        &self.filename,
+        &source,
         &mut streams,
         audio_indexes.clone(),
         silence_parameters,
@@ -378,7 +463,7 @@ impl DeepProbe {
 
     if let Some(black_parameters) = check.black_detect.clone() {
       detect_black_frames(
-        &self.filename,
+        &source,
         &mut streams,
         video_indexes.clone(),
         black_parameters,
@@ -398,7 +483,7 @@ impl DeepProbe {
 
     if let Some(crop_parameters) = check.crop_detect {
       detect_black_borders(
-        &self.filename,
+        &source,
         &mut streams,
         video_indexes.clone(),
         crop_parameters,
@@ -407,7 +492,7 @@ impl DeepProbe {
 
     if let Some(scene_parameters) = check.scene_detect {
       detect_scene(
-        &self.filename,
+        &source,
         &mut streams,
         video_indexes.clone(),
         scene_parameters,
@@ -415,12 +500,7 @@ impl DeepProbe {
     }
 
     if let Some(ocr_parameters) = check.ocr_detect {
-      detect_ocr(
-        &self.filename,
-        &mut streams,
-        video_indexes.clone(),
-        ocr_parameters,
-      );
+      detect_ocr(&source, &mut streams, video_indexes.clone(), ocr_parameters);
     }
 
     for index in 0..context.get_nb_streams() {
@@ -431,7 +511,7 @@ impl DeepProbe {
 
     if let Some(loudness_parameters) = check.loudness_detect {
       detect_loudness(
-        &self.filename,
+        &source,
         &mut streams,
         audio_indexes.clone(),
         loudness_parameters,
@@ -440,7 +520,7 @@ impl DeepProbe {
 
     if let Some(dualmono_parameters) = check.dualmono_detect {
       detect_dualmono(
-        &self.filename,
+        &source,
         &mut streams,
         audio_indexes.clone(),
         dualmono_parameters,
@@ -448,7 +528,7 @@ impl DeepProbe {
     }
 
     if let Some(sine_parameters) = check.sine_detect {
-      detect_sine(&self.filename, &mut streams, audio_indexes, sine_parameters);
+      detect_sine(&source, &mut streams, audio_indexes, sine_parameters);
     }
 
     let mut format = FormatProbeResult::new();
@@ -461,6 +541,26 @@ impl DeepProbe {
   }
 }
 
+/// Look for the timecode of a video stream in one of its packets: MPEG-1/2 GOP header,
+/// or S12M packet side data.
+fn find_timecode(codec_id: AVCodecID, packet: &Packet, frame_rate: Rational) -> Option<Timecode> {
+  if matches!(
+    codec_id,
+    AVCodecID::AV_CODEC_ID_MPEG1VIDEO | AVCodecID::AV_CODEC_ID_MPEG2VIDEO
+  ) {
+    if let Some(timecode) = Timecode::from_gop_header(packet.get_data(), frame_rate) {
+      return Some(timecode);
+    }
+  }
+  if let Some(side_data) = packet.get_side_data_of(AVPacketSideDataType::AV_PKT_DATA_S12M_TIMECODE)
+  {
+    return Timecode::from_s12m_side_data(side_data, frame_rate)
+      .into_iter()
+      .next();
+  }
+  None
+}
+
 #[test]
 fn deep_probe() {
   // use serde_json;
@@ -1,4 +1,4 @@
-use crate::format_context::FormatContext;
+use crate::error::Error;
 use crate::{
   order::{
     filter_input::FilterInput, filter_output::FilterOutput, input::Input, input_kind::InputKind,
@@ -6,7 +6,10 @@ use crate::{
     ParameterValue,
   },
   probe::deep::{CheckParameterValue, DualMonoResult, StreamProbeResult},
+  probe::get_rational_metadata,
+  probe::source::MediaSource,
   stream::Stream as ContextStream,
+  tools::rational::Rational,
 };
 use ffmpeg_sys_next::AVMediaType;
 use std::collections::HashMap;
@@ -14,7 +17,7 @@ use std::collections::HashMap;
 pub fn create_graph<S: ::std::hash::BuildHasher>(
   filename: &str,
   params: &HashMap<String, CheckParameterValue, S>,
-) -> Result<Order, String> {
+) -> Result<Order, Error> {
   let mut filters = vec![];
   let mut inputs = vec![];
   let mut outputs = vec![];
@@ -105,6 +108,7 @@ pub fn create_graph<S: ::std::hash::BuildHasher>(
                 path: None,
                 streams: vec![],
                 parameters: HashMap::new(),
+                chapters: vec![],
               });
             }
           }
@@ -118,12 +122,13 @@ pub fn create_graph<S: ::std::hash::BuildHasher>(
 }
 
 pub fn detect_dualmono<S: ::std::hash::BuildHasher>(
-  filename: &str,
+  source: &MediaSource,
   streams: &mut [StreamProbeResult],
   audio_indexes: Vec<u32>,
   params: HashMap<String, CheckParameterValue, S>,
 ) {
-  let mut order = create_graph(filename, &params).unwrap();
+  let mut order = create_graph(&source.filename, &params).unwrap();
+  source.register(&mut order);
   let mut max_duration = None;
   if let Some(duration) = params.get("duration") {
     max_duration = duration.max;
@@ -159,7 +164,7 @@ pub fn detect_dualmono<S: ::std::hash::BuildHasher>(
         None => warn!("No input message for the dualmono analysis (list of indexes to merge)"),
       }
 
-      let mut context = FormatContext::new(filename).unwrap();
+      let mut context = source.format_context().unwrap();
       if let Err(msg) = context.open_input() {
         context.close_input();
         error!("{:?}", msg);
@@ -168,10 +173,9 @@ pub fn detect_dualmono<S: ::std::hash::BuildHasher>(
       for index in 0..context.get_nb_streams() {
         if let Ok(stream) = ContextStream::new(context.get_stream(index as isize)) {
           if let AVMediaType::AVMEDIA_TYPE_VIDEO = context.get_stream_type(index as isize) {
-            let rational_frame_rate = stream.get_frame_rate();
-            let frame_rate = rational_frame_rate.num as f64 / rational_frame_rate.den as f64;
-            duration = (results.len() as f64 / audio_stream_qualif_number as f64 / frame_rate
-              * 1000.0) as i64;
+            let frame_rate = stream.get_frame_rate();
+            let frames = Rational::new(results.len() as i32, audio_stream_qualif_number);
+            duration = (frames / frame_rate * 1000).to_i64();
           }
         }
       }
@@ -191,18 +195,20 @@ pub fn detect_dualmono<S: ::std::hash::BuildHasher>(
               start: 0,
               end: duration,
             };
-            if let Some(value) = entry_map.get("lavfi.aphasemeter.mono_start") {
-              dualmono.start = (value.parse::<f64>().unwrap() * 1000.0) as i64;
+            if let Some(value) = get_rational_metadata(&entry_map, "lavfi.aphasemeter.mono_start") {
+              dualmono.start = (value * 1000).to_i64();
               detected_dualmono.push(dualmono);
             }
-            if let Some(value) = entry_map.get("lavfi.aphasemeter.mono_end") {
+            if let Some(value) = get_rational_metadata(&entry_map, "lavfi.aphasemeter.mono_end") {
               if let Some(last_detect) = detected_dualmono.last_mut() {
-                last_detect.end = (value.parse::<f64>().unwrap() * 1000.0) as i64;
+                last_detect.end = (value * 1000).to_i64();
               }
             }
-            if let Some(value) = entry_map.get("lavfi.aphasemeter.mono_duration") {
+            if let Some(value) =
+              get_rational_metadata(&entry_map, "lavfi.aphasemeter.mono_duration")
+            {
               if let Some(max) = max_duration {
-                if value.parse::<f64>().unwrap() * 1000.0 > max as f64 {
+                if value * 1000 > Rational::from(max as i32) {
                   detected_dualmono.pop();
                 }
               }
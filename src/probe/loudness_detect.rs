@@ -1,17 +1,18 @@
-use crate::format_context::FormatContext;
+use crate::error::Error;
 use crate::order::{
   filter_input::FilterInput, filter_output::FilterOutput, input::Input, input_kind::InputKind,
   output::Output, output_kind::OutputKind, stream::Stream, Filter, Order, OutputResult::Entry,
   ParameterValue,
 };
 use crate::probe::deep::{CheckParameterValue, LoudnessResult, StreamProbeResult};
+use crate::probe::source::MediaSource;
 use ffmpeg_sys_next::log10;
 use std::collections::HashMap;
 
 pub fn create_graph<S: ::std::hash::BuildHasher>(
   filename: &str,
   params: &HashMap<String, CheckParameterValue, S>,
-) -> Result<Order, String> {
+) -> Result<Order, Error> {
   let mut inputs = vec![];
   let mut outputs = vec![];
   let mut filters = vec![];
@@ -68,6 +69,7 @@ pub fn create_graph<S: ::std::hash::BuildHasher>(
             path: None,
             streams: vec![],
             parameters: HashMap::new(),
+            chapters: vec![],
           });
 
           amerge_params.insert(
@@ -101,20 +103,23 @@ pub fn create_graph<S: ::std::hash::BuildHasher>(
       }
     }
     None => {
-      return Err("No input message for the loudness analysis (audio qualification)".to_string())
+      return Err(Error::Configuration(
+        "No input message for the loudness analysis (audio qualification)".to_string(),
+      ))
     }
   }
   Order::new(inputs, filters, outputs)
 }
 
 pub fn detect_loudness<S: ::std::hash::BuildHasher>(
-  filename: &str,
+  source: &MediaSource,
   streams: &mut [StreamProbeResult],
   audio_indexes: Vec<u32>,
   params: HashMap<String, CheckParameterValue, S>,
 ) {
-  match create_graph(filename, &params) {
+  match create_graph(&source.filename, &params) {
     Ok(mut order) => {
+      source.register(&mut order);
       if let Err(msg) = order.setup() {
         error!("{:?}", msg);
         return;
@@ -126,7 +131,7 @@ pub fn detect_loudness<S: ::std::hash::BuildHasher>(
         Ok(results) => {
           info!("END OF PROCESS");
           info!("-> {:?} frames processed", results.len());
-          let mut context = FormatContext::new(filename).unwrap();
+          let mut context = source.format_context().unwrap();
           if let Err(msg) = context.open_input() {
             context.close_input();
             error!("{:?}", msg);
@@ -9,8 +9,25 @@ mod scene_detect;
 mod silence_detect;
 mod simple;
 mod sine_detect;
+mod source;
+
+use crate::tools::rational::Rational;
+use std::collections::HashMap;
 
 pub use self::black_and_silence::*;
 pub use self::deep::{CheckParameterValue, DeepProbe, DeepProbeCheck, Track};
 pub use self::simple::Probe;
 pub use self::sine_detect::*;
+pub use self::source::MediaSource;
+
+/// Parse a numeric lavfi metadata of a detection, skipped with a warning when invalid.
+fn get_rational_metadata(entry_map: &HashMap<String, String>, key: &str) -> Option<Rational> {
+  let value = entry_map.get(key)?;
+  match value.parse() {
+    Ok(value) => Some(value),
+    Err(error) => {
+      warn!("Skip invalid {key} metadata '{value}': {error}");
+      None
+    }
+  }
+}
@@ -1,11 +1,14 @@
-use crate::format_context::FormatContext;
+use crate::error::Error;
 use crate::order::{
   filter_input::FilterInput, filter_output::FilterOutput, input::Input, input_kind::InputKind,
   output::Output, output_kind::OutputKind, stream::Stream, Filter, Order, OutputResult::Entry,
   ParameterValue,
 };
 use crate::probe::deep::{CheckParameterValue, OcrResult, StreamProbeResult};
+use crate::probe::get_rational_metadata;
+use crate::probe::source::MediaSource;
 use crate::stream::Stream as ContextStream;
+use crate::tools::rational::Rational;
 use ffmpeg_sys_next::AVMediaType;
 use std::collections::HashMap;
 
@@ -13,7 +16,7 @@ pub fn create_graph<S: ::std::hash::BuildHasher>(
   filename: &str,
   video_indexes: Vec<u32>,
   params: &HashMap<String, CheckParameterValue, S>,
-) -> Result<Order, String> {
+) -> Result<Order, Error> {
   let mut filters = vec![];
   let mut inputs = vec![];
   let mut outputs = vec![];
@@ -70,18 +73,20 @@ pub fn create_graph<S: ::std::hash::BuildHasher>(
       path: None,
       streams: vec![],
       parameters: HashMap::new(),
+      chapters: vec![],
     });
   }
   Order::new(inputs, filters, outputs)
 }
 
 pub fn detect_ocr<S: ::std::hash::BuildHasher>(
-  filename: &str,
+  source: &MediaSource,
   streams: &mut [StreamProbeResult],
   video_indexes: Vec<u32>,
   params: HashMap<String, CheckParameterValue, S>,
 ) {
-  let mut order = create_graph(filename, video_indexes.clone(), &params).unwrap();
+  let mut order = create_graph(&source.filename, video_indexes.clone(), &params).unwrap();
+  source.register(&mut order);
   if let Err(msg) = order.setup() {
     error!("{:?}", msg);
     return;
@@ -94,11 +99,11 @@ pub fn detect_ocr<S: ::std::hash::BuildHasher>(
     Ok(results) => {
       info!("END OF PROCESS");
       info!("-> {:?} frames processed", results.len());
-      let mut frame_rate = 1.0;
-      let mut time_base = 1.0;
+      let mut frame_rate = Rational::new(1, 1);
+      let mut time_base = Rational::new(1, 1);
       let mut media_offline_detected = false;
       let mut nb_frames = 0;
-      let mut context = FormatContext::new(filename).unwrap();
+      let mut context = source.format_context().unwrap();
       if let Err(msg) = context.open_input() {
         context.close_input();
         error!("{:?}", msg);
@@ -107,8 +112,7 @@ pub fn detect_ocr<S: ::std::hash::BuildHasher>(
       for index in 0..context.get_nb_streams() {
         if let Ok(stream) = ContextStream::new(context.get_stream(index as isize)) {
           if let AVMediaType::AVMEDIA_TYPE_VIDEO = context.get_stream_type(index as isize) {
-            let rational_frame_rate = stream.get_frame_rate();
-            frame_rate = rational_frame_rate.num as f32 / rational_frame_rate.den as f32;
+            frame_rate = stream.get_frame_rate();
             time_base = stream.get_time_base();
             if let Some(frames_number) = stream.get_nb_frames() {
               nb_frames = frames_number;
@@ -136,9 +140,9 @@ pub fn detect_ocr<S: ::std::hash::BuildHasher>(
 
             if media_offline_detected {
               if let Some(last_detect) = detected_ocr.last_mut() {
-                if let Some(value) = entry_map.get("lavfi.scd.time") {
-                  last_detect.frame_end =
-                    (value.parse::<f32>().unwrap() * time_base / 25.0 * frame_rate - 1.0) as u64;
+                if let Some(value) = get_rational_metadata(&entry_map, "lavfi.scd.time") {
+                  let frame = value * time_base / 25 * frame_rate;
+                  last_detect.frame_end = (frame - Rational::from(1)).to_i64().max(0) as u64;
                   media_offline_detected = false;
                 }
               }
@@ -147,9 +151,9 @@ pub fn detect_ocr<S: ::std::hash::BuildHasher>(
               if value.starts_with("MEDIA OFFLINE") || value.starts_with("OFFLINE") {
                 media_offline_detected = true;
                 ocr.text = value.to_string();
-                if let Some(value) = entry_map.get("lavfi.scd.time") {
-                  ocr.frame_start =
-                    (value.parse::<f32>().unwrap() * time_base / 25.0 * frame_rate) as u64;
+                if let Some(value) = get_rational_metadata(&entry_map, "lavfi.scd.time") {
+                  let frame = value * time_base / 25 * frame_rate;
+                  ocr.frame_start = frame.to_i64().max(0) as u64;
                 }
                 if let Some(value) = entry_map.get("lavfi.ocr.confidence") {
                   let mut word_conf = value.to_string().replace(char::is_whitespace, "%,");
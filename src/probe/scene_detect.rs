@@ -1,11 +1,14 @@
-use crate::format_context::FormatContext;
+use crate::error::Error;
 use crate::order::{
   filter_input::FilterInput, filter_output::FilterOutput, input::Input, input_kind::InputKind,
   output::Output, output_kind::OutputKind, stream::Stream, Filter, Order, OutputResult::Entry,
   ParameterValue,
 };
 use crate::probe::deep::{CheckParameterValue, FalseSceneResult, SceneResult, StreamProbeResult};
+use crate::probe::get_rational_metadata;
+use crate::probe::source::MediaSource;
 use crate::stream::Stream as ContextStream;
+use crate::tools::rational::Rational;
 use ffmpeg_sys_next::AVMediaType;
 use std::collections::HashMap;
 
@@ -13,7 +16,7 @@ pub fn create_graph<S: ::std::hash::BuildHasher>(
   filename: &str,
   video_indexes: Vec<u32>,
   params: &HashMap<String, CheckParameterValue, S>,
-) -> Result<Order, String> {
+) -> Result<Order, Error> {
   let mut filters = vec![];
   let mut inputs = vec![];
   let mut outputs = vec![];
@@ -57,18 +60,20 @@ pub fn create_graph<S: ::std::hash::BuildHasher>(
       path: None,
       streams: vec![],
       parameters: HashMap::new(),
+      chapters: vec![],
     });
   }
   Order::new(inputs, filters, outputs)
 }
 
 pub fn detect_scene<S: ::std::hash::BuildHasher>(
-  filename: &str,
+  source: &MediaSource,
   streams: &mut [StreamProbeResult],
   video_indexes: Vec<u32>,
   params: HashMap<String, CheckParameterValue, S>,
 ) {
-  let mut order = create_graph(filename, video_indexes.clone(), &params).unwrap();
+  let mut order = create_graph(&source.filename, video_indexes.clone(), &params).unwrap();
+  source.register(&mut order);
   if let Err(msg) = order.setup() {
     error!("{:?}", msg);
     return;
@@ -82,10 +87,10 @@ pub fn detect_scene<S: ::std::hash::BuildHasher>(
     Ok(results) => {
       info!("END OF PROCESS");
       info!("-> {:?} frames processed", results.len());
-      let mut frame_rate = 1.0;
-      let mut time_base = 1.0;
+      let mut frame_rate = Rational::new(1, 1);
+      let mut time_base = Rational::new(1, 1);
       let mut scene_count = 0;
-      let mut context = FormatContext::new(filename).unwrap();
+      let mut context = source.format_context().unwrap();
       if let Err(msg) = context.open_input() {
         context.close_input();
         error!("{:?}", msg);
@@ -94,8 +99,7 @@ pub fn detect_scene<S: ::std::hash::BuildHasher>(
       for index in 0..context.get_nb_streams() {
         if let Ok(stream) = ContextStream::new(context.get_stream(index as isize)) {
           if let AVMediaType::AVMEDIA_TYPE_VIDEO = context.get_stream_type(index as isize) {
-            let rational_frame_rate = stream.get_frame_rate();
-            frame_rate = rational_frame_rate.num as f32 / rational_frame_rate.den as f32;
+            frame_rate = stream.get_frame_rate();
             time_base = stream.get_time_base();
           }
         }
@@ -120,11 +124,10 @@ pub fn detect_scene<S: ::std::hash::BuildHasher>(
             };
             let mut false_scene = FalseSceneResult { frame: 0 };
 
-            if let Some(value) = entry_map.get("lavfi.scd.time") {
-              scene.frame_index =
-                (value.parse::<f32>().unwrap() * time_base / 25.0 * frame_rate) as i64;
-              if let Some(value) = entry_map.get("lavfi.scd.score") {
-                scene.score = (value.parse::<f32>().unwrap()) as i32;
+            if let Some(value) = get_rational_metadata(&entry_map, "lavfi.scd.time") {
+              scene.frame_index = (value * time_base / 25 * frame_rate).to_i64();
+              if let Some(value) = get_rational_metadata(&entry_map, "lavfi.scd.score") {
+                scene.score = value.to_f64() as i32;
               }
 
               if let Some(last_detect) = detected_scene.last() {
@@ -1,11 +1,14 @@
-use crate::format_context::FormatContext;
+use crate::error::Error;
 use crate::order::{
   filter_input::FilterInput, filter_output::FilterOutput, input::Input, input_kind::InputKind,
   output::Output, output_kind::OutputKind, stream::Stream, Filter, Order, OutputResult::Entry,
   ParameterValue,
 };
 use crate::probe::deep::{CheckParameterValue, SilenceResult, StreamProbeResult};
+use crate::probe::get_rational_metadata;
+use crate::probe::source::MediaSource;
 use crate::stream::Stream as ContextStream;
+use crate::tools::rational::Rational;
 use ffmpeg_sys_next::AVMediaType;
 use std::collections::HashMap;
 
@@ -13,7 +16,7 @@ pub fn create_graph<S: ::std::hash::BuildHasher>(
   filename: &str,
   audio_indexes: Vec<u32>,
   params: &HashMap<String, CheckParameterValue, S>,
-) -> Result<Order, String> {
+) -> Result<Order, Error> {
   let mut filters = vec![];
   let mut inputs = vec![];
   let mut outputs = vec![];
@@ -75,6 +78,7 @@ pub fn create_graph<S: ::std::hash::BuildHasher>(
       path: None,
       streams: vec![],
       parameters: HashMap::new(),
+      chapters: vec![],
     });
   }
 
@@ -82,12 +86,13 @@ pub fn create_graph<S: ::std::hash::BuildHasher>(
 }
 
 pub fn detect_silence<S: ::std::hash::BuildHasher>(
-  filename: &str,
+  source: &MediaSource,
   streams: &mut [StreamProbeResult],
   audio_indexes: Vec<u32>,
   params: HashMap<String, CheckParameterValue, S>,
 ) {
-  let mut order = create_graph(filename, audio_indexes.clone(), &params).unwrap();
+  let mut order = create_graph(&source.filename, audio_indexes.clone(), &params).unwrap();
+  source.register(&mut order);
   if let Err(msg) = order.setup() {
     error!("{:?}", msg);
     return;
@@ -101,7 +106,7 @@ pub fn detect_silence<S: ::std::hash::BuildHasher>(
       info!("END OF PROCESS");
       info!("-> {:?} frames processed", results.len());
       let mut duration = 0;
-      let mut context = FormatContext::new(filename).unwrap();
+      let mut context = source.format_context().unwrap();
       if let Err(msg) = context.open_input() {
         context.close_input();
         error!("{:?}", msg);
@@ -110,10 +115,9 @@ pub fn detect_silence<S: ::std::hash::BuildHasher>(
       for index in 0..context.get_nb_streams() {
         if let Ok(stream) = ContextStream::new(context.get_stream(index as isize)) {
           if let AVMediaType::AVMEDIA_TYPE_VIDEO = context.get_stream_type(index as isize) {
-            let rational_frame_rate = stream.get_frame_rate();
-            let frame_rate = rational_frame_rate.num as f64 / rational_frame_rate.den as f64;
-            duration = (results.len() as f64 / audio_indexes.clone().len() as f64 / frame_rate
-              * 1000.0) as i64;
+            let frame_rate = stream.get_frame_rate();
+            let frames = Rational::new(results.len() as i32, audio_indexes.len() as i32);
+            duration = (frames / frame_rate * 1000).to_i64();
           }
         }
       }
@@ -135,18 +139,18 @@ pub fn detect_silence<S: ::std::hash::BuildHasher>(
               end: duration,
             };
 
-            if let Some(value) = entry_map.get("lavfi.silence_start") {
-              silence.start = (value.parse::<f64>().unwrap() * 1000.0) as i64;
+            if let Some(value) = get_rational_metadata(&entry_map, "lavfi.silence_start") {
+              silence.start = (value * 1000).to_i64();
               detected_silence.push(silence);
             }
-            if let Some(value) = entry_map.get("lavfi.silence_end") {
+            if let Some(value) = get_rational_metadata(&entry_map, "lavfi.silence_end") {
               if let Some(last_detect) = detected_silence.last_mut() {
-                last_detect.end = (value.parse::<f64>().unwrap() * 1000.0) as i64;
+                last_detect.end = (value * 1000).to_i64();
               }
             }
-            if let Some(value) = entry_map.get("lavfi.silence_duration") {
+            if let Some(value) = get_rational_metadata(&entry_map, "lavfi.silence_duration") {
               if let Some(max) = max_duration {
-                if (value.parse::<f64>().unwrap() * 1000.0) as u64 > max {
+                if (value * 1000).to_i64() > max as i64 {
                   detected_silence.pop();
                 }
               }
@@ -1,15 +1,22 @@
-use crate::format_context::FormatContext;
-use crate::stream::Stream;
-use crate::tools::rational::Rational;
+use crate::error::Error;
+use crate::hdr::{HdrFormat, HdrMetadata};
+use crate::io_context::SharedReader;
+use crate::order::chapter::Chapter;
+use crate::probe::source::MediaSource;
+use crate::stream::{Disposition, Stream};
+use crate::tools::{rational::Rational, timestamp::Timestamp};
 use ffmpeg_sys_next::*;
 use log::LevelFilter;
 use std::collections::{BTreeMap, HashMap};
 use std::fmt;
+use std::io::{Read, Seek};
 
 #[derive(Debug, Deserialize, PartialEq, Serialize)]
 pub struct Probe {
   #[serde(skip_serializing)]
   filename: String,
+  #[serde(skip)]
+  reader: Option<SharedReader>,
   pub format: Option<Format>,
 }
 
@@ -24,6 +31,10 @@ pub struct Format {
   packet_size: u32,
   nb_streams: u32,
   metadata: BTreeMap<String, String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  timecode: Option<String>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  chapters: Vec<Chapter>,
   streams: Vec<StreamDescriptor>,
 }
 
@@ -38,6 +49,12 @@ pub struct StreamDescriptor {
   duration: Option<f32>,
   bit_rate: Option<i64>,
   stream_metadata: HashMap<String, String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  timecode: Option<String>,
+  #[serde(default, skip_serializing_if = "Disposition::is_empty")]
+  disposition: Disposition,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  language: Option<String>,
 
   #[serde(flatten)]
   video_properties: Option<VideoProperties>,
@@ -45,7 +62,7 @@ pub struct StreamDescriptor {
   audio_properties: Option<AudioProperties>,
 }
 
-#[derive(Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
 pub struct VideoProperties {
   width: i32,
   height: i32,
@@ -58,11 +75,19 @@ pub struct VideoProperties {
   timebase: Option<String>,
   pix_fmt: Option<String>,
   nb_frames: Option<i64>,
+  hdr_format: HdrFormat,
+  #[serde(default, skip_serializing_if = "HdrMetadata::is_empty")]
+  hdr_metadata: HdrMetadata,
 }
 
 #[derive(Debug, Deserialize, PartialEq, Eq, Serialize)]
 pub struct AudioProperties {
   channels: i32,
+  /// Unset when only the number of channels is known.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  channel_layout: Option<String>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  channel_names: Vec<String>,
   sample_rate: i32,
   sample_fmt: String,
   bits_per_sample: i32,
@@ -81,17 +106,38 @@ impl fmt::Display for Format {
     writeln!(f, "{:30} : {}", "Packet size", self.packet_size)?;
     writeln!(f, "{:30} : {}", "Number of streams", self.nb_streams)?;
     writeln!(f, "{:30} : {}", "Number of Programs", self.program_count)?;
+    writeln!(f, "{:30} : {:?}", "Timecode", self.timecode)?;
 
     for (key, value) in &self.metadata {
       writeln!(f, "{key:30} : {value}")?;
     }
 
+    for chapter in &self.chapters {
+      writeln!(f, "\n{:30} : {}", "Chapter", chapter.id)?;
+      writeln!(f, "{:30} : {:?}", "Title", chapter.title)?;
+      writeln!(
+        f,
+        "{:30} : {}",
+        "Start",
+        Timestamp::new(chapter.start, chapter.time_base).to_seconds()
+      )?;
+      writeln!(
+        f,
+        "{:30} : {}",
+        "End",
+        Timestamp::new(chapter.end, chapter.time_base).to_seconds()
+      )?;
+    }
+
     for stream in &self.streams {
       writeln!(f, "\n{:30} : {}", "Stream type", stream.stream_type)?;
       writeln!(f, "{:30} : {}", "Index", stream.index)?;
       writeln!(f, "{:30} : {:?}", "Codec name", stream.codec_name)?;
       writeln!(f, "{:30} : {:?}", "Codec long name", stream.codec_long_name)?;
       writeln!(f, "{:30} : {:?}", "Codec tag", stream.codec_tag)?;
+      writeln!(f, "{:30} : {:?}", "Timecode", stream.timecode)?;
+      writeln!(f, "{:30} : {:?}", "Disposition", stream.disposition)?;
+      writeln!(f, "{:30} : {:?}", "Language", stream.language)?;
 
       if let Some(ref vp) = stream.video_properties {
         writeln!(f, "{:30} : {}", "Width", vp.width)?;
@@ -116,9 +162,22 @@ impl fmt::Display for Format {
         writeln!(f, "{:30} : {:?}", "Timebase", vp.timebase)?;
         writeln!(f, "{:30} : {:?}", "Pixel format", vp.pix_fmt)?;
         writeln!(f, "{:30} : {:?}", "Number of frames", vp.nb_frames)?;
+        writeln!(f, "{:30} : {}", "HDR format", vp.hdr_format)?;
+        if !vp.hdr_metadata.is_empty() {
+          writeln!(f, "{:30} : {:?}", "HDR metadata", vp.hdr_metadata)?;
+        }
       }
       if let Some(ref ap) = stream.audio_properties {
         writeln!(f, "{:30} : {}", "Channels", ap.channels)?;
+        if let Some(channel_layout) = &ap.channel_layout {
+          writeln!(f, "{:30} : {}", "Channel layout", channel_layout)?;
+          writeln!(
+            f,
+            "{:30} : {}",
+            "Channel names",
+            ap.channel_names.join(", ")
+          )?;
+        }
         writeln!(f, "{:30} : {}", "Sample rate", ap.sample_rate)?;
         writeln!(f, "{:30} : {}", "Sample format", ap.sample_fmt)?;
         writeln!(f, "{:30} : {}", "Bits per sample", ap.bits_per_sample)?;
@@ -139,11 +198,21 @@ impl Probe {
   pub fn new(filename: &str) -> Self {
     Probe {
       filename: filename.to_owned(),
+      reader: None,
       format: None,
     }
   }
 
-  pub fn process(&mut self, log_level: LevelFilter) -> Result<(), String> {
+  /// Probe media read from `reader`. The filename is only used as a format hint.
+  pub fn new_with_reader<R: Read + Seek + Send + 'static>(filename: &str, reader: R) -> Self {
+    Probe {
+      filename: filename.to_owned(),
+      reader: Some(SharedReader::new(reader)),
+      format: None,
+    }
+  }
+
+  pub fn process(&mut self, log_level: LevelFilter) -> Result<(), Error> {
     let av_log_level = match log_level {
       LevelFilter::Error => AV_LOG_ERROR,
       LevelFilter::Warn => AV_LOG_WARNING,
@@ -157,7 +226,11 @@ impl Probe {
       av_log_set_level(av_log_level);
     }
 
-    let mut context = FormatContext::new(&self.filename).unwrap();
+    let source = MediaSource {
+      filename: self.filename.clone(),
+      reader: self.reader.clone(),
+    };
+    let mut context = source.format_context()?;
     if context.open_input().is_err() {
       self.format = None;
       context.close_input();
@@ -175,6 +248,8 @@ impl Probe {
     let nb_streams = context.get_nb_streams();
 
     let metadata = context.get_metadata();
+    let timecode = context.get_timecode().map(|timecode| timecode.to_string());
+    let chapters = context.get_chapters();
     let mut streams = vec![];
 
     for index in 0..context.get_nb_streams() {
@@ -189,6 +264,9 @@ impl Probe {
         let mut vp = None;
         let mut ap = None;
         let stream_metadata = stream.get_stream_metadata();
+        let timecode = stream.get_timecode().map(|timecode| timecode.to_string());
+        let disposition = stream.get_disposition();
+        let language = stream.get_language();
 
         match context.get_stream_type(index as isize) {
           AVMediaType::AVMEDIA_TYPE_VIDEO => {
@@ -200,9 +278,15 @@ impl Probe {
             let chroma_subsampling = stream.get_chroma_sub_sample();
             let level = stream.get_level();
             let profile = stream.get_profile();
-            let timebase = Some(stream.get_time_base().to_string());
+            // kept as a decimal, like before the Rational time base
+            let timebase = Some((stream.get_time_base().to_f64() as f32).to_string());
             let pix_fmt = stream.get_pix_fmt_name();
             let nb_frames = stream.get_nb_frames();
+            let mut hdr_metadata = stream.get_hdr_metadata();
+            if let Ok(frame) = source.decode_first_video_frame(index as isize) {
+              hdr_metadata.merge(frame.get_hdr_metadata());
+            }
+            let hdr_format = stream.get_hdr_format(&hdr_metadata);
 
             vp = Some(VideoProperties {
               width,
@@ -216,10 +300,19 @@ impl Probe {
               timebase,
               pix_fmt,
               nb_frames,
+              hdr_format,
+              hdr_metadata,
             });
           }
           AVMediaType::AVMEDIA_TYPE_AUDIO => {
             let channels = stream.get_channels();
+            let layout = stream.get_channel_layout();
+            let channel_layout = if layout.is_specified() {
+              Some(layout.to_string())
+            } else {
+              None
+            };
+            let channel_names = layout.get_channel_names();
 
             let bits_per_sample = stream.get_bits_per_sample();
             let sample_fmt = stream.get_sample_fmt();
@@ -227,6 +320,8 @@ impl Probe {
 
             ap = Some(AudioProperties {
               channels,
+              channel_layout,
+              channel_names,
               sample_rate,
               sample_fmt,
               bits_per_sample,
@@ -245,6 +340,9 @@ impl Probe {
           duration,
           bit_rate,
           stream_metadata,
+          timecode,
+          disposition,
+          language,
           video_properties: vp,
           audio_properties: ap,
         })
@@ -261,6 +359,8 @@ impl Probe {
       packet_size,
       nb_streams,
       metadata,
+      timecode,
+      chapters,
       streams,
     });
 
@@ -285,3 +385,20 @@ fn probe_mxf_sample() {
   let reference: Probe = serde_json::from_str(&contents).unwrap();
   assert_eq!(probe, reference);
 }
+
+#[test]
+fn probe_mxf_sample_from_reader() {
+  use std::fs::File;
+  use std::io::prelude::*;
+
+  let filename = "tests/PAL_1080i_MPEG_XDCAM-HD_colorbar.mxf";
+  let mut probe = Probe::new_with_reader(filename, File::open(filename).unwrap());
+  probe.process(LevelFilter::Error).unwrap();
+
+  let mut file = File::open("tests/probe.json").unwrap();
+  let mut contents = String::new();
+  file.read_to_string(&mut contents).unwrap();
+
+  let reference: Probe = serde_json::from_str(&contents).unwrap();
+  assert_eq!(probe.format, reference.format);
+}
@@ -1,11 +1,13 @@
-use crate::format_context::FormatContext;
+use crate::error::Error;
 use crate::order::{
   filter_input::FilterInput, filter_output::FilterOutput, input::Input, input_kind::InputKind,
   output::Output, output_kind::OutputKind, stream::Stream, Filter, Order, OutputResult::Entry,
   ParameterValue,
 };
 use crate::probe::deep::{CheckParameterValue, SineResult, StreamProbeResult, Track};
+use crate::probe::source::MediaSource;
 use crate::stream::Stream as ContextStream;
+use crate::tools::{rational::Rational, timestamp::Timestamp};
 use ffmpeg_sys_next::AVMediaType;
 use std::collections::HashMap;
 
@@ -13,7 +15,7 @@ pub fn create_graph(
   filename: &str,
   audio_indexes: Vec<u32>,
   params: &HashMap<String, CheckParameterValue>,
-) -> Result<Order, String> {
+) -> Result<Order, Error> {
   let mut filters = vec![];
   let mut inputs = vec![];
   let mut outputs = vec![];
@@ -78,12 +80,15 @@ pub fn create_graph(
             path: None,
             streams: vec![],
             parameters: HashMap::new(),
+            chapters: vec![],
           });
         }
       }
     }
     None => {
-      return Err("No input message for the 1000Hz analysis (audio qualification)".to_string())
+      return Err(Error::Configuration(
+        "No input message for the 1000Hz analysis (audio qualification)".to_string(),
+      ))
     }
   }
 
@@ -91,12 +96,13 @@ pub fn create_graph(
 }
 
 pub fn detect_sine(
-  filename: &str,
+  source: &MediaSource,
   streams: &mut [StreamProbeResult],
   audio_indexes: Vec<u32>,
   params: HashMap<String, CheckParameterValue>,
 ) {
-  let mut order = create_graph(filename, audio_indexes.clone(), &params).unwrap();
+  let mut order = create_graph(&source.filename, audio_indexes.clone(), &params).unwrap();
+  source.register(&mut order);
   if let Err(msg) = order.setup() {
     error!("{:?}", msg);
     return;
@@ -110,13 +116,13 @@ pub fn detect_sine(
       info!("END OF PROCESS");
       info!("-> {:?} frames processed", results.len());
       let mut duration = 0;
-      let mut time_base = 1.0;
+      let mut time_base = Rational::new(1, 1);
       let mut tracks: Vec<Vec<Track>> = Vec::new();
       let mut sine: SineResult = Default::default();
       let mut range_value: f64 = 0.0; //contains the range values to code a sample (=2^n)
       let mut last_starts: HashMap<Track, Option<i64>> = HashMap::new(); //contains the previous declared start
       let mut last_crests: HashMap<Track, f64> = HashMap::new(); //contains the crest factor from the previous frame
-      let mut frames: HashMap<Track, f32> = HashMap::new(); //contains the current frame number
+      let mut frames: HashMap<Track, i64> = HashMap::new(); //contains the current frame number
       let mut zero_cross: HashMap<Track, f64> = HashMap::new(); //contains the number of zero crossings
       let mut max_duration = None;
       let mut min_duration = None;
@@ -133,7 +139,7 @@ pub fn detect_sine(
         None => return warn!("No input message for the 1000Hz analysis (audio qualification)"),
       }
 
-      let mut context = FormatContext::new(filename).unwrap();
+      let mut context = source.format_context().unwrap();
       if let Err(msg) = context.open_input() {
         context.close_input();
         error!("{:?}", msg);
@@ -142,10 +148,9 @@ pub fn detect_sine(
       for index in 0..context.get_nb_streams() {
         if let Ok(stream) = ContextStream::new(context.get_stream(index as isize)) {
           if let AVMediaType::AVMEDIA_TYPE_VIDEO = context.get_stream_type(index as isize) {
-            let rational_frame_rate = stream.get_frame_rate();
-            let frame_rate = rational_frame_rate.num as f64 / rational_frame_rate.den as f64;
-            duration =
-              (results.len() as f64 / audio_indexes.len() as f64 / frame_rate * 1000.0) as i64;
+            let frame_rate = stream.get_frame_rate();
+            let frames = Rational::new(results.len() as i32, audio_indexes.len() as i32);
+            duration = (frames / frame_rate * 1000).to_i64();
             time_base = stream.get_time_base();
           }
         }
@@ -189,9 +194,9 @@ pub fn detect_sine(
               let audio_stream_key = Track::new(index, channel);
 
               //update frame count
-              let prev_frame = frames.get(&audio_stream_key).unwrap_or(&0.0);
-              frames.insert(audio_stream_key.clone(), prev_frame + 1.0);
-              let frame = frames.get(&audio_stream_key).unwrap();
+              let prev_frame = frames.get(&audio_stream_key).unwrap_or(&0);
+              frames.insert(audio_stream_key.clone(), prev_frame + 1);
+              let frame = *frames.get(&audio_stream_key).unwrap();
               let last_start_opt = last_starts.get(&audio_stream_key).unwrap_or(&None);
 
               //update signal zero crossing count
@@ -209,7 +214,7 @@ pub fn detect_sine(
                   if last_start_opt.is_some() {
                     if let Some(last_start) = last_start_opt {
                       //check if audio ends => 1000Hz until the end
-                      if (frame * (time_base * 1000.0)) as i64 == duration {
+                      if Timestamp::new(frame, time_base).to_millis() == duration {
                         sine.channel = channel;
                         sine.start = *last_start;
                         sine.end = duration;
@@ -234,7 +239,7 @@ pub fn detect_sine(
                       }
                     }
                   } else {
-                    sine.start = ((frame - 1.0) * (time_base * 1000.0)) as i64;
+                    sine.start = Timestamp::new(frame - 1, time_base).to_millis();
                     last_starts.insert(audio_stream_key.clone(), Some(sine.start));
                   }
                 } else if (1.4129..1.4151)
@@ -244,7 +249,7 @@ pub fn detect_sine(
                   if let Some(last_start) = last_start_opt {
                     sine.channel = channel;
                     sine.start = *last_start;
-                    sine.end = ((frame - 1.0) * (time_base * 1000.0)) as i64;
+                    sine.end = Timestamp::new(frame - 1, time_base).to_millis();
                     //check if sine is a 1000Hz => push and reset
                     if let Some(zero_crossing) = zero_cross.get(&audio_stream_key) {
                       if (zero_crossing / (sine.end - sine.start) as f64) == 2.0 {
@@ -0,0 +1,66 @@
+use crate::error::Error;
+use crate::format_context::FormatContext;
+use crate::frame::Frame;
+use crate::io_context::SharedReader;
+use crate::order::Order;
+use crate::video_decoder::VideoDecoder;
+
+/// Packets read at most while looking for the first decodable frame.
+const FIRST_FRAME_MAX_PACKETS: usize = 1000;
+
+/// Media analysed by a probe: a path, or a reader named by that path.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MediaSource {
+  pub filename: String,
+  pub reader: Option<SharedReader>,
+}
+
+impl MediaSource {
+  pub fn format_context(&self) -> Result<FormatContext, Error> {
+    match &self.reader {
+      Some(reader) => FormatContext::from_reader(&self.filename, reader.clone()),
+      None => FormatContext::new(&self.filename),
+    }
+  }
+
+  /// Make the order read its inputs declared with this filename from the reader.
+  pub fn register(&self, order: &mut Order) {
+    if let Some(reader) = &self.reader {
+      order.set_input_reader(&self.filename, reader.clone());
+    }
+  }
+
+  /// Decode the first frame of a video stream, to read the side data set by the decoder.
+  pub fn decode_first_video_frame(&self, stream_index: isize) -> Result<Frame, Error> {
+    let mut context = self.format_context()?;
+    context.open_input()?;
+    let result = VideoDecoder::new(
+      format!("first_frame_{stream_index}"),
+      &context,
+      stream_index,
+      false,
+    )
+    .and_then(|decoder| {
+      for _ in 0..FIRST_FRAME_MAX_PACKETS {
+        let packet = context.next_packet()?;
+        if packet.get_stream_index() != stream_index {
+          continue;
+        }
+        match decoder.decode(&packet) {
+          Ok(frames) => {
+            if let Some(frame) = frames.into_iter().next() {
+              return Ok(frame);
+            }
+          }
+          Err(Error::InvalidData(_)) => continue,
+          Err(error) => return Err(error),
+        }
+      }
+      Err(Error::InvalidData(format!(
+        "No frame decoded from the first {FIRST_FRAME_MAX_PACKETS} packets"
+      )))
+    });
+    context.close_input();
+    result
+  }
+}
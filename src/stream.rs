@@ -1,6 +1,83 @@
-use crate::{tools, tools::rational::Rational};
+use crate::{
+  channel_layout::ChannelLayout,
+  error::Error,
+  hdr::{ContentLightLevel, DolbyVisionConfiguration, HdrFormat, HdrMetadata, MasteringDisplay},
+  tools,
+  tools::{language, rational::Rational, timecode::Timecode, timestamp::Timestamp},
+};
 use ffmpeg_sys_next::*;
-use std::{char, collections::HashMap, ffi::CString, ptr::null_mut};
+use std::{char, collections::HashMap, ffi::CString, mem::size_of, ptr::null_mut, slice};
+
+/// Typed `AV_DISPOSITION_*` flags of a stream.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(default)]
+pub struct Disposition {
+  #[serde(skip_serializing_if = "is_false")]
+  pub default: bool,
+  #[serde(skip_serializing_if = "is_false")]
+  pub forced: bool,
+  #[serde(skip_serializing_if = "is_false")]
+  pub hearing_impaired: bool,
+  #[serde(skip_serializing_if = "is_false")]
+  pub visual_impaired: bool,
+  #[serde(skip_serializing_if = "is_false")]
+  pub attached_pic: bool,
+  #[serde(skip_serializing_if = "is_false")]
+  pub commentary: bool,
+  #[serde(skip_serializing_if = "is_false")]
+  pub original: bool,
+  #[serde(skip_serializing_if = "is_false")]
+  pub dub: bool,
+}
+
+impl Disposition {
+  pub fn is_empty(&self) -> bool {
+    *self == Disposition::default()
+  }
+
+  fn flags(&self) -> [(bool, u32); 8] {
+    [
+      (self.default, AV_DISPOSITION_DEFAULT),
+      (self.forced, AV_DISPOSITION_FORCED),
+      (self.hearing_impaired, AV_DISPOSITION_HEARING_IMPAIRED),
+      (self.visual_impaired, AV_DISPOSITION_VISUAL_IMPAIRED),
+      (self.attached_pic, AV_DISPOSITION_ATTACHED_PIC),
+      (self.commentary, AV_DISPOSITION_COMMENT),
+      (self.original, AV_DISPOSITION_ORIGINAL),
+      (self.dub, AV_DISPOSITION_DUB),
+    ]
+  }
+}
+
+impl From<i32> for Disposition {
+  fn from(flags: i32) -> Self {
+    let has = |flag: u32| flags & flag as i32 != 0;
+    Disposition {
+      default: has(AV_DISPOSITION_DEFAULT),
+      forced: has(AV_DISPOSITION_FORCED),
+      hearing_impaired: has(AV_DISPOSITION_HEARING_IMPAIRED),
+      visual_impaired: has(AV_DISPOSITION_VISUAL_IMPAIRED),
+      attached_pic: has(AV_DISPOSITION_ATTACHED_PIC),
+      commentary: has(AV_DISPOSITION_COMMENT),
+      original: has(AV_DISPOSITION_ORIGINAL),
+      dub: has(AV_DISPOSITION_DUB),
+    }
+  }
+}
+
+impl From<Disposition> for i32 {
+  fn from(disposition: Disposition) -> i32 {
+    disposition
+      .flags()
+      .iter()
+      .filter(|(enabled, _)| *enabled)
+      .fold(0, |flags, (_, flag)| flags | *flag as i32)
+  }
+}
+
+fn is_false(value: &bool) -> bool {
+  !*value
+}
 
 #[derive(Clone)]
 pub struct Stream {
@@ -8,15 +85,15 @@ pub struct Stream {
 }
 
 impl Stream {
-  pub fn new(stream: *mut AVStream) -> Result<Stream, String> {
+  pub fn new(stream: *mut AVStream) -> Result<Stream, Error> {
     if stream.is_null() {
-      return Err("Null stream pointer".to_string());
+      return Err(Error::Configuration("Null stream pointer".to_string()));
     }
     Ok(Stream { stream })
   }
 
-  pub fn get_time_base(&self) -> f32 {
-    unsafe { (*self.stream).time_base.num as f32 / (*self.stream).time_base.den as f32 }
+  pub fn get_time_base(&self) -> Rational {
+    unsafe { (*self.stream).time_base.into() }
   }
 
   pub fn get_codec_name(&self) -> Option<String> {
@@ -70,13 +147,15 @@ impl Stream {
   }
 
   pub fn get_duration(&self) -> Option<f32> {
-    unsafe {
-      if (*self.stream).duration == AV_NOPTS_VALUE {
-        None
-      } else {
-        Some((*self.stream).duration as f32 * self.get_time_base())
-      }
-    }
+    self
+      .get_duration_timestamp()
+      .map(|duration| duration.to_seconds() as f32)
+  }
+
+  pub fn get_duration_timestamp(&self) -> Option<Timestamp> {
+    self
+      .get_duration_pts()
+      .map(|duration| Timestamp::new(duration, self.get_time_base()))
   }
 
   pub fn get_duration_pts(&self) -> Option<i64> {
@@ -120,11 +199,20 @@ impl Stream {
   }
 
   pub fn get_start_time(&self) -> Option<f32> {
+    self
+      .get_start_timestamp()
+      .map(|start_time| start_time.to_seconds() as f32)
+  }
+
+  pub fn get_start_timestamp(&self) -> Option<Timestamp> {
     unsafe {
       if (*self.stream).start_time == AV_NOPTS_VALUE {
         None
       } else {
-        Some((*self.stream).start_time as f32 * self.get_time_base())
+        Some(Timestamp::new(
+          (*self.stream).start_time,
+          self.get_time_base(),
+        ))
       }
     }
   }
@@ -178,12 +266,7 @@ impl Stream {
   }
 
   pub fn get_frame_rate(&self) -> Rational {
-    unsafe {
-      Rational {
-        num: (*self.stream).r_frame_rate.num,
-        den: (*self.stream).r_frame_rate.den,
-      }
-    }
+    unsafe { (*self.stream).r_frame_rate.into() }
   }
 
   pub fn get_level(&self) -> Option<i32> {
@@ -270,39 +353,123 @@ impl Stream {
   }
 
   pub fn get_channels(&self) -> i32 {
-    unsafe { (*(*self.stream).codecpar).channels }
+    unsafe { (*(*self.stream).codecpar).ch_layout.nb_channels }
   }
 
-  #[cfg(any(ffmpeg_4_4, ffmpeg_5_0, ffmpeg_5_1))]
-  pub fn get_timecode(&self) -> Option<String> {
-    unsafe {
-      let timecode_side_data = av_stream_get_side_data(
-        self.stream,
-        AVPacketSideDataType::AV_PKT_DATA_S12M_TIMECODE,
-        null_mut(),
-      );
-
-      let timecode = &mut 0;
-      if timecode_side_data.is_null() {
-        return None;
+  pub fn get_channel_layout(&self) -> ChannelLayout {
+    unsafe { ChannelLayout::from_av_layout(&(*(*self.stream).codecpar).ch_layout) }
+  }
+
+  /// Timecode from the `timecode` metadata (MOV tmcd or MXF tracks) or the S12M side data.
+  pub fn get_timecode(&self) -> Option<Timecode> {
+    let frame_rate = self.get_timecode_frame_rate()?;
+    if let Some(timecode) = self.get_stream_metadata().get("timecode") {
+      if let Ok(timecode) = Timecode::parse(timecode, frame_rate) {
+        return Some(timecode);
+      }
+    }
+    let side_data = self.get_side_data(AVPacketSideDataType::AV_PKT_DATA_S12M_TIMECODE)?;
+    Timecode::from_s12m_side_data(side_data, frame_rate)
+      .into_iter()
+      .next()
+  }
+
+  /// HDR metadata carried by the stream side data, e.g. from MKV or MP4 boxes.
+  pub fn get_hdr_metadata(&self) -> HdrMetadata {
+    let mut metadata = HdrMetadata::default();
+    if let Some(data) =
+      self.get_side_data(AVPacketSideDataType::AV_PKT_DATA_MASTERING_DISPLAY_METADATA)
+    {
+      if data.len() >= size_of::<AVMasteringDisplayMetadata>() {
+        metadata.mastering_display = Some(unsafe {
+          MasteringDisplay::from_av_metadata(data.as_ptr() as *const AVMasteringDisplayMetadata)
+        });
       }
-      av_timecode_make_mpeg_tc_string(timecode, timecode_side_data as u32);
-      Some(timecode.to_string())
     }
+    metadata.content_light_level = self
+      .get_side_data(AVPacketSideDataType::AV_PKT_DATA_CONTENT_LIGHT_LEVEL)
+      .and_then(ContentLightLevel::from_bytes);
+    metadata.dolby_vision = self
+      .get_side_data(AVPacketSideDataType::AV_PKT_DATA_DOVI_CONF)
+      .and_then(DolbyVisionConfiguration::from_bytes);
+    metadata.hdr10_plus = self
+      .get_side_data(AVPacketSideDataType::AV_PKT_DATA_DYNAMIC_HDR10_PLUS)
+      .is_some();
+    metadata
   }
 
-  #[cfg(any(ffmpeg_4_0, ffmpeg_4_1, ffmpeg_4_2, ffmpeg_4_3))]
-  pub fn get_timecode(&self) -> Option<String> {
+  /// Classify the dynamic range from the transfer characteristic and HDR metadata.
+  pub fn get_hdr_format(&self, metadata: &HdrMetadata) -> HdrFormat {
+    unsafe { metadata.get_format((*(*self.stream).codecpar).color_trc) }
+  }
+
+  pub fn get_side_data(&self, kind: AVPacketSideDataType) -> Option<&[u8]> {
     unsafe {
-      let tc = &mut 0;
-      let timecode = (*(*self.stream).codec).timecode_frame_start;
-      if timecode < 0 {
+      let mut size = 0;
+      let side_data = av_stream_get_side_data(self.stream, kind, &mut size);
+      if side_data.is_null() {
         return None;
       }
+      Some(slice::from_raw_parts(side_data, size))
+    }
+  }
+
+  /// Average frame rate, or the real base frame rate, when known.
+  pub fn get_timecode_frame_rate(&self) -> Option<Rational> {
+    let (avg_frame_rate, r_frame_rate): (Rational, Rational) = unsafe {
+      (
+        (*self.stream).avg_frame_rate.into(),
+        (*self.stream).r_frame_rate.into(),
+      )
+    };
+    [avg_frame_rate, r_frame_rate]
+      .into_iter()
+      .find(|frame_rate| frame_rate.num > 0 && frame_rate.den > 0)
+  }
+
+  pub fn get_disposition(&self) -> Disposition {
+    unsafe { (*self.stream).disposition.into() }
+  }
+
+  /// Replace the disposition flags covered by `Disposition`, keeping the other ones.
+  pub fn set_disposition(&mut self, disposition: Disposition) {
+    unsafe {
+      let mask = Disposition::default()
+        .flags()
+        .iter()
+        .fold(0, |mask, (_, flag)| mask | *flag as i32);
+      (*self.stream).disposition = ((*self.stream).disposition & !mask) | i32::from(disposition);
+    }
+  }
 
-      av_timecode_make_mpeg_tc_string(tc, timecode as u32);
-      Some(tc.to_string())
+  /// ISO 639-2/B code of the `language` metadata.
+  pub fn get_language(&self) -> Option<String> {
+    self
+      .get_stream_metadata()
+      .get("language")
+      .and_then(|language| language::normalize_language(language))
+  }
+
+  /// Set the `language` metadata, from an ISO 639-1 or ISO 639-2 code, or "und".
+  pub fn set_language(&mut self, language: &str) -> Result<(), Error> {
+    let language = if language.trim().eq_ignore_ascii_case("und") {
+      "und".to_string()
+    } else {
+      language::normalize_language(language).ok_or_else(|| {
+        Error::Configuration(format!("'{language}' is not a valid ISO 639 language"))
+      })?
+    };
+    let key = CString::new("language").unwrap();
+    let value = CString::new(language).unwrap();
+    unsafe {
+      check_result!(av_dict_set(
+        &mut (*self.stream).metadata,
+        key.as_ptr(),
+        value.as_ptr(),
+        0
+      ));
     }
+    Ok(())
   }
 
   pub fn get_bits_per_raw_sample(&self) -> Option<i32> {
@@ -421,3 +588,23 @@ impl Stream {
     }
   }
 }
+
+#[test]
+fn disposition_flags() {
+  let disposition = Disposition {
+    default: true,
+    hearing_impaired: true,
+    ..Default::default()
+  };
+  let flags = i32::from(disposition);
+  assert_eq!(
+    flags,
+    (AV_DISPOSITION_DEFAULT | AV_DISPOSITION_HEARING_IMPAIRED) as i32
+  );
+  assert_eq!(Disposition::from(flags), disposition);
+  assert!(Disposition::from(AV_DISPOSITION_KARAOKE as i32).is_empty());
+  assert_eq!(
+    serde_json::to_string(&disposition).unwrap(),
+    r#"{"default":true,"hearing_impaired":true}"#
+  );
+}
@@ -0,0 +1,287 @@
+use crate::{
+  error::Error,
+  tools::{self, rational::Rational, timestamp::Timestamp},
+};
+use ffmpeg_sys_next::*;
+use libc::c_char;
+use std::{
+  ffi::CString,
+  mem::{size_of, zeroed},
+  ptr::copy_nonoverlapping,
+  slice,
+};
+
+/// A decoded subtitle, copied out of the `AVSubtitle` filled by the decoder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Subtitle {
+  pub name: Option<String>,
+  pub index: usize,
+  /// Presentation timestamp, in `AV_TIME_BASE` units.
+  pub pts: Option<i64>,
+  /// Display times in milliseconds, relative to `pts`.
+  pub start_display_time: u32,
+  pub end_display_time: u32,
+  pub rects: Vec<SubtitleRect>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubtitleRect {
+  Text(String),
+  /// An ASS dialogue line: "ReadOrder,Layer,Style,Name,MarginL,MarginR,MarginV,Effect,Text".
+  Ass(String),
+  Bitmap(Bitmap),
+}
+
+/// A paletted bitmap, with one palette index per pixel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bitmap {
+  pub x: i32,
+  pub y: i32,
+  pub width: usize,
+  pub height: usize,
+  pub pixels: Vec<u8>,
+  /// ARGB colors, as stored by ffmpeg in `AV_PIX_FMT_PAL8` palettes.
+  pub palette: Vec<u32>,
+  pub forced: bool,
+}
+
+impl Subtitle {
+  /// # Safety
+  /// `subtitle` must point to a valid `AVSubtitle`, which is left untouched.
+  pub unsafe fn from_av_subtitle(
+    subtitle: *const AVSubtitle,
+    name: Option<String>,
+    index: usize,
+  ) -> Self {
+    let subtitle = &*subtitle;
+    let rects = if subtitle.rects.is_null() {
+      vec![]
+    } else {
+      slice::from_raw_parts(subtitle.rects, subtitle.num_rects as usize)
+        .iter()
+        .filter(|rect| !rect.is_null())
+        .filter_map(|rect| SubtitleRect::from_av_rect(*rect))
+        .collect()
+    };
+
+    Subtitle {
+      name,
+      index,
+      pts: if subtitle.pts == AV_NOPTS_VALUE {
+        None
+      } else {
+        Some(subtitle.pts)
+      },
+      start_display_time: subtitle.start_display_time,
+      end_display_time: subtitle.end_display_time,
+      rects,
+    }
+  }
+
+  /// Copy into an `AVSubtitle`, to be released with `avsubtitle_free`.
+  /// Text rects are converted to ASS lines, as expected by the text subtitle encoders.
+  pub(crate) unsafe fn to_av_subtitle(&self) -> Result<AVSubtitle, Error> {
+    let mut subtitle: AVSubtitle = zeroed();
+    subtitle.pts = self.pts.unwrap_or(AV_NOPTS_VALUE);
+    subtitle.start_display_time = self.start_display_time;
+    subtitle.end_display_time = self.end_display_time;
+    if self.rects.is_empty() {
+      return Ok(subtitle);
+    }
+
+    subtitle.rects =
+      av_mallocz(self.rects.len() * size_of::<*mut AVSubtitleRect>()) as *mut *mut AVSubtitleRect;
+    if subtitle.rects.is_null() {
+      return Err(Error::from_av_error(AVERROR(ENOMEM)));
+    }
+    for (index, rect) in self.rects.iter().enumerate() {
+      let av_rect = av_mallocz(size_of::<AVSubtitleRect>()) as *mut AVSubtitleRect;
+      if av_rect.is_null() {
+        avsubtitle_free(&mut subtitle);
+        return Err(Error::from_av_error(AVERROR(ENOMEM)));
+      }
+      *subtitle.rects.add(index) = av_rect;
+      subtitle.num_rects += 1;
+      if let Err(error) = rect.fill_av_rect(&mut *av_rect, index) {
+        avsubtitle_free(&mut subtitle);
+        return Err(error);
+      }
+    }
+    Ok(subtitle)
+  }
+
+  pub fn get_start(&self) -> Option<Timestamp> {
+    self.display_timestamp(self.start_display_time)
+  }
+
+  pub fn get_end(&self) -> Option<Timestamp> {
+    self.display_timestamp(self.end_display_time)
+  }
+
+  /// Text of all the text and ASS rects, one line per rect.
+  pub fn get_text(&self) -> String {
+    self
+      .rects
+      .iter()
+      .filter_map(SubtitleRect::get_text)
+      .collect::<Vec<_>>()
+      .join("\n")
+  }
+
+  fn display_timestamp(&self, display_time: u32) -> Option<Timestamp> {
+    let time_base = Rational::new(1, AV_TIME_BASE as i32);
+    self.pts.map(|pts| {
+      Timestamp::new(
+        pts
+          + Timestamp::from_millis(i64::from(display_time))
+            .rescale(time_base)
+            .value,
+        time_base,
+      )
+    })
+  }
+}
+
+impl SubtitleRect {
+  unsafe fn from_av_rect(rect: *const AVSubtitleRect) -> Option<Self> {
+    let rect = &*rect;
+    match rect.type_ {
+      AVSubtitleType::SUBTITLE_TEXT if !rect.text.is_null() => {
+        Some(SubtitleRect::Text(tools::to_string(rect.text)))
+      }
+      AVSubtitleType::SUBTITLE_ASS if !rect.ass.is_null() => {
+        Some(SubtitleRect::Ass(tools::to_string(rect.ass)))
+      }
+      AVSubtitleType::SUBTITLE_BITMAP if !rect.data[0].is_null() => {
+        let width = rect.w.max(0) as usize;
+        let height = rect.h.max(0) as usize;
+        let linesize = rect.linesize[0].max(0) as usize;
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+          let row = slice::from_raw_parts(rect.data[0].add(y * linesize), width.min(linesize));
+          pixels.extend_from_slice(row);
+        }
+        let palette = if rect.data[1].is_null() {
+          vec![]
+        } else {
+          slice::from_raw_parts(rect.data[1] as *const u32, rect.nb_colors.max(0) as usize).to_vec()
+        };
+
+        Some(SubtitleRect::Bitmap(Bitmap {
+          x: rect.x,
+          y: rect.y,
+          width,
+          height,
+          pixels,
+          palette,
+          forced: rect.flags & AV_SUBTITLE_FLAG_FORCED as i32 != 0,
+        }))
+      }
+      _ => None,
+    }
+  }
+
+  unsafe fn fill_av_rect(&self, rect: &mut AVSubtitleRect, index: usize) -> Result<(), Error> {
+    match self {
+      SubtitleRect::Text(text) => {
+        let line = format!("{index},0,Default,,0,0,0,,{}", text.replace('\n', "\\N"));
+        rect.type_ = AVSubtitleType::SUBTITLE_ASS;
+        rect.ass = strdup(&line)?;
+      }
+      SubtitleRect::Ass(line) => {
+        rect.type_ = AVSubtitleType::SUBTITLE_ASS;
+        rect.ass = strdup(line)?;
+      }
+      SubtitleRect::Bitmap(bitmap) => {
+        if bitmap.pixels.len() < bitmap.width * bitmap.height || bitmap.palette.len() > 256 {
+          return Err(Error::Configuration(format!(
+            "Invalid {}x{} subtitle bitmap",
+            bitmap.width, bitmap.height
+          )));
+        }
+        rect.type_ = AVSubtitleType::SUBTITLE_BITMAP;
+        rect.x = bitmap.x;
+        rect.y = bitmap.y;
+        rect.w = bitmap.width as i32;
+        rect.h = bitmap.height as i32;
+        rect.linesize[0] = bitmap.width as i32;
+        rect.nb_colors = bitmap.palette.len() as i32;
+        if bitmap.forced {
+          rect.flags |= AV_SUBTITLE_FLAG_FORCED as i32;
+        }
+
+        rect.data[0] = av_malloc(bitmap.width * bitmap.height) as *mut u8;
+        rect.data[1] = av_mallocz(AVPALETTE_SIZE as usize) as *mut u8;
+        if rect.data[0].is_null() || rect.data[1].is_null() {
+          return Err(Error::from_av_error(AVERROR(ENOMEM)));
+        }
+        copy_nonoverlapping(
+          bitmap.pixels.as_ptr(),
+          rect.data[0],
+          bitmap.width * bitmap.height,
+        );
+        copy_nonoverlapping(
+          bitmap.palette.as_ptr(),
+          rect.data[1] as *mut u32,
+          bitmap.palette.len(),
+        );
+      }
+    }
+    Ok(())
+  }
+
+  /// Plain text of the rect, with the ASS header fields and override tags removed.
+  pub fn get_text(&self) -> Option<String> {
+    match self {
+      SubtitleRect::Text(text) => Some(text.clone()),
+      SubtitleRect::Ass(line) => Some(ass_to_text(line)),
+      SubtitleRect::Bitmap(_) => None,
+    }
+  }
+}
+
+unsafe fn strdup(value: &str) -> Result<*mut c_char, Error> {
+  let value = CString::new(value)
+    .map_err(|_| Error::Configuration("Subtitle text contains a NUL character".to_string()))?;
+  let copy = av_strdup(value.as_ptr());
+  if copy.is_null() {
+    return Err(Error::from_av_error(AVERROR(ENOMEM)));
+  }
+  Ok(copy)
+}
+
+fn ass_to_text(line: &str) -> String {
+  let dialogue = line.strip_prefix("Dialogue:").unwrap_or(line);
+  let text = dialogue.splitn(9, ',').nth(8).unwrap_or(dialogue);
+
+  let mut result = String::with_capacity(text.len());
+  let mut in_override = false;
+  for c in text.chars() {
+    match c {
+      '{' => in_override = true,
+      '}' if in_override => in_override = false,
+      _ if !in_override => result.push(c),
+      _ => {}
+    }
+  }
+  result
+    .replace("\\N", "\n")
+    .replace("\\n", "\n")
+    .replace("\\h", "\u{a0}")
+}
+
+#[test]
+fn subtitle_text() {
+  let subtitle = Subtitle {
+    name: None,
+    index: 0,
+    pts: Some(2_000_000),
+    start_display_time: 0,
+    end_display_time: 1500,
+    rects: vec![SubtitleRect::Ass(
+      "0,0,Default,,0,0,0,,{\\i1}Hello,{\\i0} world\\Nsecond line".to_string(),
+    )],
+  };
+  assert_eq!(subtitle.get_text(), "Hello, world\nsecond line");
+  assert_eq!(subtitle.get_end().unwrap().to_millis(), 3500);
+}
@@ -1,5 +1,6 @@
-use crate::{format_context::FormatContext, tools};
+use crate::{error::Error, format_context::FormatContext, packet::Packet, subtitle::Subtitle};
 use ffmpeg_sys_next::*;
+use std::{mem::zeroed, ptr::null_mut, slice};
 
 #[derive(Debug)]
 pub struct SubtitleDecoder {
@@ -13,20 +14,23 @@ impl SubtitleDecoder {
     identifier: String,
     format: &FormatContext,
     stream_index: isize,
-  ) -> Result<Self, String> {
+  ) -> Result<Self, Error> {
     unsafe {
       let codec = avcodec_find_decoder(format.get_codec_id(stream_index));
       let mut codec_context = avcodec_alloc_context3(codec);
+      let stream = *(*format.format_context).streams.offset(stream_index);
 
       check_result!(
-        avcodec_parameters_to_context(
-          codec_context,
-          (**(*format.format_context).streams.offset(stream_index)).codecpar
-        ),
+        avcodec_parameters_to_context(codec_context, (*stream).codecpar),
         {
           avcodec_free_context(&mut codec_context);
         }
       );
+      // required to get the subtitle pts in AV_TIME_BASE, and the duration of text subtitles
+      (*codec_context).pkt_timebase = (*stream).time_base;
+      check_result!(avcodec_open2(codec_context, codec, null_mut()), {
+        avcodec_free_context(&mut codec_context);
+      });
 
       Ok(SubtitleDecoder {
         identifier,
@@ -35,6 +39,66 @@ impl SubtitleDecoder {
       })
     }
   }
+
+  /// ASS header set by text subtitle decoders, to be given to the subtitle encoders.
+  pub fn get_subtitle_header(&self) -> Option<&[u8]> {
+    unsafe {
+      let header = (*self.codec_context).subtitle_header;
+      let size = (*self.codec_context).subtitle_header_size;
+      if header.is_null() || size <= 0 {
+        return None;
+      }
+      Some(slice::from_raw_parts(header, size as usize))
+    }
+  }
+
+  /// Decode a subtitle packet. `Ok(None)` when the packet does not complete a subtitle.
+  pub fn decode(&self, packet: &Packet) -> Result<Option<Subtitle>, Error> {
+    if packet.get_stream_index() != self.stream_index {
+      return Err(Error::Configuration("bad stream".to_string()));
+    }
+    self.decode_packet(packet)
+  }
+
+  /// Signal the end of the stream, and return the subtitles still buffered by the decoder.
+  pub fn drain(&self) -> Result<Vec<Subtitle>, Error> {
+    let mut subtitles = vec![];
+    unsafe {
+      if (*(*self.codec_context).codec).capabilities & AV_CODEC_CAP_DELAY as i32 == 0 {
+        return Ok(subtitles);
+      }
+    }
+    // an empty packet asks the decoder for its delayed subtitles
+    let packet = Packet::new()?;
+    while let Some(subtitle) = self.decode_packet(&packet)? {
+      subtitles.push(subtitle);
+    }
+    Ok(subtitles)
+  }
+
+  fn decode_packet(&self, packet: &Packet) -> Result<Option<Subtitle>, Error> {
+    unsafe {
+      let mut subtitle: AVSubtitle = zeroed();
+      let mut got_subtitle = 0;
+      check_result!(avcodec_decode_subtitle2(
+        self.codec_context,
+        &mut subtitle,
+        &mut got_subtitle,
+        packet.packet
+      ));
+      if got_subtitle == 0 {
+        return Ok(None);
+      }
+
+      let decoded = Subtitle::from_av_subtitle(
+        &subtitle,
+        Some(self.identifier.clone()),
+        self.stream_index as usize,
+      );
+      avsubtitle_free(&mut subtitle);
+      Ok(Some(decoded))
+    }
+  }
 }
 
 impl Drop for SubtitleDecoder {
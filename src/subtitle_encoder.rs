@@ -1,40 +1,199 @@
 use crate::{
-  order::{output::OutputStream, parameters::ParameterValue},
-  tools,
+  error::Error,
+  order::{
+    output::OutputStream,
+    parameters::{self, ParameterValue},
+  },
+  packet::Packet,
+  subtitle::Subtitle,
+  tools::{self, rational::Rational},
 };
 use ffmpeg_sys_next::*;
+use std::ptr::copy_nonoverlapping;
+
+/// Largest packet produced by `avcodec_encode_subtitle`, as in the ffmpeg command line.
+const SUBTITLE_MAX_SIZE: usize = 1024 * 1024;
+
+/// ASS header used by the text subtitle encoders when the decoder does not provide one.
+const DEFAULT_ASS_HEADER: &str = "[Script Info]
+; Script generated by FFmpeg/Lavc
+ScriptType: v4.00+
+PlayResX: 384
+PlayResY: 288
+ScaledBorderAndShadow: yes
+YCbCr Matrix: None
+
+[V4+ Styles]
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding
+Style: Default,Arial,16,&Hffffff,&Hffffff,&H0,&H0,0,0,0,0,100,100,0,0,1,1,0,2,10,10,10,1
+
+[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+";
+
+/// Parameters handled by the encoder, the others are passed as codec options.
+const SUBTITLE_PARAMETERS: &[&str] = &["frame_rate", "input_frame_rate", "time_offset"];
 
 #[derive(Debug)]
 pub struct SubtitleEncoder {
   pub identifier: String,
   pub stream_index: isize,
   pub codec_context: *mut AVCodecContext,
+  /// Shift applied to every cue, in `AV_TIME_BASE` units.
+  time_offset: i64,
+  /// Ratio between the source and output frame rates, applied to the cue timings.
+  speed: Rational,
 }
 
 impl SubtitleEncoder {
+  /// `subtitle_header` is the ASS header of the decoder, used by text subtitle encoders.
+  ///
+  /// Cue timings can be shifted with the `time_offset` parameter (in milliseconds),
+  /// and converted from `input_frame_rate` to `frame_rate`.
   pub fn new(
     identifier: String,
     stream_index: isize,
     output_stream: &OutputStream,
-  ) -> Result<Self, String> {
+    subtitle_header: Option<&[u8]>,
+  ) -> Result<Self, Error> {
+    let parameters = &output_stream.parameters;
+    let frame_rate = match parameters.get("frame_rate") {
+      Some(ParameterValue::Rational(data)) => Some(*data),
+      _ => None,
+    };
+    let speed = match (parameters.get("input_frame_rate"), frame_rate) {
+      (Some(ParameterValue::Rational(input_frame_rate)), Some(frame_rate)) => {
+        if input_frame_rate.num <= 0 || frame_rate.num <= 0 {
+          return Err(Error::Configuration(format!(
+            "Invalid subtitle frame rate conversion from {input_frame_rate} to {frame_rate}"
+          )));
+        }
+        (*input_frame_rate / frame_rate).reduce()
+      }
+      (Some(_), _) => {
+        return Err(Error::Configuration(
+          "Subtitle frame rate conversion requires an input_frame_rate and a frame_rate"
+            .to_string(),
+        ))
+      }
+      (None, _) => Rational::from(1),
+    };
+    let time_offset = match parameters.get("time_offset") {
+      Some(ParameterValue::Int64(milliseconds)) => milliseconds * 1000,
+      _ => 0,
+    };
+
     unsafe {
       let codec = tools::get_codec(&output_stream.codec);
       if codec.is_null() {
-        return Err(format!("Unable to found codec {}", output_stream.codec));
+        return Err(Error::CodecNotFound {
+          code: AVERROR_ENCODER_NOT_FOUND,
+          message: format!("Unable to found codec {}", output_stream.codec),
+        });
+      }
+      let mut codec_context = avcodec_alloc_context3(codec);
+
+      (*codec_context).time_base = match frame_rate {
+        Some(frame_rate) => frame_rate.invert().into(),
+        None => AV_TIME_BASE_Q,
+      };
+
+      let descriptor = avcodec_descriptor_get((*codec).id);
+      if !descriptor.is_null() && (*descriptor).props & AV_CODEC_PROP_TEXT_SUB as i32 != 0 {
+        let header = subtitle_header.unwrap_or_else(|| DEFAULT_ASS_HEADER.as_bytes());
+        // freed by avcodec_free_context
+        let data = av_mallocz(header.len() + 1) as *mut u8;
+        if data.is_null() {
+          avcodec_free_context(&mut codec_context);
+          return Err(Error::from_av_error(AVERROR(ENOMEM)));
+        }
+        copy_nonoverlapping(header.as_ptr(), data, header.len());
+        (*codec_context).subtitle_header = data;
+        (*codec_context).subtitle_header_size = header.len() as i32;
       }
-      let codec_context = avcodec_alloc_context3(codec);
 
-      if let Some(ParameterValue::Rational(data)) = output_stream.parameters.get("frame_rate") {
-        (*codec_context).time_base = data.clone().invert().into();
+      if let Err(error) =
+        parameters::open_encoder(codec_context, codec, parameters, SUBTITLE_PARAMETERS)
+      {
+        avcodec_free_context(&mut codec_context);
+        return Err(error);
       }
 
       Ok(SubtitleEncoder {
         identifier,
         stream_index,
         codec_context,
+        time_offset,
+        speed,
       })
     }
   }
+
+  /// Encode a subtitle into a packet, with its timestamps in the encoder time base.
+  /// `Ok(None)` when the cue ends before the start of the output, or encodes to nothing.
+  pub fn encode(&self, subtitle: &Subtitle) -> Result<Option<Packet>, Error> {
+    let pts = subtitle.pts.ok_or_else(|| {
+      Error::Configuration(format!(
+        "Missing timestamp on subtitle from {}",
+        self.identifier
+      ))
+    })?;
+    let units_per_ms = i64::from(AV_TIME_BASE) / 1000;
+    let start = self.retime(pts + i64::from(subtitle.start_display_time) * units_per_ms);
+    let end = self.retime(pts + i64::from(subtitle.end_display_time) * units_per_ms);
+    if end <= 0 && subtitle.end_display_time > subtitle.start_display_time {
+      return Ok(None);
+    }
+    let start = start.max(0);
+    let duration = (end - start).max(0) / units_per_ms;
+
+    unsafe {
+      let mut av_subtitle = subtitle.to_av_subtitle()?;
+      av_subtitle.pts = start;
+      av_subtitle.start_display_time = 0;
+      av_subtitle.end_display_time = duration as u32;
+
+      let mut buffer = vec![0u8; SUBTITLE_MAX_SIZE];
+      let size = avcodec_encode_subtitle(
+        self.codec_context,
+        buffer.as_mut_ptr(),
+        buffer.len() as i32,
+        &av_subtitle,
+      );
+      avsubtitle_free(&mut av_subtitle);
+      check_result!(size);
+      if size == 0 {
+        return Ok(None);
+      }
+
+      let mut packet = Packet::new()?;
+      check_result!(av_new_packet(packet.packet, size));
+      copy_nonoverlapping(buffer.as_ptr(), (*packet.packet).data, size as usize);
+
+      let time_base = (*self.codec_context).time_base;
+      let pts = av_rescale_q(start, AV_TIME_BASE_Q, time_base);
+      packet.set_pts(Some(pts));
+      packet.set_dts(Some(pts));
+      packet.set_duration(av_rescale_q(
+        duration,
+        AVRational { num: 1, den: 1000 },
+        time_base,
+      ));
+      packet.set_stream_index(self.stream_index);
+      packet.name = Some(self.identifier.clone());
+      Ok(Some(packet))
+    }
+  }
+
+  /// Apply the frame rate conversion and the time offset to a timestamp in `AV_TIME_BASE`.
+  fn retime(&self, timestamp: i64) -> i64 {
+    retime(timestamp, self.speed, self.time_offset)
+  }
+}
+
+fn retime(timestamp: i64, speed: Rational, time_offset: i64) -> i64 {
+  let scaled = i128::from(timestamp) * i128::from(speed.num) / i128::from(speed.den);
+  scaled as i64 + time_offset
 }
 
 impl Drop for SubtitleEncoder {
@@ -47,3 +206,10 @@ impl Drop for SubtitleEncoder {
     }
   }
 }
+
+#[test]
+fn subtitle_retime() {
+  let film_to_pal = Rational::new(24000, 1001) / Rational::from(25);
+  assert_eq!(retime(25_025_000, film_to_pal, 0), 24_000_000);
+  assert_eq!(retime(1_000_000, Rational::from(1), -500_000), 500_000);
+}
@@ -7,7 +7,10 @@ use std::{
   str::from_utf8_unchecked,
 };
 
+pub mod language;
 pub mod rational;
+pub mod timecode;
+pub mod timestamp;
 
 /// # Safety
 pub unsafe fn from_buf_raw<T>(ptr: *const T, elts: usize) -> Vec<T> {
@@ -1,7 +1,14 @@
+use crate::error::Error;
 use ffmpeg_sys_next::*;
-use std::mem::swap;
+use std::{
+  cmp::Ordering,
+  fmt,
+  mem::swap,
+  ops::{Add, Div, Mul, Neg, Sub},
+  str::FromStr,
+};
 
-#[derive(Debug, Clone, Deserialize, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
 pub struct Rational {
   pub num: i32,
   pub den: i32,
@@ -18,10 +25,66 @@ impl Rational {
   }
 
   pub fn reduce(self) -> Self {
-    let gcd = gcd(self.num, self.den);
+    let gcd = gcd(i64::from(self.num), i64::from(self.den));
+    if gcd == 0 {
+      return self;
+    }
+    // the gcd is positive, so the quotients fit in 32 bits
     Rational {
-      num: self.num / gcd,
-      den: self.den / gcd,
+      num: (i64::from(self.num) / gcd) as i32,
+      den: (i64::from(self.den) / gcd) as i32,
+    }
+  }
+
+  pub fn to_f64(&self) -> f64 {
+    f64::from(self.num) / f64::from(self.den)
+  }
+
+  /// Integer part, truncated toward zero. 0 when the denominator is 0.
+  pub fn to_i64(&self) -> i64 {
+    if self.den == 0 {
+      return 0;
+    }
+    i64::from(self.num) / i64::from(self.den)
+  }
+
+  /// Parse a frame rate, mapping NTSC decimal notations such as "29.97" to 30000/1001.
+  pub fn parse_frame_rate(value: &str) -> Result<Self, Error> {
+    let rational: Rational = value.parse()?;
+    if !value.contains('.') {
+      return Ok(rational);
+    }
+    for base in [24, 30, 48, 60, 120] {
+      let ntsc = Rational::new(base * 1000, 1001);
+      if (rational.to_f64() - ntsc.to_f64()).abs() < 0.005 {
+        return Ok(ntsc);
+      }
+    }
+    Ok(rational)
+  }
+
+  /// Reduce a 64 bits fraction, approximating it when it does not fit in 32 bits.
+  fn from_i64(num: i64, den: i64) -> Self {
+    let mut rational = Rational::new(0, 1);
+    unsafe {
+      av_reduce(
+        &mut rational.num,
+        &mut rational.den,
+        num,
+        den,
+        i64::from(i32::MAX),
+      );
+    }
+    rational
+  }
+
+  fn normalized(&self) -> (i64, i64) {
+    let reduced = self.reduce();
+    let (num, den) = (i64::from(reduced.num), i64::from(reduced.den));
+    if den < 0 {
+      (-num, -den)
+    } else {
+      (num, den)
     }
   }
 }
@@ -35,7 +98,167 @@ impl From<Rational> for AVRational {
   }
 }
 
-fn gcd(x: i32, y: i32) -> i32 {
+impl From<AVRational> for Rational {
+  fn from(ratio: AVRational) -> Rational {
+    Rational {
+      num: ratio.num,
+      den: ratio.den,
+    }
+  }
+}
+
+impl From<i32> for Rational {
+  fn from(value: i32) -> Rational {
+    Rational { num: value, den: 1 }
+  }
+}
+
+/// Fractions with a null denominator are unequal to any value, like a NaN.
+impl PartialEq for Rational {
+  fn eq(&self, other: &Self) -> bool {
+    self.partial_cmp(other) == Some(Ordering::Equal)
+  }
+}
+
+impl PartialOrd for Rational {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    let (num, den) = self.normalized();
+    let (other_num, other_den) = other.normalized();
+    if den == 0 || other_den == 0 {
+      return None;
+    }
+    (num * other_den).partial_cmp(&(other_num * den))
+  }
+}
+
+impl Add for Rational {
+  type Output = Rational;
+
+  fn add(self, other: Rational) -> Rational {
+    Rational::from_i64(
+      i64::from(self.num) * i64::from(other.den) + i64::from(other.num) * i64::from(self.den),
+      i64::from(self.den) * i64::from(other.den),
+    )
+  }
+}
+
+impl Sub for Rational {
+  type Output = Rational;
+
+  fn sub(self, other: Rational) -> Rational {
+    Rational::from_i64(
+      i64::from(self.num) * i64::from(other.den) - i64::from(other.num) * i64::from(self.den),
+      i64::from(self.den) * i64::from(other.den),
+    )
+  }
+}
+
+impl Mul for Rational {
+  type Output = Rational;
+
+  fn mul(self, other: Rational) -> Rational {
+    Rational::from_i64(
+      i64::from(self.num) * i64::from(other.num),
+      i64::from(self.den) * i64::from(other.den),
+    )
+  }
+}
+
+impl Div for Rational {
+  type Output = Rational;
+
+  fn div(self, other: Rational) -> Rational {
+    Rational::from_i64(
+      i64::from(self.num) * i64::from(other.den),
+      i64::from(self.den) * i64::from(other.num),
+    )
+  }
+}
+
+impl Mul<i32> for Rational {
+  type Output = Rational;
+
+  fn mul(self, other: i32) -> Rational {
+    self * Rational::from(other)
+  }
+}
+
+impl Div<i32> for Rational {
+  type Output = Rational;
+
+  fn div(self, other: i32) -> Rational {
+    self / Rational::from(other)
+  }
+}
+
+impl Neg for Rational {
+  type Output = Rational;
+
+  fn neg(self) -> Rational {
+    match self.num.checked_neg() {
+      Some(num) => Rational::new(num, self.den),
+      None => Rational::from_i64(-i64::from(self.num), i64::from(self.den)),
+    }
+  }
+}
+
+impl fmt::Display for Rational {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}/{}", self.num, self.den)
+  }
+}
+
+/// Parse "30000/1001", "16:9", "25" or an exact decimal such as "29.97" (2997/100).
+impl FromStr for Rational {
+  type Err = Error;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    let value = value.trim();
+
+    if let Some((num, den)) = value.split_once('/').or_else(|| value.split_once(':')) {
+      if let (Ok(num), Ok(den)) = (num.trim().parse(), den.trim().parse()) {
+        return Ok(Rational::new(num, den));
+      }
+    } else if let Some(rational) = parse_decimal(value) {
+      return Ok(rational);
+    } else if let Ok(float) = value.parse::<f64>() {
+      if float.is_finite() {
+        return Ok(unsafe { av_d2q(float, i32::MAX) }.into());
+      }
+    }
+    Err(Error::Configuration(format!(
+      "Invalid rational value: {value}"
+    )))
+  }
+}
+
+fn parse_decimal(value: &str) -> Option<Rational> {
+  let (integer, decimals) = value.split_once('.').unwrap_or((value, ""));
+  if decimals.len() > 18 || !decimals.chars().all(|c| c.is_ascii_digit()) {
+    return None;
+  }
+  let negative = integer.starts_with('-');
+  let integer: i64 = match integer {
+    "" if !decimals.is_empty() => 0,
+    _ => integer.parse().ok()?,
+  };
+  let den = 10_i64.pow(decimals.len() as u32);
+  let fraction: i64 = if decimals.is_empty() {
+    0
+  } else {
+    decimals.parse().ok()?
+  };
+  let num = integer.checked_mul(den)?;
+  let num = if negative {
+    num.checked_sub(fraction)?
+  } else {
+    num.checked_add(fraction)?
+  };
+  Some(Rational::from_i64(num, den))
+}
+
+/// Positive greatest common divisor, 0 when both values are 0.
+fn gcd(x: i64, y: i64) -> i64 {
   let mut x = x;
   let mut y = y;
   while y != 0 {
@@ -43,7 +266,7 @@ fn gcd(x: i32, y: i32) -> i32 {
     y = x % y;
     x = t;
   }
-  x
+  x.abs()
 }
 
 #[test]
@@ -61,3 +284,56 @@ fn rational() {
   assert!(av_r.num == 2);
   assert!(av_r.den == 1);
 }
+
+#[test]
+fn rational_arithmetic() {
+  let third = Rational::new(1, 3);
+  let half = Rational::new(1, 2);
+  assert_eq!(third + half, Rational::new(5, 6));
+  assert_eq!(third - half, Rational::new(-1, 6));
+  assert_eq!(third * half, Rational::new(1, 6));
+  assert_eq!(third / half, Rational::new(2, 3));
+  assert_eq!(half * 4, Rational::from(2));
+  assert_eq!(Rational::new(2, 4), half);
+  assert!(third < half);
+  assert_eq!((Rational::new(7, 2)).to_i64(), 3);
+
+  assert_eq!(
+    "30000/1001".parse::<Rational>().unwrap(),
+    Rational::new(30000, 1001)
+  );
+  assert_eq!(
+    "29.97".parse::<Rational>().unwrap(),
+    Rational::new(2997, 100)
+  );
+  assert_eq!("-1.5".parse::<Rational>().unwrap(), Rational::new(-3, 2));
+  assert_eq!(
+    Rational::parse_frame_rate("29.97").unwrap(),
+    Rational::new(30000, 1001)
+  );
+  assert_eq!(
+    Rational::parse_frame_rate("25").unwrap(),
+    Rational::from(25)
+  );
+  assert_eq!(
+    "5e-05".parse::<Rational>().unwrap(),
+    Rational::new(1, 20000)
+  );
+  assert!("abc".parse::<Rational>().is_err());
+  for value in ["", "-", "+", ".", "-.", "+."] {
+    assert!(value.parse::<Rational>().is_err(), "{value:?}");
+  }
+  assert_eq!(".5".parse::<Rational>().unwrap(), Rational::new(1, 2));
+
+  // null denominators are not ordered, nor equal
+  assert_ne!(Rational::new(0, 0), Rational::new(0, 0));
+  assert_ne!(Rational::new(1, 0), Rational::new(1, 0));
+  assert_eq!(Rational::new(1, 0).partial_cmp(&half), None);
+
+  assert_eq!(
+    Rational::new(i32::MIN, -1).reduce(),
+    Rational::new(i32::MIN, -1)
+  );
+  assert_eq!(-Rational::new(i32::MIN, 2), Rational::new(1 << 30, 1));
+  assert!(-Rational::new(i32::MIN, 1) > Rational::from(i32::MAX - 1));
+}
@@ -0,0 +1,315 @@
+use crate::{
+  error::Error,
+  tools::{rational::Rational, timestamp::Timestamp},
+};
+use std::{
+  cmp::Ordering,
+  fmt,
+  ops::{Add, Sub},
+};
+
+/// SMPTE timecode "HH:MM:SS:FF" at a given frame rate.
+///
+/// Drop-frame timecodes ("HH:MM:SS;FF") skip the first 2 frame numbers (4 at 60 fps)
+/// of every minute except each tenth one, to follow NTSC rates such as 30000/1001.
+#[derive(Debug, Clone, Copy)]
+pub struct Timecode {
+  pub hours: u32,
+  pub minutes: u32,
+  pub seconds: u32,
+  pub frames: u32,
+  pub drop_frame: bool,
+  pub frame_rate: Rational,
+}
+
+impl Timecode {
+  pub fn new(
+    hours: u32,
+    minutes: u32,
+    seconds: u32,
+    frames: u32,
+    frame_rate: Rational,
+    drop_frame: bool,
+  ) -> Result<Self, Error> {
+    let fps = nominal_fps(frame_rate)?;
+    if drop_frame && fps % 30 != 0 {
+      return Err(Error::Configuration(format!(
+        "Drop frame timecode is not supported at {frame_rate} fps"
+      )));
+    }
+    let dropped = drop_frame
+      && seconds == 0
+      && i64::from(minutes) % 10 != 0
+      && i64::from(frames) < drop_frames(fps);
+    if hours >= 24 || minutes >= 60 || seconds >= 60 || i64::from(frames) >= fps || dropped {
+      return Err(Error::Configuration(format!(
+        "Invalid timecode {hours:02}:{minutes:02}:{seconds:02}:{frames:02} at {frame_rate} fps"
+      )));
+    }
+    Ok(Timecode {
+      hours,
+      minutes,
+      seconds,
+      frames,
+      drop_frame,
+      frame_rate,
+    })
+  }
+
+  /// Timecode of the nth frame counted from 00:00:00:00, wrapping around 24 hours.
+  pub fn from_frame_number(
+    frame_number: i64,
+    frame_rate: Rational,
+    drop_frame: bool,
+  ) -> Result<Self, Error> {
+    let fps = nominal_fps(frame_rate)?;
+    let mut frame_number = frame_number.rem_euclid(frames_per_day(fps, drop_frame));
+    if drop_frame {
+      let drops = drop_frames(fps);
+      let frames_per_10_minutes = fps * 600 - 9 * drops;
+      let tens = frame_number / frames_per_10_minutes;
+      let remainder = frame_number % frames_per_10_minutes;
+      frame_number +=
+        9 * drops * tens + drops * ((remainder - drops) / (frames_per_10_minutes / 10));
+    }
+    Timecode::new(
+      (frame_number / (fps * 3600) % 24) as u32,
+      (frame_number / (fps * 60) % 60) as u32,
+      (frame_number / fps % 60) as u32,
+      (frame_number % fps) as u32,
+      frame_rate,
+      drop_frame,
+    )
+  }
+
+  pub fn from_timestamp(
+    timestamp: Timestamp,
+    frame_rate: Rational,
+    drop_frame: bool,
+  ) -> Result<Self, Error> {
+    let frame_number = timestamp.rescale(frame_rate.invert()).value;
+    Timecode::from_frame_number(frame_number, frame_rate, drop_frame)
+  }
+
+  /// Parse "HH:MM:SS:FF". A ';', '.' or ',' before the frames marks a drop-frame timecode.
+  pub fn parse(value: &str, frame_rate: Rational) -> Result<Self, Error> {
+    let invalid = || Error::Configuration(format!("Invalid timecode: {value}"));
+    let value = value.trim();
+    let separator = value.rfind([':', ';', '.', ',']).ok_or_else(invalid)?;
+    let drop_frame = !value[separator..].starts_with(':');
+    let fields = value[..separator]
+      .split([':', ';'])
+      .map(|field| field.parse::<u32>().map_err(|_| invalid()))
+      .collect::<Result<Vec<_>, _>>()?;
+    let frames = value[separator + 1..]
+      .parse::<u32>()
+      .map_err(|_| invalid())?;
+    match fields.as_slice() {
+      [hours, minutes, seconds] => {
+        Timecode::new(*hours, *minutes, *seconds, frames, frame_rate, drop_frame)
+      }
+      _ => Err(invalid()),
+    }
+  }
+
+  /// Decode a SMPTE ST 12-1 binary timecode, as stored in S12M side data.
+  pub fn from_smpte_12m(value: u32, frame_rate: Rational) -> Result<Self, Error> {
+    let hours = from_bcd(value & 0x3f)?;
+    let minutes = from_bcd((value >> 8) & 0x7f)?;
+    let seconds = from_bcd((value >> 16) & 0x7f)?;
+    let mut frames = from_bcd((value >> 24) & 0x3f)?;
+    let fps = nominal_fps(frame_rate)?;
+    // above 30 fps the frame count is stored in pairs, with a field bit for the parity
+    if fps > 30 {
+      let field = if frame_rate == Rational::from(50) {
+        (value >> 7) & 1
+      } else {
+        (value >> 23) & 1
+      };
+      frames = frames * 2 + field;
+    }
+    let drop_frame = value & (1 << 30) != 0 && fps % 30 == 0;
+    Timecode::new(hours, minutes, seconds, frames, frame_rate, drop_frame)
+  }
+
+  /// Decode the 25 bits timecode of a MPEG-1/2 GOP header.
+  pub fn from_gop(value: u32, frame_rate: Rational) -> Result<Self, Error> {
+    let fps = nominal_fps(frame_rate)?;
+    Timecode::new(
+      (value >> 19) & 0x1f,
+      (value >> 13) & 0x3f,
+      (value >> 6) & 0x3f,
+      value & 0x3f,
+      frame_rate,
+      value & (1 << 24) != 0 && fps % 30 == 0,
+    )
+  }
+
+  /// Find a GOP header in MPEG-1/2 video data and decode its timecode.
+  pub fn from_gop_header(data: &[u8], frame_rate: Rational) -> Option<Self> {
+    let header = data
+      .windows(8)
+      .find(|window| window[..4] == [0x00, 0x00, 0x01, 0xb8])?;
+    let value = (u32::from(header[4]) << 17)
+      | (u32::from(header[5]) << 9)
+      | (u32::from(header[6]) << 1)
+      | (u32::from(header[7]) >> 7);
+    Timecode::from_gop(value, frame_rate).ok()
+  }
+
+  /// Decode S12M side data: a count followed by up to 3 SMPTE 12M timecodes.
+  pub fn from_s12m_side_data(data: &[u8], frame_rate: Rational) -> Vec<Self> {
+    let mut words = data
+      .chunks_exact(4)
+      .map(|word| u32::from_ne_bytes([word[0], word[1], word[2], word[3]]));
+    let count = words.next().unwrap_or(0).min(3) as usize;
+    words
+      .take(count)
+      .filter_map(|value| Timecode::from_smpte_12m(value, frame_rate).ok())
+      .collect()
+  }
+
+  /// Number of frames elapsed since 00:00:00:00.
+  pub fn to_frame_number(&self) -> i64 {
+    let fps = nominal_fps(self.frame_rate).unwrap_or(1);
+    let total_minutes = i64::from(self.hours) * 60 + i64::from(self.minutes);
+    let frame_number =
+      (total_minutes * 60 + i64::from(self.seconds)) * fps + i64::from(self.frames);
+    if self.drop_frame {
+      frame_number - drop_frames(fps) * (total_minutes - total_minutes / 10)
+    } else {
+      frame_number
+    }
+  }
+
+  pub fn to_timestamp(&self) -> Timestamp {
+    Timestamp::new(self.to_frame_number(), self.frame_rate.invert())
+  }
+}
+
+/// Frames per second counted by the timecode, e.g. 30 for 30000/1001.
+fn nominal_fps(frame_rate: Rational) -> Result<i64, Error> {
+  if frame_rate.num <= 0 || frame_rate.den <= 0 {
+    return Err(Error::Configuration(format!(
+      "Invalid timecode frame rate: {frame_rate}"
+    )));
+  }
+  let (num, den) = (i64::from(frame_rate.num), i64::from(frame_rate.den));
+  Ok(((num + den / 2) / den).max(1))
+}
+
+fn drop_frames(fps: i64) -> i64 {
+  fps / 30 * 2
+}
+
+fn frames_per_day(fps: i64, drop_frame: bool) -> i64 {
+  if drop_frame {
+    fps * 86400 - drop_frames(fps) * (24 * 60 - 24 * 6)
+  } else {
+    fps * 86400
+  }
+}
+
+fn from_bcd(value: u32) -> Result<u32, Error> {
+  if value & 0xf > 9 || (value >> 4) > 9 {
+    return Err(Error::Configuration(format!(
+      "Invalid BCD timecode digit: {value:#x}"
+    )));
+  }
+  Ok((value >> 4) * 10 + (value & 0xf))
+}
+
+impl Add<i64> for Timecode {
+  type Output = Timecode;
+
+  /// Move forward by a number of frames, wrapping around 24 hours.
+  fn add(self, frames: i64) -> Timecode {
+    Timecode::from_frame_number(
+      self.to_frame_number() + frames,
+      self.frame_rate,
+      self.drop_frame,
+    )
+    .unwrap_or(self)
+  }
+}
+
+impl Sub<i64> for Timecode {
+  type Output = Timecode;
+
+  fn sub(self, frames: i64) -> Timecode {
+    Timecode::from_frame_number(
+      self.to_frame_number() - frames,
+      self.frame_rate,
+      self.drop_frame,
+    )
+    .unwrap_or(self)
+  }
+}
+
+/// Timecodes are equal when they address the same instant, whatever their frame rate or counting.
+impl PartialEq for Timecode {
+  fn eq(&self, other: &Self) -> bool {
+    self.partial_cmp(other) == Some(Ordering::Equal)
+  }
+}
+
+impl PartialOrd for Timecode {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    self.to_timestamp().partial_cmp(&other.to_timestamp())
+  }
+}
+
+impl fmt::Display for Timecode {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "{:02}:{:02}:{:02}{}{:02}",
+      self.hours,
+      self.minutes,
+      self.seconds,
+      if self.drop_frame { ';' } else { ':' },
+      self.frames
+    )
+  }
+}
+
+#[test]
+fn timecode() {
+  let ntsc = Rational::new(30000, 1001);
+  let timecode = Timecode::parse("00:01:00;02", ntsc).unwrap();
+  assert!(timecode.drop_frame);
+  assert_eq!(timecode.to_frame_number(), 1800);
+  assert_eq!((timecode - 1).to_string(), "00:00:59;29");
+  assert_eq!(
+    Timecode::from_frame_number(17982, ntsc, true)
+      .unwrap()
+      .to_string(),
+    "00:10:00;00"
+  );
+  assert!(Timecode::parse("00:01:00;00", ntsc).is_err());
+
+  // the same frame, with and without drop frame counting
+  let non_drop_frame = Timecode::parse("00:01:00:00", ntsc).unwrap();
+  assert_eq!(timecode, non_drop_frame);
+  assert_eq!(timecode.partial_cmp(&non_drop_frame), Some(Ordering::Equal));
+  assert!(timecode - 1 < non_drop_frame);
+  assert!(Timecode::parse("00:00:00;00", Rational::from(25)).is_err());
+
+  let pal = Rational::from(25);
+  let timecode = Timecode::parse("23:59:59:24", pal).unwrap();
+  assert_eq!((timecode + 1).to_string(), "00:00:00:00");
+  assert_eq!(timecode.to_timestamp().to_millis(), 86_399_960);
+  assert!(Timecode::parse("00:00:00:25", pal).is_err());
+
+  assert_eq!(
+    Timecode::from_smpte_12m(0x1530_2010, pal)
+      .unwrap()
+      .to_string(),
+    "10:20:30:15"
+  );
+  let gop = [0x00, 0x00, 0x01, 0xb8, 0x00, 0x28, 0x60, 0x80];
+  assert_eq!(
+    Timecode::from_gop_header(&gop, pal).unwrap().to_string(),
+    "00:02:03:01"
+  );
+}
@@ -0,0 +1,124 @@
+use crate::tools::rational::Rational;
+use std::{cmp::Ordering, fmt, time::Duration};
+
+/// A timestamp expressed as a count of `time_base` units.
+#[derive(Debug, Clone, Copy)]
+pub struct Timestamp {
+  pub value: i64,
+  pub time_base: Rational,
+}
+
+impl Timestamp {
+  pub fn new(value: i64, time_base: Rational) -> Self {
+    Timestamp { value, time_base }
+  }
+
+  pub fn from_millis(value: i64) -> Self {
+    Timestamp::new(value, Rational::new(1, 1000))
+  }
+
+  /// Express the timestamp in another time base, rounding to the nearest unit.
+  pub fn rescale(&self, time_base: Rational) -> Self {
+    let value = rescale(self.value, self.time_base, time_base, |num, den| {
+      let quotient = num / den;
+      let remainder = num % den;
+      if 2 * remainder.abs() >= den.abs() {
+        quotient + num.signum() * den.signum()
+      } else {
+        quotient
+      }
+    });
+    Timestamp::new(value, time_base)
+  }
+
+  pub fn to_seconds(&self) -> f64 {
+    self.value as f64 * self.time_base.to_f64()
+  }
+
+  /// Milliseconds, truncated toward zero.
+  pub fn to_millis(&self) -> i64 {
+    rescale(
+      self.value,
+      self.time_base,
+      Rational::new(1, 1000),
+      |num, den| num / den,
+    )
+  }
+
+  /// `None` for negative timestamps.
+  pub fn to_duration(&self) -> Option<Duration> {
+    let nanos = rescale(
+      self.value,
+      self.time_base,
+      Rational::new(1, 1_000_000_000),
+      |num, den| num / den,
+    );
+    if nanos < 0 {
+      None
+    } else {
+      Some(Duration::from_nanos(nanos as u64))
+    }
+  }
+}
+
+/// value * from / to, computed on 128 bits, with the given integer division.
+fn rescale(value: i64, from: Rational, to: Rational, divide: fn(i128, i128) -> i128) -> i64 {
+  let num = i128::from(value) * i128::from(from.num) * i128::from(to.den);
+  let den = i128::from(from.den) * i128::from(to.num);
+  if den == 0 {
+    return 0;
+  }
+  divide(num, den).clamp(i128::from(i64::MIN), i128::from(i64::MAX)) as i64
+}
+
+impl PartialEq for Timestamp {
+  fn eq(&self, other: &Self) -> bool {
+    self.partial_cmp(other) == Some(Ordering::Equal)
+  }
+}
+
+impl PartialOrd for Timestamp {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    if self.time_base.den == 0 || other.time_base.den == 0 {
+      return None;
+    }
+    let left = i128::from(self.value) * i128::from(self.time_base.num);
+    let right = i128::from(other.value) * i128::from(other.time_base.num);
+    let (left_den, right_den) = (
+      i128::from(self.time_base.den),
+      i128::from(other.time_base.den),
+    );
+    // cross multiply with positive denominators to keep the ordering
+    let sign = left_den.signum() * right_den.signum();
+    (left * right_den * sign).partial_cmp(&(right * left_den * sign))
+  }
+}
+
+impl fmt::Display for Timestamp {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{} ({})", self.value, self.time_base)
+  }
+}
+
+#[test]
+fn timestamp() {
+  let timestamp = Timestamp::new(1001, Rational::new(1, 30000));
+  assert_eq!(timestamp.to_millis(), 33);
+  assert_eq!(timestamp.rescale(Rational::new(1, 90000)).value, 3003);
+  assert_eq!(
+    timestamp.to_duration(),
+    Some(Duration::from_nanos(33_366_666))
+  );
+  assert_eq!(
+    Timestamp::new(25, Rational::new(1, 25)),
+    Timestamp::from_millis(1000)
+  );
+  assert!(Timestamp::new(24, Rational::new(1, 25)) < Timestamp::from_millis(1000));
+  assert_eq!(
+    Timestamp::new(-3, Rational::new(1, 2))
+      .rescale(Rational::from(1))
+      .value,
+    -2
+  );
+  assert_eq!(Timestamp::new(-1, Rational::new(1, 1)).to_duration(), None);
+}
@@ -1,6 +1,13 @@
-use crate::{format_context::FormatContext, frame::Frame, packet::Packet, tools};
+use crate::{
+  error::Error,
+  format_context::{FormatContext, SeekMode, StreamDecoder},
+  frame::Frame,
+  packet::Packet,
+  tools::{self, rational::Rational},
+};
 use ffmpeg_sys_next::*;
 use std::{
+  cell::RefCell,
   ffi::CString,
   ptr::{null, null_mut},
 };
@@ -11,6 +18,8 @@ pub struct VideoDecoder {
   pub stream_index: isize,
   pub codec_context: *mut AVCodecContext,
   pub hw_pixel_format: Option<AVPixelFormat>,
+  /// Frames decoded past a seek target, returned first by the next decode.
+  pending_frames: RefCell<Vec<Frame>>,
 }
 
 impl VideoDecoder {
@@ -19,7 +28,7 @@ impl VideoDecoder {
     format: &FormatContext,
     stream_index: isize,
     hw_accel: bool,
-  ) -> Result<Self, String> {
+  ) -> Result<Self, Error> {
     unsafe {
       let codec = avcodec_find_decoder(format.get_codec_id(stream_index));
       let mut codec_context = avcodec_alloc_context3(codec);
@@ -33,6 +42,8 @@ impl VideoDecoder {
           avcodec_free_context(&mut codec_context);
         }
       );
+      (*codec_context).pkt_timebase =
+        (**(*format.format_context).streams.offset(stream_index)).time_base;
 
       let hw_pixel_format = if hw_accel {
         let hw_configs = get_hw_configs(codec_context);
@@ -65,6 +76,7 @@ impl VideoDecoder {
         stream_index,
         codec_context,
         hw_pixel_format,
+        pending_frames: RefCell::new(vec![]),
       })
     }
   }
@@ -75,7 +87,7 @@ impl VideoDecoder {
     width: i32,
     height: i32,
     stream_index: isize,
-  ) -> Result<Self, String> {
+  ) -> Result<Self, Error> {
     unsafe {
       let cn = CString::new(codec_name).unwrap();
       let codec = avcodec_find_decoder_by_name(cn.as_ptr());
@@ -92,6 +104,7 @@ impl VideoDecoder {
         stream_index,
         codec_context,
         hw_pixel_format: Some(AVPixelFormat::AV_PIX_FMT_NONE),
+        pending_frames: RefCell::new(vec![]),
       })
     }
   }
@@ -138,24 +151,109 @@ impl VideoDecoder {
     }
   }
 
-  pub fn decode(&self, packet: &Packet) -> Result<Frame, String> {
+  /// Time base of the packets, and of the timestamps of the decoded frames.
+  pub fn get_packet_time_base(&self) -> Option<Rational> {
+    unsafe {
+      let time_base = (*self.codec_context).pkt_timebase;
+      if time_base.num > 0 && time_base.den > 0 {
+        Some(time_base.into())
+      } else {
+        None
+      }
+    }
+  }
+
+  /// Decode a packet and return every frame the decoder outputs.
+  /// An empty list means the decoder needs more input.
+  pub fn decode(&self, packet: &Packet) -> Result<Vec<Frame>, Error> {
     if packet.get_stream_index() != self.stream_index {
-      return Err("bad stream".to_string());
+      return Err(Error::Configuration("bad stream".to_string()));
     }
-    unsafe {
+    let frames = unsafe {
       check_result!(avcodec_send_packet(self.codec_context, packet.packet));
+      Frame::receive_all(
+        self.codec_context,
+        &self.identifier,
+        self.stream_index as usize,
+      )?
+    };
+    let mut pending_frames = self.pending_frames.take();
+    pending_frames.extend(frames);
+    Ok(pending_frames)
+  }
 
-      let frame = av_frame_alloc();
-
-      check_result!(avcodec_receive_frame(self.codec_context, frame));
+  /// Signal the end of the stream, and return the frames still buffered by the decoder.
+  pub fn drain(&self) -> Result<Vec<Frame>, Error> {
+    let frames = unsafe {
+      check_result!(avcodec_send_packet(self.codec_context, null()));
+      Frame::receive_all(
+        self.codec_context,
+        &self.identifier,
+        self.stream_index as usize,
+      )?
+    };
+    let mut pending_frames = self.pending_frames.take();
+    pending_frames.extend(frames);
+    Ok(pending_frames)
+  }
 
-      Ok(Frame {
-        frame,
-        name: Some(self.identifier.clone()),
-        index: self.stream_index as usize,
-      })
+  /// Drop the frames buffered by the decoder, required after each seek.
+  pub fn flush(&self) {
+    self.pending_frames.borrow_mut().clear();
+    unsafe {
+      avcodec_flush_buffers(self.codec_context);
     }
   }
+
+  /// Seek to a timestamp in the stream time base and return the first decoded frame.
+  pub fn seek(
+    &self,
+    format: &mut FormatContext,
+    timestamp: i64,
+    mode: SeekMode,
+  ) -> Result<Frame, Error> {
+    format.seek_decoder(self, timestamp, mode)
+  }
+
+  pub fn seek_to_seconds(
+    &self,
+    format: &mut FormatContext,
+    seconds: f64,
+    mode: SeekMode,
+  ) -> Result<Frame, Error> {
+    format.seek_decoder_to_seconds(self, seconds, mode)
+  }
+
+  pub fn seek_to_frame(
+    &self,
+    format: &mut FormatContext,
+    frame_number: i64,
+    mode: SeekMode,
+  ) -> Result<Frame, Error> {
+    format.seek_decoder_to_frame(self, frame_number, mode)
+  }
+}
+
+impl StreamDecoder for VideoDecoder {
+  fn get_stream_index(&self) -> isize {
+    self.stream_index
+  }
+
+  fn decode(&self, packet: &Packet) -> Result<Vec<Frame>, Error> {
+    VideoDecoder::decode(self, packet)
+  }
+
+  fn drain(&self) -> Result<Vec<Frame>, Error> {
+    VideoDecoder::drain(self)
+  }
+
+  fn flush(&self) {
+    VideoDecoder::flush(self)
+  }
+
+  fn set_pending_frames(&self, frames: Vec<Frame>) {
+    *self.pending_frames.borrow_mut() = frames;
+  }
 }
 
 impl Drop for VideoDecoder {
@@ -195,3 +293,40 @@ unsafe fn get_hw_configs(
   }
   hw_configs
 }
+
+#[test]
+fn video_decoder_seek() {
+  let mut format = FormatContext::new("tests/PAL_1080i_MPEG_XDCAM-HD_colorbar.mxf").unwrap();
+  format.open_input().unwrap();
+  let decoder = VideoDecoder::new("video".to_string(), &format, 0, false).unwrap();
+
+  let target = format.get_frame_timestamp(0, 5).unwrap();
+  let frame = decoder
+    .seek_to_frame(&mut format, 5, SeekMode::Accurate)
+    .unwrap();
+  assert_eq!(frame.get_best_effort_timestamp(), Some(target));
+
+  // the frames decoded past the target are returned by the next decode
+  let next = format.get_frame_timestamp(0, 6).unwrap();
+  let frames = loop {
+    let packet = format.next_packet().unwrap();
+    if packet.get_stream_index() == 0 {
+      let frames = decoder.decode(&packet).unwrap();
+      if !frames.is_empty() {
+        break frames;
+      }
+    }
+  };
+  assert_eq!(frames[0].get_best_effort_timestamp(), Some(next));
+
+  // the last frames are only output when draining the decoder
+  let nb_frames = (format.get_duration().unwrap() * 25.0).round() as i64;
+  let target = format.get_frame_timestamp(0, nb_frames - 1).unwrap();
+  let frame = decoder
+    .seek_to_frame(&mut format, nb_frames - 1, SeekMode::Accurate)
+    .unwrap();
+  assert_eq!(frame.get_best_effort_timestamp(), Some(target));
+
+  let frame = decoder.seek(&mut format, 0, SeekMode::Keyframe).unwrap();
+  assert_eq!(frame.get_best_effort_timestamp(), Some(0));
+}
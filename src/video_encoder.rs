@@ -1,49 +1,91 @@
 use crate::{
+  error::Error,
   frame::Frame,
   order::{
-    output::{ColorRange, Colorspace, OutputStream, PixelFormat},
-    parameters::ParameterValue,
+    output::{ColorRange, Colorspace, OutputStream, PixelFormat, VideoSync},
+    parameters::{self, ParameterValue},
   },
   packet::Packet,
-  tools,
+  tools::{self, rational::Rational, timestamp::Timestamp},
 };
 use ffmpeg_sys_next::*;
 use std::ptr::null_mut;
 
+/// Parameters applied to the codec context, the others are passed as codec options.
+const VIDEO_PARAMETERS: &[&str] = &[
+  "vsync",
+  "frame_rate",
+  "sample_aspect_ratio",
+  "pixel_format",
+  "width",
+  "height",
+  "bitrate",
+  "gop_size",
+  "max_b_frames",
+  "refs",
+  "keyint_min",
+  "colorspace",
+  "color_range",
+];
+
 #[derive(Debug)]
 pub struct VideoEncoder {
   pub identifier: String,
   pub stream_index: isize,
   pub codec_context: *mut AVCodecContext,
   pub codec: *const AVCodec,
-  pub pts: i64,
+  pub vsync: VideoSync,
+  /// Timestamp of the next frame, in the encoder time base.
+  next_pts: Option<i64>,
 }
 
 impl VideoEncoder {
+  /// The timestamps policy is set by the `vsync` parameter ("passthrough", "cfr" or "vfr"),
+  /// "vfr" by default.
   pub fn new(
     identifier: String,
     stream_index: isize,
     output_stream: &OutputStream,
-  ) -> Result<Self, String> {
+  ) -> Result<Self, Error> {
+    let parameters = &output_stream.parameters;
+    let vsync = match parameters.get("vsync") {
+      Some(ParameterValue::String(data)) => data.parse()?,
+      _ => VideoSync::Vfr,
+    };
+    let pixel_format = match parameters.get("pixel_format") {
+      Some(ParameterValue::String(data)) => Some(data.parse::<PixelFormat>()?),
+      _ => None,
+    };
+    let colorspace = match parameters.get("colorspace") {
+      Some(ParameterValue::String(data)) => Some(data.parse::<Colorspace>()?),
+      _ => None,
+    };
+    let color_range = match parameters.get("color_range") {
+      Some(ParameterValue::String(data)) => Some(data.parse::<ColorRange>()?),
+      _ => None,
+    };
+
     unsafe {
       let codec = tools::get_codec(&output_stream.codec);
       if codec.is_null() {
-        return Err(format!("Unable to found codec {}", output_stream.codec));
+        return Err(Error::CodecNotFound {
+          code: AVERROR_ENCODER_NOT_FOUND,
+          message: format!("Unable to found codec {}", output_stream.codec),
+        });
       }
       let mut codec_context = avcodec_alloc_context3(codec);
 
-      let parameters = &output_stream.parameters;
       if let Some(ParameterValue::Rational(data)) = parameters.get("frame_rate") {
-        (*codec_context).time_base = data.clone().invert().into();
+        (*codec_context).time_base = data.invert().into();
+        (*codec_context).framerate = (*data).into();
       }
 
       if let Some(ParameterValue::Rational(data)) = parameters.get("sample_aspect_ratio") {
-        (*codec_context).sample_aspect_ratio = data.clone().into();
+        (*codec_context).sample_aspect_ratio = (*data).into();
       }
 
-      if let Some(ParameterValue::String(data)) = parameters.get("pixel_format") {
-        let format: PixelFormat = data.parse().unwrap();
-        (*codec_context).pix_fmt = format.into();
+      if let Some(pixel_format) = pixel_format {
+        (*codec_context).pix_fmt = pixel_format.into();
       }
 
       if let Some(ParameterValue::Int64(data)) = parameters.get("width") {
@@ -74,26 +116,28 @@ impl VideoEncoder {
         (*codec_context).keyint_min = *data as i32;
       }
 
-      if let Some(ParameterValue::String(data)) = parameters.get("colorspace") {
-        let colorspace: Colorspace = data.parse().unwrap();
+      if let Some(colorspace) = colorspace {
         (*codec_context).colorspace = colorspace.into();
       }
 
-      if let Some(ParameterValue::String(data)) = parameters.get("color_range") {
-        let color_range: ColorRange = data.parse().unwrap();
+      if let Some(color_range) = color_range {
         (*codec_context).color_range = color_range.into();
       }
 
-      check_result!(avcodec_open2(codec_context, codec, null_mut()), {
+      if let Err(error) =
+        parameters::open_encoder(codec_context, codec, parameters, VIDEO_PARAMETERS)
+      {
         avcodec_free_context(&mut codec_context);
-      });
+        return Err(error);
+      }
 
       Ok(VideoEncoder {
         identifier,
         stream_index,
         codec_context,
         codec,
-        pts: 0,
+        vsync,
+        next_pts: None,
       })
     }
   }
@@ -124,28 +168,134 @@ impl VideoEncoder {
     }
   }
 
-  pub fn encode(&mut self, frame: &Frame, packet: &Packet) -> Result<bool, String> {
+  /// Encode a frame, and return the packets produced by the encoder.
+  ///
+  /// The frame timestamp is rescaled from the frame time base (the one of the buffersink)
+  /// into the encoder time base, then the frame is dropped or duplicated following `vsync`.
+  pub fn encode(&mut self, frame: &Frame) -> Result<Vec<Packet>, Error> {
+    let time_base: Rational = unsafe { (*self.codec_context).time_base.into() };
+    let pts = match frame.get_pts() {
+      AV_NOPTS_VALUE => None,
+      pts => Some(rescale_pts(
+        pts,
+        frame.get_time_base().unwrap_or(time_base),
+        time_base,
+      )),
+    };
+
+    let (mut pts, count) = sync_frame(self.vsync, pts, self.next_pts);
+    if count == 0 {
+      return Ok(vec![]);
+    }
+    let mut packets = vec![];
     unsafe {
-      (*frame.frame).pts = self.pts;
-      self.pts += 1;
+      // a new reference to the same buffers, to leave the timestamps of the input untouched
+      let mut encoded_frame = av_frame_clone(frame.frame);
+      if encoded_frame.is_null() {
+        return Err(Error::from_av_error(AVERROR(ENOMEM)));
+      }
+      (*encoded_frame).time_base = time_base.into();
+      for _ in 0..count {
+        (*encoded_frame).pts = pts;
+        match self.send_frame(encoded_frame) {
+          Ok(encoded) => packets.extend(encoded),
+          Err(error) => {
+            av_frame_free(&mut encoded_frame);
+            return Err(error);
+          }
+        }
+        pts += 1;
+      }
+      av_frame_free(&mut encoded_frame);
+    }
+    self.next_pts = Some(pts);
+    Ok(packets)
+  }
 
-      check_result!(avcodec_send_frame(self.codec_context, frame.frame));
-      let ret = avcodec_receive_packet(self.codec_context, packet.packet as *mut _);
+  /// Signal the end of the stream, and return the packets delayed by the encoder
+  /// (B-frames, lookahead...).
+  pub fn flush(&mut self) -> Result<Vec<Packet>, Error> {
+    self.send_frame(null_mut())
+  }
+
+  /// Send a frame, or null to enter draining mode, and receive all the available packets.
+  fn send_frame(&self, frame: *mut AVFrame) -> Result<Vec<Packet>, Error> {
+    let mut packets = vec![];
+    unsafe {
+      check_result!(avcodec_send_frame(self.codec_context, frame));
+      loop {
+        let mut packet = Packet::new()?;
+        let ret = avcodec_receive_packet(self.codec_context, packet.packet);
+
+        if ret == AVERROR(EAGAIN) || ret == AVERROR_EOF {
+          let mut data = [0; AV_ERROR_MAX_STRING_SIZE];
+          av_strerror(ret, data.as_mut_ptr(), AV_ERROR_MAX_STRING_SIZE);
+          trace!("{}", tools::to_string(data.as_ptr()));
+          break;
+        }
 
-      if ret == AVERROR(EAGAIN) || ret == AVERROR_EOF {
-        let mut data = [0; AV_ERROR_MAX_STRING_SIZE];
-        av_strerror(ret, data.as_mut_ptr(), AV_ERROR_MAX_STRING_SIZE);
-        trace!("{}", tools::to_string(data.as_ptr()));
-        return Ok(false);
+        check_result!(ret);
+
+        let frame_rate = (*self.codec_context).framerate;
+        if packet.get_duration() == 0 && frame_rate.num > 0 && frame_rate.den > 0 {
+          packet.set_duration(av_rescale_q(
+            1,
+            av_inv_q(frame_rate),
+            (*self.codec_context).time_base,
+          ));
+        }
+
+        trace!("received encoded packet with {} bytes", packet.get_size());
+        packets.push(packet);
       }
+    }
+    Ok(packets)
+  }
+}
+
+/// Timestamp in another time base: the nearest tick, and the offset from that tick
+/// (between -0.5 and 0.5) in units of the new time base.
+fn rescale_pts(pts: i64, from: Rational, to: Rational) -> (i64, f64) {
+  let tick = Timestamp::new(pts, from).rescale(to).value;
+  let den = i128::from(from.den) * i128::from(to.num);
+  if den == 0 {
+    return (tick, 0.0);
+  }
+  let num = i128::from(pts) * i128::from(from.num) * i128::from(to.den) - i128::from(tick) * den;
+  (tick, num as f64 / den as f64)
+}
 
-      check_result!(ret);
+/// Timestamp of the first copy of a frame, and the number of copies to encode,
+/// with `pts` (tick and offset, see `rescale_pts`) and `next_pts` in the encoder time base.
+fn sync_frame(vsync: VideoSync, pts: Option<(i64, f64)>, next_pts: Option<i64>) -> (i64, usize) {
+  let next_pts = match (next_pts, pts) {
+    (Some(next_pts), _) => next_pts,
+    (None, Some((tick, _))) => tick,
+    (None, None) => 0,
+  };
+  let (tick, offset) = match pts {
+    Some(pts) => pts,
+    None => return (next_pts, 1),
+  };
 
-      trace!(
-        "received encoded packet with {} bytes",
-        (*packet.packet).size
-      );
-      Ok(true)
+  let delta = (tick - next_pts) as f64 + offset;
+  match vsync {
+    // timestamps must increase, the frames falling on the tick of the previous one are dropped
+    VideoSync::Passthrough if tick < next_pts => (next_pts, 0),
+    VideoSync::Passthrough => (tick, 1),
+    VideoSync::Vfr if delta <= -0.6 => (next_pts, 0),
+    VideoSync::Vfr if delta > 0.6 => (tick, 1),
+    VideoSync::Vfr => (next_pts, 1),
+    VideoSync::Cfr => {
+      // the frame covers [pts, pts + 1[
+      let delta = delta + 1.0;
+      if delta < -1.1 {
+        (next_pts, 0)
+      } else if delta > 1.1 {
+        (next_pts, delta.round() as usize)
+      } else {
+        (next_pts, 1)
+      }
     }
   }
 }
@@ -160,3 +310,100 @@ impl Drop for VideoEncoder {
     }
   }
 }
+
+#[test]
+fn video_encoder_sync() {
+  // 25 fps source, half of the frames missing
+  assert_eq!(sync_frame(VideoSync::Cfr, Some((10, 0.0)), None), (10, 1));
+  assert_eq!(
+    sync_frame(VideoSync::Cfr, Some((12, 0.0)), Some(11)),
+    (11, 2)
+  );
+  assert_eq!(
+    sync_frame(VideoSync::Vfr, Some((12, 0.0)), Some(11)),
+    (12, 1)
+  );
+  assert_eq!(
+    sync_frame(VideoSync::Passthrough, Some((12, 0.0)), Some(11)),
+    (12, 1)
+  );
+
+  // 50 fps source into a 25 fps encoder, without frame rate conversion
+  assert_eq!(
+    sync_frame(VideoSync::Passthrough, Some((12, 0.4)), Some(13)),
+    (13, 0)
+  );
+  assert_eq!(
+    sync_frame(VideoSync::Passthrough, Some((13, -0.4)), Some(13)),
+    (13, 1)
+  );
+
+  // 50 fps source into a 25 fps encoder
+  let half_tick = |pts| rescale_pts(pts, Rational::new(1, 50), Rational::new(1, 25));
+  assert_eq!(half_tick(25), (13, -0.5));
+  assert_eq!(half_tick(24), (12, 0.0));
+  assert_eq!(
+    sync_frame(VideoSync::Cfr, Some(half_tick(25)), Some(13)),
+    (13, 1)
+  );
+  assert_eq!(
+    sync_frame(VideoSync::Cfr, Some(half_tick(21)), Some(13)),
+    (13, 0)
+  );
+  assert_eq!(
+    sync_frame(VideoSync::Vfr, Some(half_tick(25)), Some(13)),
+    (13, 1)
+  );
+  assert_eq!(
+    sync_frame(VideoSync::Vfr, Some(half_tick(24)), Some(13)),
+    (13, 0)
+  );
+
+  assert_eq!(sync_frame(VideoSync::Vfr, None, Some(4)), (4, 1));
+
+  // 90 kHz timestamps past 2^33, rounded half away from zero
+  assert_eq!(
+    rescale_pts(
+      8_589_938_400 + 1800,
+      Rational::new(1, 90000),
+      Rational::new(1, 25)
+    ),
+    (2_386_095, -0.5)
+  );
+}
+
+#[test]
+fn video_encoder_options() {
+  use std::collections::HashMap;
+
+  let mut parameters = HashMap::new();
+  parameters.insert("width".to_string(), ParameterValue::Int64(320));
+  parameters.insert("height".to_string(), ParameterValue::Int64(240));
+  parameters.insert(
+    "frame_rate".to_string(),
+    ParameterValue::Rational(Rational::new(25, 1)),
+  );
+  parameters.insert(
+    "pixel_format".to_string(),
+    ParameterValue::String("yuv420p".to_string()),
+  );
+  parameters.insert("g".to_string(), ParameterValue::Int64(12));
+  parameters.insert("unknown_option".to_string(), ParameterValue::Int64(1));
+  let mut output_stream = OutputStream {
+    label: Some("video".to_string()),
+    codec: "mpeg2video".to_string(),
+    parameters,
+    disposition: Default::default(),
+    language: None,
+  };
+
+  let error = VideoEncoder::new("video".to_string(), 0, &output_stream).unwrap_err();
+  assert_eq!(
+    error,
+    Error::Configuration("Unknown options for codec mpeg2video: unknown_option".to_string())
+  );
+
+  output_stream.parameters.remove("unknown_option");
+  let encoder = VideoEncoder::new("video".to_string(), 0, &output_stream).unwrap();
+  assert_eq!(unsafe { (*encoder.codec_context).gop_size }, 12);
+}